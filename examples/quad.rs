@@ -0,0 +1,70 @@
+use cgmath::{InnerSpace, Rotation3};
+use wgpu_render_practice2::renderer_backend::{
+    app::{self, App, RunnerSettings},
+    frame_loop::FrameTime,
+    instance::Instance,
+    material::Material,
+    mesh_builder,
+    state::State,
+};
+
+const MESH_SIZE: f32 = 0.1;
+const NUM_INSTANCES: u16 = 8;
+// Degrees per second the instances spin around their z axis
+const SPIN_SPEED: f32 = 45.0;
+
+// A row of textured quads, each one rotated 30 degrees further than the previous
+#[derive(Default)]
+struct QuadDemo {
+    // Spin angle, stepped by the fixed update and interpolated for rendering
+    previous_angle: f32,
+    angle: f32,
+    // Interpolated angle the instances were last rotated to
+    rendered_angle: f32,
+}
+
+impl App for QuadDemo {
+    fn init(&mut self, state: &mut State) {
+        let mesh = mesh_builder::create_mesh(&state.device, &MESH_SIZE);
+        state.add_mesh(mesh);
+        let material = Material::new(&state.device, &state.queue, "textures/texture_diamond.jpg");
+        state.add_material(material);
+
+        let init_position = -MESH_SIZE * (NUM_INSTANCES - 1) as f32;
+        state.instances = (0..NUM_INSTANCES)
+            .map(|i| Instance {
+                position: cgmath::Vector3 {
+                    x: init_position + 2.0 * MESH_SIZE * i as f32,
+                    y: 0.0,
+                    z: 0.0,
+                },
+                rotation: cgmath::Quaternion::from_angle_z(cgmath::Deg(30.0 * i as f32)),
+                scale: cgmath::Vector3 {
+                    x: 1.0,
+                    y: 1.0,
+                    z: 1.0,
+                },
+            })
+            .collect();
+    }
+
+    fn update(&mut self, _state: &mut State, dt: f32) {
+        self.previous_angle = self.angle;
+        self.angle += SPIN_SPEED * dt;
+    }
+
+    fn render(&mut self, state: &mut State, frame_time: &FrameTime) {
+        let angle = self.previous_angle + (self.angle - self.previous_angle) * frame_time.alpha;
+        // Spin by the change since the last frame, so rotations edited in the
+        // debug panel are kept and spin on from there
+        let spin = cgmath::Quaternion::from_angle_z(cgmath::Deg(angle - self.rendered_angle));
+        self.rendered_angle = angle;
+        for instance in &mut state.instances {
+            instance.rotation = (spin * instance.rotation).normalize();
+        }
+    }
+}
+
+fn main() {
+    app::run(RunnerSettings::default(), QuadDemo::default());
+}
@@ -0,0 +1,7 @@
+//! Forward and deferred wgpu renderer. Build a demo by implementing
+//! `renderer_backend::app::App` and passing it to `renderer_backend::app::run`.
+
+pub mod renderer_backend;
+
+#[cfg(not(any(feature = "glfw", feature = "winit")))]
+compile_error!("Enable a windowing backend, the `glfw` or `winit` feature");
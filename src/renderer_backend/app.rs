@@ -0,0 +1,162 @@
+use super::{
+    capture::CaptureFormat,
+    frame_loop::{FrameLoop, FrameLoopSettings, FrameTime},
+    state::State,
+    ui,
+    window::{Key, Modifiers, WindowEvent},
+};
+
+const RECORDING_FPS: f32 = 60.0;
+
+/// A demo built on the renderer. The runner owns the window and `State` and
+/// calls the hooks from its frame loop.
+pub trait App {
+    // Called once the renderer exists, adds meshes, materials, instances and lights
+    fn init(&mut self, state: &mut State);
+
+    // Fixed timestep update, `dt` is `FrameLoopSettings::fixed_timestep`
+    fn update(&mut self, _state: &mut State, _dt: f32) {}
+
+    // Window events, after the runner has handled resizing and capture keys. Events
+    // the debug UI consumes are not passed on.
+    fn handle_event(&mut self, _state: &mut State, _event: &WindowEvent) {}
+
+    // Right before the frame is rendered, `frame_time.alpha` interpolates between updates.
+    // egui windows can be added through `state.ui.context`.
+    fn render(&mut self, _state: &mut State, _frame_time: &FrameTime) {}
+}
+
+pub struct RunnerSettings {
+    pub title: String,
+    // Window size in logical pixels, the surface gets the framebuffer size
+    pub width: u32,
+    pub height: u32,
+    pub present_mode: wgpu::PresentMode,
+    pub frame_loop: FrameLoopSettings,
+}
+
+impl Default for RunnerSettings {
+    fn default() -> Self {
+        Self {
+            title: "WGPU Project".to_string(),
+            width: 1000,
+            height: 1000,
+            present_mode: wgpu::PresentMode::Fifo,
+            frame_loop: FrameLoopSettings::default(),
+        }
+    }
+}
+
+/// Opens the window and runs `app` until it is closed. Uses glfw when the `glfw`
+/// feature is enabled, winit otherwise.
+pub fn run(settings: RunnerSettings, app: impl App) {
+    #[cfg(feature = "glfw")]
+    super::glfw_window::run(settings, app);
+    #[cfg(all(feature = "winit", not(feature = "glfw")))]
+    super::winit_window::run(settings, app);
+}
+
+/// Frame loop shared by the window backends: they create `State`, translate
+/// their events and call `handle_event` and `frame`.
+pub struct Runner<A: App> {
+    pub app: A,
+    pub state: State,
+    pub frame_loop: FrameLoop,
+    // Toggled with F1
+    pub show_debug_panel: bool,
+}
+
+impl<A: App> Runner<A> {
+    pub fn new(settings: &RunnerSettings, mut app: A, mut state: State) -> Self {
+        state.set_present_mode(settings.present_mode);
+        app.init(&mut state);
+        Self {
+            app,
+            state,
+            frame_loop: FrameLoop::new(settings.frame_loop),
+            show_debug_panel: false,
+        }
+    }
+
+    pub fn handle_event(&mut self, event: &WindowEvent) {
+        self.state.ui.handle_event(event, self.state.scale_factor);
+        match *event {
+            WindowEvent::Resized { width, height } => {
+                self.state.resize(width, height);
+            }
+            WindowEvent::ScaleFactorChanged(scale_factor) => {
+                self.state.set_scale_factor(scale_factor);
+            }
+            // Toggles and captures fire once per press, not while the key is held
+            WindowEvent::Key {
+                key,
+                pressed: true,
+                repeat: false,
+                modifiers,
+            } => {
+                if key == Key::F(1) {
+                    self.show_debug_panel = !self.show_debug_panel;
+                }
+                handle_capture_key(&mut self.state, key, modifiers);
+            }
+            _ => {}
+        }
+        if !self.state.ui.consumes(event) {
+            self.app.handle_event(&mut self.state, event);
+        }
+    }
+
+    pub fn frame(&mut self) {
+        let state = &mut self.state;
+        let app = &mut self.app;
+        let frame_time = self
+            .frame_loop
+            .tick(state.recording_timestep(), |dt| app.update(state, dt));
+        let context = self.state.ui.begin_frame(
+            (self.state.config.width, self.state.config.height),
+            self.state.scale_factor,
+        );
+        if self.show_debug_panel {
+            ui::debug_panel(&context, &mut self.state, &frame_time);
+        }
+        self.app.render(&mut self.state, &frame_time);
+        self.state.render();
+        self.frame_loop.limit_frame_rate();
+    }
+
+    // Writes out captures still in flight
+    pub fn finish(&mut self) {
+        self.state.stop_recording();
+    }
+}
+
+// F12 saves a PNG screenshot, Shift+F12 the HDR frame as EXR, F10 toggles recording
+fn handle_capture_key(state: &mut State, key: Key, modifiers: Modifiers) {
+    match key {
+        Key::F(12) => {
+            let timestamp = std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .unwrap()
+                .as_millis();
+            let extension = if modifiers.shift { "exr" } else { "png" };
+            let path = format!("captures/capture_{}.{}", timestamp, extension);
+            if let Err(error) = std::fs::create_dir_all("captures") {
+                log::error!("Failed to create the captures directory: {}", error);
+                return;
+            }
+            if let Err(error) = state.capture_frame(&path) {
+                log::error!("Failed to capture {}: {}", path, error);
+            }
+        }
+        Key::F(10) => {
+            if state.recording_timestep().is_some() {
+                state.stop_recording();
+            } else if let Err(error) =
+                state.start_recording("recording", CaptureFormat::Png, RECORDING_FPS)
+            {
+                log::error!("Failed to start recording: {}", error);
+            }
+        }
+        _ => {}
+    }
+}
@@ -0,0 +1,148 @@
+use cgmath::SquareMatrix;
+use wgpu::util::DeviceExt;
+
+// cgmath produces OpenGL clip space (z in -1..1), wgpu expects z in 0..1
+#[rustfmt::skip]
+pub const OPENGL_TO_WGPU_MATRIX: cgmath::Matrix4<f32> = cgmath::Matrix4::new(
+    1.0, 0.0, 0.0, 0.0,
+    0.0, 1.0, 0.0, 0.0,
+    0.0, 0.0, 0.5, 0.0,
+    0.0, 0.0, 0.5, 1.0,
+);
+
+#[derive(Copy, Clone, Debug)]
+pub enum Projection {
+    Perspective { fovy: cgmath::Deg<f32> },
+    // Half extents of the view volume, scaled horizontally by the aspect ratio
+    Orthographic { half_height: f32 },
+}
+
+#[derive(Clone, Debug)]
+pub struct Camera {
+    pub eye: cgmath::Point3<f32>,
+    pub target: cgmath::Point3<f32>,
+    pub up: cgmath::Vector3<f32>,
+    pub aspect: f32,
+    pub znear: f32,
+    pub zfar: f32,
+    pub projection: Projection,
+}
+
+impl Camera {
+    /// Orthographic camera in pixel units over a `width` x `height` target: the
+    /// origin is the top-left corner and y points down. Content between z -1 and 1
+    /// is visible, lower z is nearer.
+    pub fn pixel_space(width: f32, height: f32) -> Self {
+        let center = cgmath::Point3::new(width * 0.5, height * 0.5, 0.0);
+        Self {
+            eye: center + cgmath::Vector3::new(0.0, 0.0, -1.0),
+            target: center,
+            // Looking down +z with -y up keeps x pointing right
+            up: -cgmath::Vector3::unit_y(),
+            aspect: width / height,
+            znear: 0.0,
+            zfar: 2.0,
+            projection: Projection::Orthographic {
+                half_height: height * 0.5,
+            },
+        }
+    }
+
+    pub fn build_view_projection_matrix(&self) -> cgmath::Matrix4<f32> {
+        let view = cgmath::Matrix4::look_at_rh(self.eye, self.target, self.up);
+        let projection = match self.projection {
+            Projection::Perspective { fovy } => {
+                cgmath::perspective(fovy, self.aspect, self.znear, self.zfar)
+            }
+            Projection::Orthographic { half_height } => {
+                let half_width = half_height * self.aspect;
+                cgmath::ortho(
+                    -half_width,
+                    half_width,
+                    -half_height,
+                    half_height,
+                    self.znear,
+                    self.zfar,
+                )
+            }
+        };
+        OPENGL_TO_WGPU_MATRIX * projection * view
+    }
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct CameraUniform {
+    pub view_proj: [[f32; 4]; 4],
+    // w is unused, vec3 uniforms are padded to 16 bytes anyway
+    pub view_position: [f32; 4],
+    // Unprojects screen positions, used by the skybox to rebuild view rays
+    pub inverse_view_proj: [[f32; 4]; 4],
+}
+
+impl CameraUniform {
+    pub fn from_camera(camera: &Camera) -> Self {
+        let view_proj = camera.build_view_projection_matrix();
+        Self {
+            view_proj: view_proj.into(),
+            view_position: camera.eye.to_homogeneous().into(),
+            inverse_view_proj: view_proj
+                .invert()
+                .unwrap_or(cgmath::Matrix4::identity())
+                .into(),
+        }
+    }
+}
+
+pub struct CameraBinding {
+    pub buffer: wgpu::Buffer,
+    pub bind_group: wgpu::BindGroup,
+    pub bind_group_layout: wgpu::BindGroupLayout,
+}
+
+impl CameraBinding {
+    pub fn new(device: &wgpu::Device, camera: &Camera) -> Self {
+        let buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Camera Buffer"),
+            contents: bytemuck::cast_slice(&[CameraUniform::from_camera(camera)]),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: &[wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::VERTEX | wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            }],
+            label: Some("camera_bind_group_layout"),
+        });
+
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &bind_group_layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: buffer.as_entire_binding(),
+            }],
+            label: Some("camera_bind_group"),
+        });
+
+        Self {
+            buffer,
+            bind_group,
+            bind_group_layout,
+        }
+    }
+
+    pub fn update(&self, queue: &wgpu::Queue, camera: &Camera) {
+        queue.write_buffer(
+            &self.buffer,
+            0,
+            bytemuck::cast_slice(&[CameraUniform::from_camera(camera)]),
+        );
+    }
+}
@@ -0,0 +1,371 @@
+use std::{
+    collections::VecDeque,
+    path::{Path, PathBuf},
+    sync::{Arc, OnceLock},
+};
+
+// Readbacks in flight before `FrameCapture::poll` blocks, bounds memory while recording
+const MAX_PENDING_CAPTURES: usize = 8;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum CaptureFormat {
+    // Final surface image after post-processing, 8 bit sRGB
+    Png,
+    // Linear HDR scene color before post-processing
+    Exr,
+}
+
+impl CaptureFormat {
+    pub fn from_path(path: &Path) -> Option<Self> {
+        let extension = path.extension()?.to_str()?.to_ascii_lowercase();
+        match extension.as_str() {
+            "png" => Some(Self::Png),
+            "exr" => Some(Self::Exr),
+            _ => None,
+        }
+    }
+
+    fn extension(&self) -> &'static str {
+        match self {
+            Self::Png => "png",
+            Self::Exr => "exr",
+        }
+    }
+}
+
+/// Writes every rendered frame as `frame_00000.<ext>` and so on into `directory`.
+pub struct Recording {
+    pub directory: PathBuf,
+    pub format: CaptureFormat,
+    // Seconds of animation between two frames
+    pub timestep: f32,
+    pub frame: u32,
+}
+
+// A copy waiting for the GPU, mapped once the frame's submission has completed
+struct PendingCapture {
+    path: PathBuf,
+    format: CaptureFormat,
+    texture_format: wgpu::TextureFormat,
+    buffer: wgpu::Buffer,
+    width: u32,
+    height: u32,
+    padded_bytes_per_row: u32,
+    // Result of `map_async`, unset while the mapping is pending
+    mapped: Arc<OnceLock<Result<(), wgpu::BufferAsyncError>>>,
+}
+
+/// Screenshots and recordings. Frames are copied into readback buffers during
+/// `render`, mapped asynchronously after submission and saved in capture order by
+/// `poll`, so capturing doesn't stall the frame.
+#[derive(Default)]
+pub struct FrameCapture {
+    requests: Vec<(PathBuf, CaptureFormat)>,
+    // Copied this frame, mapped after the submit
+    copied: Vec<PendingCapture>,
+    pending: VecDeque<PendingCapture>,
+    pub recording: Option<Recording>,
+}
+
+impl FrameCapture {
+    /// Captures the next rendered frame, the format follows the extension of `path`.
+    pub fn request(&mut self, path: impl AsRef<Path>) -> anyhow::Result<()> {
+        let path = path.as_ref();
+        let format = CaptureFormat::from_path(path)
+            .ok_or_else(|| anyhow::anyhow!("Unsupported capture format: {}", path.display()))?;
+        self.requests.push((path.to_path_buf(), format));
+        Ok(())
+    }
+
+    pub fn start_recording(
+        &mut self,
+        directory: impl AsRef<Path>,
+        format: CaptureFormat,
+        frames_per_second: f32,
+    ) -> anyhow::Result<()> {
+        if !frames_per_second.is_finite() || frames_per_second <= 0.0 {
+            anyhow::bail!(
+                "Invalid recording rate: {} frames per second",
+                frames_per_second
+            );
+        }
+        std::fs::create_dir_all(directory.as_ref())?;
+        self.recording = Some(Recording {
+            directory: directory.as_ref().to_path_buf(),
+            format,
+            timestep: 1.0 / frames_per_second,
+            frame: 0,
+        });
+        Ok(())
+    }
+
+    // Waits for the frames still in flight so the recording is complete on disk
+    pub fn stop_recording(&mut self, device: &wgpu::Device) {
+        self.recording = None;
+        self.flush(device);
+    }
+
+    /// Fixed time step to advance the animation by while recording, so the frames
+    /// play back at the recording rate however long each one took to render.
+    pub fn timestep(&self) -> Option<f32> {
+        self.recording.as_ref().map(|recording| recording.timestep)
+    }
+
+    /// Takes the captures of `format` wanted this frame, including the recording.
+    pub fn take_requests(&mut self, format: CaptureFormat) -> Vec<PathBuf> {
+        let mut paths = Vec::new();
+        self.requests.retain(|(path, requested)| {
+            if *requested == format {
+                paths.push(path.clone());
+            }
+            *requested != format
+        });
+        if let Some(recording) = &mut self.recording
+            && recording.format == format
+        {
+            let file_name = format!("frame_{:05}.{}", recording.frame, format.extension());
+            paths.push(recording.directory.join(file_name));
+            recording.frame += 1;
+        }
+        paths
+    }
+
+    /// Records a copy of `texture` into a readback buffer for each path.
+    pub fn copy_texture(
+        &mut self,
+        device: &wgpu::Device,
+        command_encoder: &mut wgpu::CommandEncoder,
+        texture: &wgpu::Texture,
+        format: CaptureFormat,
+        paths: Vec<PathBuf>,
+    ) {
+        let texture_format = texture.format();
+        let bytes_per_pixel = texture_format
+            .block_copy_size(None)
+            .expect("Capture needs a color texture");
+        let width = texture.width();
+        let height = texture.height();
+        // Rows of a texture to buffer copy start at multiples of 256 bytes
+        let padded_bytes_per_row =
+            (width * bytes_per_pixel).next_multiple_of(wgpu::COPY_BYTES_PER_ROW_ALIGNMENT);
+
+        for path in paths {
+            let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+                label: Some("Capture Buffer"),
+                size: (padded_bytes_per_row * height) as wgpu::BufferAddress,
+                usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+                mapped_at_creation: false,
+            });
+            command_encoder.copy_texture_to_buffer(
+                texture.as_image_copy(),
+                wgpu::TexelCopyBufferInfo {
+                    buffer: &buffer,
+                    layout: wgpu::TexelCopyBufferLayout {
+                        offset: 0,
+                        bytes_per_row: Some(padded_bytes_per_row),
+                        rows_per_image: Some(height),
+                    },
+                },
+                texture.size(),
+            );
+            self.copied.push(PendingCapture {
+                path,
+                format,
+                texture_format,
+                buffer,
+                width,
+                height,
+                padded_bytes_per_row,
+                mapped: Arc::new(OnceLock::new()),
+            });
+        }
+    }
+
+    /// Starts mapping the buffers copied this frame, call after the queue submit.
+    pub fn after_submit(&mut self) {
+        for capture in self.copied.drain(..) {
+            let mapped = capture.mapped.clone();
+            capture
+                .buffer
+                .slice(..)
+                .map_async(wgpu::MapMode::Read, move |result| {
+                    let _ = mapped.set(result);
+                });
+            self.pending.push_back(capture);
+        }
+    }
+
+    /// Saves the captures whose buffers are mapped, in the order they were taken.
+    /// Captures whose mapping failed are dropped.
+    pub fn poll(&mut self, device: &wgpu::Device) {
+        let poll_type = if self.pending.len() > MAX_PENDING_CAPTURES {
+            wgpu::PollType::Wait
+        } else {
+            wgpu::PollType::Poll
+        };
+        if let Err(error) = device.poll(poll_type) {
+            log::error!("Device poll failed: {}", error);
+        }
+        while let Some(result) = self
+            .pending
+            .front()
+            .and_then(|capture| capture.mapped.get().cloned())
+        {
+            let capture = self.pending.pop_front().unwrap();
+            let saved = result
+                .map_err(anyhow::Error::from)
+                .and_then(|()| capture.save());
+            if let Err(error) = saved {
+                log::error!("Failed to save {}: {}", capture.path.display(), error);
+            }
+        }
+    }
+
+    pub fn flush(&mut self, device: &wgpu::Device) {
+        if self.pending.is_empty() {
+            return;
+        }
+        if let Err(error) = device.poll(wgpu::PollType::Wait) {
+            log::error!("Device poll failed: {}", error);
+        }
+        self.poll(device);
+    }
+}
+
+impl PendingCapture {
+    fn save(&self) -> anyhow::Result<()> {
+        let bytes_per_pixel = self.texture_format.block_copy_size(None).unwrap();
+        let row_bytes = (self.width * bytes_per_pixel) as usize;
+        // Drop the row padding
+        let mut data = Vec::with_capacity(row_bytes * self.height as usize);
+        {
+            let mapped = self.buffer.slice(..).get_mapped_range();
+            for row in mapped.chunks(self.padded_bytes_per_row as usize) {
+                data.extend_from_slice(&row[..row_bytes]);
+            }
+        }
+        self.buffer.unmap();
+
+        match self.format {
+            CaptureFormat::Png => {
+                if matches!(
+                    self.texture_format,
+                    wgpu::TextureFormat::Bgra8Unorm | wgpu::TextureFormat::Bgra8UnormSrgb
+                ) {
+                    for pixel in data.chunks_mut(4) {
+                        pixel.swap(0, 2);
+                    }
+                }
+                let image = image::RgbaImage::from_raw(self.width, self.height, data)
+                    .ok_or_else(|| anyhow::anyhow!("Capture size mismatch"))?;
+                image.save(&self.path)?;
+            }
+            CaptureFormat::Exr => {
+                let pixels = data
+                    .chunks(8)
+                    .flat_map(|pixel| {
+                        (0..3).map(|channel| {
+                            f16_to_f32(u16::from_le_bytes([
+                                pixel[channel * 2],
+                                pixel[channel * 2 + 1],
+                            ]))
+                        })
+                    })
+                    .collect::<Vec<_>>();
+                let image = image::Rgb32FImage::from_raw(self.width, self.height, pixels)
+                    .ok_or_else(|| anyhow::anyhow!("Capture size mismatch"))?;
+                image.save(&self.path)?;
+            }
+        }
+        log::info!("Saved capture {}", self.path.display());
+        Ok(())
+    }
+}
+
+// The HDR target is Rgba16Float
+fn f16_to_f32(bits: u16) -> f32 {
+    let sign = ((bits >> 15) as u32) << 31;
+    let exponent = ((bits >> 10) & 0x1f) as u32;
+    let mantissa = (bits & 0x3ff) as u32;
+    let value = match exponent {
+        0 => {
+            // Subnormal, scale by 2^-24
+            let magnitude = mantissa as f32 / (1 << 24) as f32;
+            return if sign != 0 { -magnitude } else { magnitude };
+        }
+        0x1f => sign | 0x7f80_0000 | (mantissa << 13),
+        _ => sign | ((exponent + 112) << 23) | (mantissa << 13),
+    };
+    f32::from_bits(value)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn recording_rate_must_be_positive() {
+        let directory = std::env::temp_dir().join("capture_rate_test");
+        let mut capture = FrameCapture::default();
+        for frames_per_second in [0.0, -30.0, f32::NAN, f32::INFINITY] {
+            let result = capture.start_recording(&directory, CaptureFormat::Png, frames_per_second);
+            assert!(result.is_err());
+            assert!(capture.recording.is_none());
+        }
+    }
+
+    #[test]
+    fn format_from_extension() {
+        assert_eq!(
+            CaptureFormat::from_path(Path::new("captures/frame.png")),
+            Some(CaptureFormat::Png)
+        );
+        assert_eq!(
+            CaptureFormat::from_path(Path::new("frame.EXR")),
+            Some(CaptureFormat::Exr)
+        );
+        assert_eq!(
+            CaptureFormat::from_path(Path::new("archive.tar.png")),
+            Some(CaptureFormat::Png)
+        );
+    }
+
+    #[test]
+    fn unknown_or_missing_extension_has_no_format() {
+        assert_eq!(CaptureFormat::from_path(Path::new("frame.jpg")), None);
+        assert_eq!(CaptureFormat::from_path(Path::new("frame")), None);
+        assert_eq!(CaptureFormat::from_path(Path::new("png")), None);
+        assert_eq!(CaptureFormat::from_path(Path::new(".png")), None);
+    }
+
+    #[test]
+    fn f16_normal_values() {
+        assert_eq!(f16_to_f32(0x3c00), 1.0);
+        assert_eq!(f16_to_f32(0xc000), -2.0);
+        assert_eq!(f16_to_f32(0x3555), 0.333_251_95);
+        // Largest finite half
+        assert_eq!(f16_to_f32(0x7bff), 65504.0);
+    }
+
+    #[test]
+    fn f16_zeros_keep_their_sign() {
+        assert_eq!(f16_to_f32(0x0000).to_bits(), 0.0f32.to_bits());
+        assert_eq!(f16_to_f32(0x8000).to_bits(), (-0.0f32).to_bits());
+    }
+
+    #[test]
+    fn f16_subnormals() {
+        // Smallest and largest subnormal halves
+        assert_eq!(f16_to_f32(0x0001), 2.0f32.powi(-24));
+        assert_eq!(f16_to_f32(0x03ff), 1023.0 * 2.0f32.powi(-24));
+        assert_eq!(f16_to_f32(0x8001), -(2.0f32.powi(-24)));
+    }
+
+    #[test]
+    fn f16_infinities_and_nan() {
+        assert_eq!(f16_to_f32(0x7c00), f32::INFINITY);
+        assert_eq!(f16_to_f32(0xfc00), f32::NEG_INFINITY);
+        assert!(f16_to_f32(0x7e00).is_nan());
+        assert!(f16_to_f32(0xfc01).is_nan());
+    }
+}
@@ -0,0 +1,442 @@
+use std::mem;
+use std::ops::Range;
+
+use cgmath::InnerSpace;
+use wgpu::util::DrawIndexedIndirectArgs;
+
+use crate::renderer_backend::{
+    instance::{InstanceRaw, InstanceStorage},
+    pipeline_builder,
+};
+
+const WORKGROUP_SIZE: u32 = 64;
+
+/// The six clip planes of a view-projection matrix, as (normal, distance) with normals
+/// pointing inwards.
+#[derive(Copy, Clone, Debug)]
+pub struct Frustum {
+    pub planes: [cgmath::Vector4<f32>; 6],
+}
+
+impl Frustum {
+    // Gribb-Hartmann plane extraction for wgpu's 0..1 depth range
+    pub fn from_matrix(view_proj: cgmath::Matrix4<f32>) -> Self {
+        let row = |i: usize| {
+            cgmath::Vector4::new(
+                view_proj.x[i],
+                view_proj.y[i],
+                view_proj.z[i],
+                view_proj.w[i],
+            )
+        };
+        let planes = [
+            row(3) + row(0), // left
+            row(3) - row(0), // right
+            row(3) + row(1), // bottom
+            row(3) - row(1), // top
+            row(2),          // near
+            row(3) - row(2), // far
+        ]
+        .map(|plane| plane / plane.truncate().magnitude());
+        Self { planes }
+    }
+
+    pub fn intersects_sphere(&self, center: cgmath::Vector3<f32>, radius: f32) -> bool {
+        self.planes
+            .iter()
+            .all(|plane| plane.truncate().dot(center) + plane.w >= -radius)
+    }
+
+    /// World-space test of a local bounding sphere transformed by an instance's model matrix.
+    pub fn intersects_instance(&self, instance: &InstanceRaw, local_radius: f32) -> bool {
+        let model = cgmath::Matrix4::from(instance.model);
+        let center = model.w.truncate();
+        let scale = model
+            .x
+            .truncate()
+            .magnitude()
+            .max(model.y.truncate().magnitude())
+            .max(model.z.truncate().magnitude());
+        self.intersects_sphere(center, local_radius * scale)
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum CullingMode {
+    /// Compute pass writes visible indices and `DrawIndexedIndirect` arguments.
+    Gpu,
+    /// Visibility is computed on the CPU and drawn with a regular `draw_indexed`.
+    Cpu,
+}
+
+impl CullingMode {
+    // Indirect draws with a non-zero first instance need INDIRECT_FIRST_INSTANCE
+    pub fn for_device(device: &wgpu::Device) -> Self {
+        if device
+            .features()
+            .contains(wgpu::Features::INDIRECT_FIRST_INSTANCE)
+        {
+            CullingMode::Gpu
+        } else {
+            CullingMode::Cpu
+        }
+    }
+}
+
+/// A range of instances drawn with the same mesh.
+#[derive(Clone, Debug)]
+pub struct CullBatch {
+    pub index_count: u32,
+    pub bounding_radius: f32,
+    pub instances: Range<u32>,
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct CullParams {
+    planes: [[f32; 4]; 6],
+    instance_count: u32,
+    batch_count: u32,
+    _padding: [u32; 2],
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct CullBatchRaw {
+    // xyz: local space center, w: radius
+    bounding_sphere: [f32; 4],
+    first_instance: u32,
+    instance_count: u32,
+    _padding: [u32; 2],
+}
+
+/// Frustum culls the instances of an `InstanceStorage` against per-batch bounding spheres
+/// and compacts the visible instance indices of each batch in place, starting at the
+/// batch's first instance, for the `vs_culled` vertex entry point.
+pub struct InstanceCuller {
+    pub mode: CullingMode,
+    // Number of visible instances per batch, only known on the CPU path
+    pub visible_counts: Vec<u32>,
+    pub params_buffer: wgpu::Buffer,
+    pub batch_buffer: wgpu::Buffer,
+    pub visible_buffer: wgpu::Buffer,
+    pub indirect_buffer: wgpu::Buffer,
+    pub compute_pipeline: wgpu::ComputePipeline,
+    pub compute_bind_group_layout: wgpu::BindGroupLayout,
+    pub render_bind_group_layout: wgpu::BindGroupLayout,
+    compute_bind_group: Option<wgpu::BindGroup>,
+    render_bind_group: Option<wgpu::BindGroup>,
+    batches: Vec<CullBatch>,
+    visible_capacity: u32,
+    batch_capacity: u32,
+    // Capacity of the instance buffer the bind groups were built for
+    bound_instance_capacity: wgpu::BufferAddress,
+}
+
+impl InstanceCuller {
+    pub fn new(device: &wgpu::Device, mode: CullingMode) -> Self {
+        let storage_entry = |binding, visibility, read_only| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility,
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Storage { read_only },
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        };
+
+        let compute_bind_group_layout =
+            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                entries: &[
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 0,
+                        visibility: wgpu::ShaderStages::COMPUTE,
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Uniform,
+                            has_dynamic_offset: false,
+                            min_binding_size: None,
+                        },
+                        count: None,
+                    },
+                    storage_entry(1, wgpu::ShaderStages::COMPUTE, true),
+                    storage_entry(2, wgpu::ShaderStages::COMPUTE, false),
+                    storage_entry(3, wgpu::ShaderStages::COMPUTE, false),
+                    storage_entry(4, wgpu::ShaderStages::COMPUTE, true),
+                ],
+                label: Some("cull_compute_bind_group_layout"),
+            });
+
+        let render_bind_group_layout =
+            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                entries: &[
+                    storage_entry(0, wgpu::ShaderStages::VERTEX, true),
+                    storage_entry(1, wgpu::ShaderStages::VERTEX, true),
+                ],
+                label: Some("cull_render_bind_group_layout"),
+            });
+
+        let source_code = pipeline_builder::builtin_shader("shaders/cull.wgsl");
+        let shader_module = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("Cull shader module"),
+            source: wgpu::ShaderSource::Wgsl(source_code.into()),
+        });
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("cull pipeline layout"),
+            bind_group_layouts: &[&compute_bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        let compute_pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: Some("Cull Pipeline"),
+            layout: Some(&pipeline_layout),
+            module: &shader_module,
+            entry_point: Some("cs_cull"),
+            compilation_options: wgpu::PipelineCompilationOptions::default(),
+            cache: None,
+        });
+
+        let params_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Cull Params Buffer"),
+            size: mem::size_of::<CullParams>() as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let batch_capacity = 1;
+        let (batch_buffer, indirect_buffer) = Self::create_batch_buffers(device, batch_capacity);
+        let visible_capacity = 1;
+        let visible_buffer = Self::create_visible_buffer(device, visible_capacity);
+
+        Self {
+            mode,
+            visible_counts: Vec::new(),
+            params_buffer,
+            batch_buffer,
+            visible_buffer,
+            indirect_buffer,
+            compute_pipeline,
+            compute_bind_group_layout,
+            render_bind_group_layout,
+            compute_bind_group: None,
+            render_bind_group: None,
+            batches: Vec::new(),
+            visible_capacity,
+            batch_capacity,
+            bound_instance_capacity: 0,
+        }
+    }
+
+    /// Records the culling compute pass into `encoder`. The visible counts stay on the GPU
+    /// and are consumed by `draw_indexed_indirect`, one argument struct per batch.
+    pub fn cull_gpu(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        encoder: &mut wgpu::CommandEncoder,
+        storage: &InstanceStorage,
+        batches: &[CullBatch],
+        frustum: &Frustum,
+    ) {
+        self.prepare(device, storage, batches);
+        if batches.is_empty() {
+            return;
+        }
+
+        let params = CullParams {
+            planes: frustum.planes.map(|plane| plane.into()),
+            instance_count: storage.count,
+            batch_count: batches.len() as u32,
+            _padding: [0; 2],
+        };
+        queue.write_buffer(&self.params_buffer, 0, bytemuck::cast_slice(&[params]));
+
+        let batches_raw = batches
+            .iter()
+            .map(|batch| CullBatchRaw {
+                bounding_sphere: [0.0, 0.0, 0.0, batch.bounding_radius],
+                first_instance: batch.instances.start,
+                instance_count: batch.instances.len() as u32,
+                _padding: [0; 2],
+            })
+            .collect::<Vec<_>>();
+        queue.write_buffer(&self.batch_buffer, 0, bytemuck::cast_slice(&batches_raw));
+
+        // instance_count is accumulated atomically by the compute shader
+        let args = batches
+            .iter()
+            .flat_map(|batch| {
+                DrawIndexedIndirectArgs {
+                    index_count: batch.index_count,
+                    instance_count: 0,
+                    first_index: 0,
+                    base_vertex: 0,
+                    first_instance: batch.instances.start,
+                }
+                .as_bytes()
+                .to_vec()
+            })
+            .collect::<Vec<_>>();
+        queue.write_buffer(&self.indirect_buffer, 0, &args);
+
+        let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+            label: Some("Cull Pass"),
+            timestamp_writes: None,
+        });
+        compute_pass.set_pipeline(&self.compute_pipeline);
+        compute_pass.set_bind_group(0, self.compute_bind_group.as_ref(), &[]);
+        compute_pass.dispatch_workgroups(storage.count.div_ceil(WORKGROUP_SIZE), 1, 1);
+    }
+
+    /// CPU fallback, `instances` must match the contents of `storage`.
+    pub fn cull_cpu(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        storage: &InstanceStorage,
+        instances: &[InstanceRaw],
+        batches: &[CullBatch],
+        frustum: &Frustum,
+    ) {
+        self.prepare(device, storage, batches);
+
+        self.visible_counts.clear();
+        for batch in batches {
+            let visible = batch
+                .instances
+                .clone()
+                .filter(|&index| {
+                    frustum.intersects_instance(&instances[index as usize], batch.bounding_radius)
+                })
+                .collect::<Vec<_>>();
+            if !visible.is_empty() {
+                let offset = batch.instances.start as usize * mem::size_of::<u32>();
+                queue.write_buffer(
+                    &self.visible_buffer,
+                    offset as wgpu::BufferAddress,
+                    bytemuck::cast_slice(&visible),
+                );
+            }
+            self.visible_counts.push(visible.len() as u32);
+        }
+    }
+
+    /// Issues the draw for the visible instances of `batch_index`, as passed to the last
+    /// cull. The caller binds `render_bind_group` at the group expected by `vs_culled`.
+    pub fn draw(&self, render_pass: &mut wgpu::RenderPass, batch_index: usize) {
+        match self.mode {
+            CullingMode::Gpu => {
+                let offset = batch_index * mem::size_of::<DrawIndexedIndirectArgs>();
+                render_pass
+                    .draw_indexed_indirect(&self.indirect_buffer, offset as wgpu::BufferAddress)
+            }
+            CullingMode::Cpu => {
+                let batch = &self.batches[batch_index];
+                let first = batch.instances.start;
+                render_pass.draw_indexed(
+                    0..batch.index_count,
+                    0,
+                    first..first + self.visible_counts[batch_index],
+                )
+            }
+        }
+    }
+
+    pub fn render_bind_group(&self) -> &wgpu::BindGroup {
+        self.render_bind_group
+            .as_ref()
+            .expect("InstanceCuller::cull must run before drawing")
+    }
+
+    // (Re)creates the bind groups whenever the instance buffer was reallocated
+    fn prepare(&mut self, device: &wgpu::Device, storage: &InstanceStorage, batches: &[CullBatch]) {
+        self.batches = batches.to_vec();
+
+        let mut rebind = self.bound_instance_capacity != storage.capacity;
+        if batches.len() as u32 > self.batch_capacity {
+            self.batch_capacity = (batches.len() as u32).next_power_of_two();
+            self.batch_buffer.destroy();
+            self.indirect_buffer.destroy();
+            (self.batch_buffer, self.indirect_buffer) =
+                Self::create_batch_buffers(device, self.batch_capacity);
+            rebind = true;
+        }
+        if storage.count > self.visible_capacity {
+            self.visible_capacity = storage.count.next_power_of_two();
+            self.visible_buffer.destroy();
+            self.visible_buffer = Self::create_visible_buffer(device, self.visible_capacity);
+            rebind = true;
+        }
+        if !rebind {
+            return;
+        }
+
+        self.compute_bind_group = Some(device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &self.compute_bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: self.params_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: storage.buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: self.visible_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: self.indirect_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 4,
+                    resource: self.batch_buffer.as_entire_binding(),
+                },
+            ],
+            label: Some("cull_compute_bind_group"),
+        }));
+        self.render_bind_group = Some(device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &self.render_bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: storage.buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: self.visible_buffer.as_entire_binding(),
+                },
+            ],
+            label: Some("cull_render_bind_group"),
+        }));
+        self.bound_instance_capacity = storage.capacity;
+    }
+
+    fn create_batch_buffers(device: &wgpu::Device, capacity: u32) -> (wgpu::Buffer, wgpu::Buffer) {
+        let batch_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Cull Batch Buffer"),
+            size: (capacity as usize * mem::size_of::<CullBatchRaw>()) as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let indirect_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Draw Indirect Buffer"),
+            size: (capacity as usize * mem::size_of::<DrawIndexedIndirectArgs>())
+                as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::INDIRECT
+                | wgpu::BufferUsages::STORAGE
+                | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        (batch_buffer, indirect_buffer)
+    }
+
+    fn create_visible_buffer(device: &wgpu::Device, capacity: u32) -> wgpu::Buffer {
+        device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Visible Instances Buffer"),
+            size: (capacity as usize * mem::size_of::<u32>()) as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        })
+    }
+}
@@ -0,0 +1,289 @@
+use std::time::{Duration, Instant};
+
+use cgmath::{EuclideanSpace, Matrix4, Point3, Transform, Vector3};
+
+use super::{camera::CameraBinding, depth::DEPTH_FORMAT, pipeline_builder};
+
+// Segments of each wire sphere circle
+const SPHERE_SEGMENTS: usize = 32;
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+struct DebugVertex {
+    position: [f32; 3],
+    color: [f32; 4],
+}
+
+impl DebugVertex {
+    fn desc() -> wgpu::VertexBufferLayout<'static> {
+        const ATTRIBUTES: [wgpu::VertexAttribute; 2] =
+            wgpu::vertex_attr_array![0 => Float32x3, 1 => Float32x4];
+        wgpu::VertexBufferLayout {
+            array_stride: std::mem::size_of::<DebugVertex>() as u64,
+            step_mode: wgpu::VertexStepMode::Vertex,
+            attributes: &ATTRIBUTES,
+        }
+    }
+}
+
+struct DebugLine {
+    start: Point3<f32>,
+    end: Point3<f32>,
+    color: [f32; 4],
+    depth_test: bool,
+    // Drawn for a single frame when None
+    expires: Option<Instant>,
+}
+
+// Line list pipelines for one sample count, with and without depth testing
+struct DebugPipelines {
+    sample_count: u32,
+    depth_tested: wgpu::RenderPipeline,
+    overlay: wgpu::RenderPipeline,
+}
+
+/// Immediate-mode lines for visualizing transforms, bounds and lights. Primitives
+/// are collected during the frame and drawn at the end of the scene pass. The
+/// depth test and lifetime set with `set_depth_test` and `set_lifetime` apply to
+/// the primitives added after them.
+pub struct DebugDraw {
+    lines: Vec<DebugLine>,
+    depth_test: bool,
+    lifetime: f32,
+    pipelines: Vec<DebugPipelines>,
+    vertex_buffer: wgpu::Buffer,
+    // In vertices
+    capacity: usize,
+    // Vertices uploaded by `prepare`, depth tested lines first
+    depth_tested_count: u32,
+    overlay_count: u32,
+}
+
+impl DebugDraw {
+    pub fn new(
+        device: &wgpu::Device,
+        format: wgpu::TextureFormat,
+        sample_count: u32,
+        camera_binding: &CameraBinding,
+    ) -> Self {
+        let build_pipelines = |sample_count| {
+            let build_pipeline = |depth_compare, label| {
+                let mut pipeline_builder = pipeline_builder::PipelineBuilder::new(
+                    device,
+                    pipeline_builder::ShaderSource::Builtin("shaders/debug_draw.wgsl"),
+                    "vs_debug",
+                    "fs_debug",
+                    format,
+                );
+                pipeline_builder.add_bind_group_layout(&camera_binding.bind_group_layout);
+                pipeline_builder.add_vertex_buffer_layout(DebugVertex::desc());
+                pipeline_builder.set_topology(wgpu::PrimitiveTopology::LineList);
+                pipeline_builder.set_cull_mode(None);
+                pipeline_builder.set_depth_stencil(DEPTH_FORMAT, false, depth_compare);
+                pipeline_builder.set_blend_states(&[wgpu::BlendState::ALPHA_BLENDING]);
+                pipeline_builder.set_multisample(sample_count, false);
+                pipeline_builder.build_pipeline(label)
+            };
+            DebugPipelines {
+                sample_count,
+                depth_tested: build_pipeline(
+                    wgpu::CompareFunction::LessEqual,
+                    "Debug Draw Pipeline",
+                ),
+                overlay: build_pipeline(wgpu::CompareFunction::Always, "Debug Overlay Pipeline"),
+            }
+        };
+        let mut pipelines = vec![build_pipelines(1)];
+        if sample_count > 1 {
+            pipelines.push(build_pipelines(sample_count));
+        }
+
+        let capacity = 1024;
+        Self {
+            lines: Vec::new(),
+            depth_test: true,
+            lifetime: 0.0,
+            pipelines,
+            vertex_buffer: Self::create_buffer(device, capacity),
+            capacity,
+            depth_tested_count: 0,
+            overlay_count: 0,
+        }
+    }
+
+    fn create_buffer(device: &wgpu::Device, capacity: usize) -> wgpu::Buffer {
+        device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Debug Draw Vertex Buffer"),
+            size: (capacity * std::mem::size_of::<DebugVertex>()) as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        })
+    }
+
+    // When disabled, primitives are drawn on top of the scene
+    pub fn set_depth_test(&mut self, depth_test: bool) {
+        self.depth_test = depth_test;
+    }
+
+    // Seconds primitives stay visible, 0 draws them for the next frame only
+    pub fn set_lifetime(&mut self, seconds: f32) {
+        self.lifetime = seconds.max(0.0);
+    }
+
+    pub fn clear(&mut self) {
+        self.lines.clear();
+    }
+
+    pub fn line(&mut self, start: Point3<f32>, end: Point3<f32>, color: [f32; 4]) {
+        let expires =
+            (self.lifetime > 0.0).then(|| Instant::now() + Duration::from_secs_f32(self.lifetime));
+        self.lines.push(DebugLine {
+            start,
+            end,
+            color,
+            depth_test: self.depth_test,
+            expires,
+        });
+    }
+
+    pub fn aabb(&mut self, min: Point3<f32>, max: Point3<f32>, color: [f32; 4]) {
+        let corner = |index: usize| {
+            Point3::new(
+                if index & 1 == 0 { min.x } else { max.x },
+                if index & 2 == 0 { min.y } else { max.y },
+                if index & 4 == 0 { min.z } else { max.z },
+            )
+        };
+        // Corners differing in a single axis bit share an edge
+        for index in 0..8 {
+            for axis_bit in [1, 2, 4] {
+                if index & axis_bit == 0 {
+                    self.line(corner(index), corner(index | axis_bit), color);
+                }
+            }
+        }
+    }
+
+    // Three great circles, one around each axis
+    pub fn wire_sphere(&mut self, center: Point3<f32>, radius: f32, color: [f32; 4]) {
+        let point = |angle: f32, axis: usize| {
+            let (sin, cos) = angle.sin_cos();
+            let offset = match axis {
+                0 => Vector3::new(0.0, cos, sin),
+                1 => Vector3::new(cos, 0.0, sin),
+                _ => Vector3::new(cos, sin, 0.0),
+            };
+            center + offset * radius
+        };
+        let step = std::f32::consts::TAU / SPHERE_SEGMENTS as f32;
+        for axis in 0..3 {
+            for segment in 0..SPHERE_SEGMENTS {
+                let angle = segment as f32 * step;
+                self.line(point(angle, axis), point(angle + step, axis), color);
+            }
+        }
+    }
+
+    // X, Y and Z axes of `transform` in red, green and blue
+    pub fn axes(&mut self, transform: Matrix4<f32>, length: f32) {
+        let origin = transform.transform_point(Point3::origin());
+        let axes = [
+            (Vector3::unit_x(), [1.0, 0.0, 0.0, 1.0]),
+            (Vector3::unit_y(), [0.0, 1.0, 0.0, 1.0]),
+            (Vector3::unit_z(), [0.0, 0.0, 1.0, 1.0]),
+        ];
+        for (axis, color) in axes {
+            let end = transform.transform_point(Point3::from_vec(axis * length));
+            self.line(origin, end, color);
+        }
+    }
+
+    // Square grid on the XZ plane, `size` wide with `divisions` cells per side
+    pub fn grid(&mut self, center: Point3<f32>, size: f32, divisions: u32, color: [f32; 4]) {
+        let half = size * 0.5;
+        let divisions = divisions.max(1);
+        for line in 0..=divisions {
+            let offset = -half + size * line as f32 / divisions as f32;
+            self.line(
+                center + Vector3::new(offset, 0.0, -half),
+                center + Vector3::new(offset, 0.0, half),
+                color,
+            );
+            self.line(
+                center + Vector3::new(-half, 0.0, offset),
+                center + Vector3::new(half, 0.0, offset),
+                color,
+            );
+        }
+    }
+
+    /// Uploads this frame's lines, call before the scene pass is recorded.
+    pub fn prepare(&mut self, device: &wgpu::Device, queue: &wgpu::Queue) {
+        let mut vertices = Vec::with_capacity(self.lines.len() * 2);
+        for depth_test in [true, false] {
+            for line in self
+                .lines
+                .iter()
+                .filter(|line| line.depth_test == depth_test)
+            {
+                for position in [line.start, line.end] {
+                    vertices.push(DebugVertex {
+                        position: position.into(),
+                        color: line.color,
+                    });
+                }
+            }
+            if depth_test {
+                self.depth_tested_count = vertices.len() as u32;
+            }
+        }
+        self.overlay_count = vertices.len() as u32 - self.depth_tested_count;
+
+        if vertices.len() > self.capacity {
+            self.capacity = vertices.len().next_power_of_two();
+            self.vertex_buffer.destroy();
+            self.vertex_buffer = Self::create_buffer(device, self.capacity);
+        }
+        if !vertices.is_empty() {
+            queue.write_buffer(&self.vertex_buffer, 0, bytemuck::cast_slice(&vertices));
+        }
+    }
+
+    // Drops single frame and expired primitives, call after the frame is submitted
+    pub fn end_frame(&mut self) {
+        let now = Instant::now();
+        self.lines
+            .retain(|line| line.expires.is_some_and(|expires| expires > now));
+    }
+
+    // `sample_count` of the attachments in `render_pass`
+    pub fn draw(
+        &self,
+        render_pass: &mut wgpu::RenderPass,
+        camera_binding: &CameraBinding,
+        sample_count: u32,
+    ) {
+        if self.depth_tested_count + self.overlay_count == 0 {
+            return;
+        }
+        let Some(pipelines) = self
+            .pipelines
+            .iter()
+            .find(|pipelines| pipelines.sample_count == sample_count)
+        else {
+            return;
+        };
+        render_pass.set_bind_group(0, &camera_binding.bind_group, &[]);
+        render_pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
+        let total = self.depth_tested_count + self.overlay_count;
+        for (pipeline, vertices) in [
+            (&pipelines.depth_tested, 0..self.depth_tested_count),
+            (&pipelines.overlay, self.depth_tested_count..total),
+        ] {
+            if !vertices.is_empty() {
+                render_pass.set_pipeline(pipeline);
+                render_pass.draw(vertices, 0..1);
+            }
+        }
+    }
+}
@@ -0,0 +1,213 @@
+use std::{cell::RefCell, mem};
+
+use super::{
+    camera::CameraBinding, light::LightBinding, pipeline_builder, post_process::HDR_FORMAT,
+};
+
+// Albedo + occlusion, world normal, metallic/roughness, emissive
+pub const GBUFFER_FORMATS: [wgpu::TextureFormat; 4] = [
+    wgpu::TextureFormat::Rgba8UnormSrgb,
+    wgpu::TextureFormat::Rgba16Float,
+    wgpu::TextureFormat::Rgba8Unorm,
+    wgpu::TextureFormat::Rgba16Float,
+];
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum RenderPath {
+    Forward,
+    // Opaque geometry goes through the G-buffer, custom pipelines are ignored
+    Deferred,
+}
+
+/// G-buffer channel shown instead of the lit result.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum DebugView {
+    None,
+    Albedo,
+    Normal,
+    Metallic,
+    Roughness,
+    Occlusion,
+    Emissive,
+    Depth,
+}
+
+impl DebugView {
+    fn to_raw(self) -> u32 {
+        match self {
+            DebugView::None => 0,
+            DebugView::Albedo => 1,
+            DebugView::Normal => 2,
+            DebugView::Metallic => 3,
+            DebugView::Roughness => 4,
+            DebugView::Occlusion => 5,
+            DebugView::Emissive => 6,
+            DebugView::Depth => 7,
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct DeferredParams {
+    clear_color: [f32; 4],
+    debug_view: u32,
+    _padding: [u32; 3],
+}
+
+// Bind group of the G-buffer and the views it was created with
+struct GBufferBindGroup {
+    views: [wgpu::TextureView; 5],
+    bind_group: wgpu::BindGroup,
+}
+
+/// Fullscreen lighting pass over the G-buffer. It shares `shade_pbr` and the
+/// light bind group with the forward path, so lights, shadows and IBL match.
+pub struct DeferredLighting {
+    pub debug_view: DebugView,
+    pub pipeline: wgpu::RenderPipeline,
+    pub bind_group_layout: wgpu::BindGroupLayout,
+    params_buffer: wgpu::Buffer,
+    // Kept across frames and rebuilt when the graph hands out other G-buffer
+    // textures, which happens on resize. `draw` runs inside the render graph.
+    bind_group: RefCell<Option<GBufferBindGroup>>,
+}
+
+impl DeferredLighting {
+    pub fn new(
+        device: &wgpu::Device,
+        camera_binding: &CameraBinding,
+        light_binding: &LightBinding,
+    ) -> Self {
+        let texture_entry = |binding, sample_type| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::FRAGMENT,
+            ty: wgpu::BindingType::Texture {
+                multisampled: false,
+                view_dimension: wgpu::TextureViewDimension::D2,
+                sample_type,
+            },
+            count: None,
+        };
+        let color = wgpu::TextureSampleType::Float { filterable: false };
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: &[
+                texture_entry(7, color),
+                texture_entry(8, color),
+                texture_entry(9, color),
+                texture_entry(10, color),
+                texture_entry(11, wgpu::TextureSampleType::Depth),
+                wgpu::BindGroupLayoutEntry {
+                    binding: 12,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+            ],
+            label: Some("gbuffer_bind_group_layout"),
+        });
+
+        let params_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Deferred Params Buffer"),
+            size: mem::size_of::<DeferredParams>() as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        let mut pipeline_builder = pipeline_builder::PipelineBuilder::new(
+            device,
+            pipeline_builder::ShaderSource::Builtin("shaders/shader.wgsl"),
+            "vs_deferred",
+            "fs_deferred",
+            HDR_FORMAT,
+        );
+        pipeline_builder.add_bind_group_layout(&bind_group_layout);
+        pipeline_builder.add_bind_group_layout(&camera_binding.bind_group_layout);
+        pipeline_builder.add_bind_group_layout(&light_binding.bind_group_layout);
+        pipeline_builder.set_cull_mode(None);
+        let pipeline = pipeline_builder.build_pipeline("Deferred Lighting Pipeline");
+
+        Self {
+            debug_view: DebugView::None,
+            pipeline,
+            bind_group_layout,
+            params_buffer,
+            bind_group: RefCell::new(None),
+        }
+    }
+
+    // Releases the old G-buffer textures, the next draw rebuilds the bind group
+    pub fn resize(&self) {
+        self.bind_group.replace(None);
+    }
+
+    pub fn update(&self, queue: &wgpu::Queue, clear_color: wgpu::Color) {
+        let params = DeferredParams {
+            clear_color: [
+                clear_color.r as f32,
+                clear_color.g as f32,
+                clear_color.b as f32,
+                clear_color.a as f32,
+            ],
+            debug_view: self.debug_view.to_raw(),
+            _padding: [0; 3],
+        };
+        queue.write_buffer(&self.params_buffer, 0, bytemuck::cast_slice(&[params]));
+    }
+
+    /// `gbuffer` holds the views in `GBUFFER_FORMATS` order followed by depth.
+    pub fn draw(
+        &self,
+        device: &wgpu::Device,
+        render_pass: &mut wgpu::RenderPass,
+        gbuffer: [&wgpu::TextureView; 5],
+        camera_binding: &CameraBinding,
+        light_binding: &LightBinding,
+    ) {
+        let mut cached = self.bind_group.borrow_mut();
+        let outdated = cached
+            .as_ref()
+            .is_none_or(|cached| cached.views.iter().zip(gbuffer).any(|(a, b)| a != b));
+        if outdated {
+            *cached = Some(GBufferBindGroup {
+                views: gbuffer.map(Clone::clone),
+                bind_group: self.create_bind_group(device, gbuffer),
+            });
+        }
+        let bind_group = &cached.as_ref().unwrap().bind_group;
+
+        render_pass.set_pipeline(&self.pipeline);
+        render_pass.set_bind_group(0, bind_group, &[]);
+        render_pass.set_bind_group(1, &camera_binding.bind_group, &[]);
+        render_pass.set_bind_group(2, &light_binding.bind_group, &[]);
+        render_pass.draw(0..3, 0..1);
+    }
+
+    fn create_bind_group(
+        &self,
+        device: &wgpu::Device,
+        gbuffer: [&wgpu::TextureView; 5],
+    ) -> wgpu::BindGroup {
+        let mut entries = gbuffer
+            .iter()
+            .zip(7..)
+            .map(|(view, binding)| wgpu::BindGroupEntry {
+                binding,
+                resource: wgpu::BindingResource::TextureView(view),
+            })
+            .collect::<Vec<_>>();
+        entries.push(wgpu::BindGroupEntry {
+            binding: 12,
+            resource: self.params_buffer.as_entire_binding(),
+        });
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &self.bind_group_layout,
+            entries: &entries,
+            label: Some("gbuffer_bind_group"),
+        })
+    }
+}
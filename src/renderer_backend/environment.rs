@@ -0,0 +1,460 @@
+use wgpu::util::DeviceExt;
+
+use super::pipeline_builder;
+
+pub const ENVIRONMENT_SIZE: u32 = 512;
+pub const IRRADIANCE_SIZE: u32 = 32;
+pub const PREFILTERED_SIZE: u32 = 128;
+// Roughness 0.0, 0.25, 0.5, 0.75 and 1.0
+pub const PREFILTERED_MIP_LEVELS: u32 = 5;
+pub const BRDF_LUT_SIZE: u32 = 256;
+const PREFILTER_SAMPLE_COUNT: u32 = 1024;
+const WORKGROUP_SIZE: u32 = 8;
+const CUBE_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba16Float;
+
+#[repr(C)]
+#[derive(Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct FilterParams {
+    roughness: f32,
+    source_size: f32,
+    sample_count: u32,
+    _padding: u32,
+}
+
+/// Image based lighting maps precomputed on the GPU from an equirectangular image:
+/// the environment cubemap itself, a diffuse irradiance cubemap, a GGX prefiltered
+/// specular cubemap (one roughness per mip) and the split sum BRDF lookup table.
+#[derive(Clone)]
+pub struct Environment {
+    pub environment_map: wgpu::Texture,
+    pub irradiance_map: wgpu::Texture,
+    pub prefiltered_map: wgpu::Texture,
+    pub brdf_lut: wgpu::Texture,
+    pub environment_view: wgpu::TextureView,
+    pub irradiance_view: wgpu::TextureView,
+    pub prefiltered_view: wgpu::TextureView,
+    pub brdf_lut_view: wgpu::TextureView,
+    pub sampler: wgpu::Sampler,
+}
+
+impl Environment {
+    /// Loads an equirectangular `.hdr` or `.exr` image, path relative to `src/`.
+    pub fn from_equirectangular(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        image_path: &str,
+    ) -> anyhow::Result<Self> {
+        let full_path = format!("src/{}", image_path);
+        let image = image::open(full_path)?.to_rgba32f();
+        let (width, height) = image.dimensions();
+        Ok(Self::from_pixels(
+            device,
+            queue,
+            image.as_raw(),
+            width,
+            height,
+        ))
+    }
+
+    /// Uniformly colored environment, used when no HDR image is available.
+    pub fn from_color(device: &wgpu::Device, queue: &wgpu::Queue, color: [f32; 3]) -> Self {
+        let pixel = [color[0], color[1], color[2], 1.0];
+        Self::from_pixels(device, queue, &[pixel, pixel].concat(), 2, 1)
+    }
+
+    fn from_pixels(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        rgba: &[f32],
+        width: u32,
+        height: u32,
+    ) -> Self {
+        let equirect_texture = device.create_texture_with_data(
+            queue,
+            &wgpu::TextureDescriptor {
+                label: Some("Equirectangular Texture"),
+                size: wgpu::Extent3d {
+                    width,
+                    height,
+                    depth_or_array_layers: 1,
+                },
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: wgpu::TextureDimension::D2,
+                format: wgpu::TextureFormat::Rgba32Float,
+                usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+                view_formats: &[],
+            },
+            wgpu::util::TextureDataOrder::LayerMajor,
+            bytemuck::cast_slice(rgba),
+        );
+
+        let environment_mip_levels = ENVIRONMENT_SIZE.ilog2() + 1;
+        let environment_map = create_cubemap(
+            device,
+            "Environment Map",
+            ENVIRONMENT_SIZE,
+            environment_mip_levels,
+        );
+        let irradiance_map = create_cubemap(device, "Irradiance Map", IRRADIANCE_SIZE, 1);
+        let prefiltered_map = create_cubemap(
+            device,
+            "Prefiltered Map",
+            PREFILTERED_SIZE,
+            PREFILTERED_MIP_LEVELS,
+        );
+        let brdf_lut = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("BRDF LUT"),
+            size: wgpu::Extent3d {
+                width: BRDF_LUT_SIZE,
+                height: BRDF_LUT_SIZE,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: CUBE_FORMAT,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::STORAGE_BINDING,
+            view_formats: &[],
+        });
+
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("Environment Sampler"),
+            address_mode_u: wgpu::AddressMode::ClampToEdge,
+            address_mode_v: wgpu::AddressMode::ClampToEdge,
+            address_mode_w: wgpu::AddressMode::ClampToEdge,
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            mipmap_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
+        });
+
+        let passes = IblPasses::new(device);
+        let mut command_encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("IBL Command Encoder"),
+        });
+        {
+            let mut compute_pass =
+                command_encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+                    label: Some("IBL Pass"),
+                    timestamp_writes: None,
+                });
+
+            // Equirectangular image to the first mip of the environment cubemap
+            let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+                layout: &passes.equirect_layout,
+                entries: &[
+                    wgpu::BindGroupEntry {
+                        binding: 0,
+                        resource: wgpu::BindingResource::TextureView(
+                            &equirect_texture.create_view(&wgpu::TextureViewDescriptor::default()),
+                        ),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 1,
+                        resource: wgpu::BindingResource::TextureView(&face_array_view(
+                            &environment_map,
+                            0,
+                        )),
+                    },
+                ],
+                label: Some("equirect_bind_group"),
+            });
+            compute_pass.set_pipeline(&passes.equirect_to_cube);
+            compute_pass.set_bind_group(0, &bind_group, &[]);
+            dispatch_cube(&mut compute_pass, ENVIRONMENT_SIZE);
+
+            // Mip chain of the environment, sampled by the filters below
+            compute_pass.set_pipeline(&passes.downsample);
+            for level in 1..environment_mip_levels {
+                let bind_group = passes.filter_bind_group(
+                    device,
+                    &cube_view(&environment_map, level - 1, Some(1)),
+                    &face_array_view(&environment_map, level),
+                    &sampler,
+                    0.0,
+                    0,
+                );
+                compute_pass.set_bind_group(0, &bind_group, &[]);
+                dispatch_cube(&mut compute_pass, ENVIRONMENT_SIZE >> level);
+            }
+
+            let environment_view = cube_view(&environment_map, 0, None);
+            let bind_group = passes.filter_bind_group(
+                device,
+                &environment_view,
+                &face_array_view(&irradiance_map, 0),
+                &sampler,
+                0.0,
+                0,
+            );
+            compute_pass.set_pipeline(&passes.irradiance);
+            compute_pass.set_bind_group(0, &bind_group, &[]);
+            dispatch_cube(&mut compute_pass, IRRADIANCE_SIZE);
+
+            compute_pass.set_pipeline(&passes.prefilter);
+            for level in 0..PREFILTERED_MIP_LEVELS {
+                let roughness = level as f32 / (PREFILTERED_MIP_LEVELS - 1) as f32;
+                let bind_group = passes.filter_bind_group(
+                    device,
+                    &environment_view,
+                    &face_array_view(&prefiltered_map, level),
+                    &sampler,
+                    roughness,
+                    PREFILTER_SAMPLE_COUNT,
+                );
+                compute_pass.set_bind_group(0, &bind_group, &[]);
+                dispatch_cube(&mut compute_pass, PREFILTERED_SIZE >> level);
+            }
+
+            let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+                layout: &passes.brdf_layout,
+                entries: &[wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(
+                        &brdf_lut.create_view(&wgpu::TextureViewDescriptor::default()),
+                    ),
+                }],
+                label: Some("brdf_lut_bind_group"),
+            });
+            compute_pass.set_pipeline(&passes.brdf_lut);
+            compute_pass.set_bind_group(0, &bind_group, &[]);
+            compute_pass.dispatch_workgroups(
+                BRDF_LUT_SIZE.div_ceil(WORKGROUP_SIZE),
+                BRDF_LUT_SIZE.div_ceil(WORKGROUP_SIZE),
+                1,
+            );
+        }
+        queue.submit(std::iter::once(command_encoder.finish()));
+
+        Self {
+            environment_view: cube_view(&environment_map, 0, None),
+            irradiance_view: cube_view(&irradiance_map, 0, None),
+            prefiltered_view: cube_view(&prefiltered_map, 0, None),
+            brdf_lut_view: brdf_lut.create_view(&wgpu::TextureViewDescriptor::default()),
+            environment_map,
+            irradiance_map,
+            prefiltered_map,
+            brdf_lut,
+            sampler,
+        }
+    }
+}
+
+fn create_cubemap(device: &wgpu::Device, label: &str, size: u32, mip_levels: u32) -> wgpu::Texture {
+    device.create_texture(&wgpu::TextureDescriptor {
+        label: Some(label),
+        size: wgpu::Extent3d {
+            width: size,
+            height: size,
+            depth_or_array_layers: 6,
+        },
+        mip_level_count: mip_levels,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D2,
+        format: CUBE_FORMAT,
+        usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::STORAGE_BINDING,
+        view_formats: &[],
+    })
+}
+
+fn cube_view(
+    texture: &wgpu::Texture,
+    base_mip_level: u32,
+    mip_levels: Option<u32>,
+) -> wgpu::TextureView {
+    texture.create_view(&wgpu::TextureViewDescriptor {
+        dimension: Some(wgpu::TextureViewDimension::Cube),
+        base_mip_level,
+        mip_level_count: mip_levels,
+        ..Default::default()
+    })
+}
+
+// Storage textures cannot be cubes, the six faces are written as a 2D array
+fn face_array_view(texture: &wgpu::Texture, mip_level: u32) -> wgpu::TextureView {
+    texture.create_view(&wgpu::TextureViewDescriptor {
+        dimension: Some(wgpu::TextureViewDimension::D2Array),
+        base_mip_level: mip_level,
+        mip_level_count: Some(1),
+        ..Default::default()
+    })
+}
+
+fn dispatch_cube(compute_pass: &mut wgpu::ComputePass, size: u32) {
+    let groups = size.max(1).div_ceil(WORKGROUP_SIZE);
+    compute_pass.dispatch_workgroups(groups, groups, 6);
+}
+
+struct IblPasses {
+    equirect_layout: wgpu::BindGroupLayout,
+    filter_layout: wgpu::BindGroupLayout,
+    brdf_layout: wgpu::BindGroupLayout,
+    equirect_to_cube: wgpu::ComputePipeline,
+    downsample: wgpu::ComputePipeline,
+    irradiance: wgpu::ComputePipeline,
+    prefilter: wgpu::ComputePipeline,
+    brdf_lut: wgpu::ComputePipeline,
+}
+
+impl IblPasses {
+    fn new(device: &wgpu::Device) -> Self {
+        let storage_entry = |binding, view_dimension| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::COMPUTE,
+            ty: wgpu::BindingType::StorageTexture {
+                access: wgpu::StorageTextureAccess::WriteOnly,
+                format: CUBE_FORMAT,
+                view_dimension,
+            },
+            count: None,
+        };
+
+        let equirect_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Texture {
+                        multisampled: false,
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        sample_type: wgpu::TextureSampleType::Float { filterable: false },
+                    },
+                    count: None,
+                },
+                storage_entry(1, wgpu::TextureViewDimension::D2Array),
+            ],
+            label: Some("ibl_equirect_bind_group_layout"),
+        });
+
+        let filter_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Texture {
+                        multisampled: false,
+                        view_dimension: wgpu::TextureViewDimension::Cube,
+                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                    },
+                    count: None,
+                },
+                storage_entry(1, wgpu::TextureViewDimension::D2Array),
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 3,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+            ],
+            label: Some("ibl_filter_bind_group_layout"),
+        });
+
+        let brdf_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: &[storage_entry(0, wgpu::TextureViewDimension::D2)],
+            label: Some("ibl_brdf_bind_group_layout"),
+        });
+
+        // One module per bind group layout, each with the shared helpers prepended
+        let create_module = |name: &str| {
+            let source_code = pipeline_builder::builtin_shader("shaders/ibl_common.wgsl")
+                .to_string()
+                + pipeline_builder::builtin_shader(name);
+            device.create_shader_module(wgpu::ShaderModuleDescriptor {
+                label: Some(name),
+                source: wgpu::ShaderSource::Wgsl(source_code.into()),
+            })
+        };
+        let equirect_module = create_module("shaders/ibl_equirect.wgsl");
+        let filter_module = create_module("shaders/ibl_filter.wgsl");
+        let brdf_module = create_module("shaders/ibl_brdf.wgsl");
+
+        let create_pipeline = |shader_module: &wgpu::ShaderModule,
+                               layout: &wgpu::BindGroupLayout,
+                               entry_point: &str| {
+            let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("ibl pipeline layout"),
+                bind_group_layouts: &[layout],
+                push_constant_ranges: &[],
+            });
+            device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+                label: Some(entry_point),
+                layout: Some(&pipeline_layout),
+                module: shader_module,
+                entry_point: Some(entry_point),
+                compilation_options: wgpu::PipelineCompilationOptions::default(),
+                cache: None,
+            })
+        };
+
+        Self {
+            equirect_to_cube: create_pipeline(
+                &equirect_module,
+                &equirect_layout,
+                "cs_equirect_to_cube",
+            ),
+            downsample: create_pipeline(&filter_module, &filter_layout, "cs_downsample"),
+            irradiance: create_pipeline(&filter_module, &filter_layout, "cs_irradiance"),
+            prefilter: create_pipeline(&filter_module, &filter_layout, "cs_prefilter"),
+            brdf_lut: create_pipeline(&brdf_module, &brdf_layout, "cs_brdf_lut"),
+            equirect_layout,
+            filter_layout,
+            brdf_layout,
+        }
+    }
+
+    fn filter_bind_group(
+        &self,
+        device: &wgpu::Device,
+        source: &wgpu::TextureView,
+        output: &wgpu::TextureView,
+        sampler: &wgpu::Sampler,
+        roughness: f32,
+        sample_count: u32,
+    ) -> wgpu::BindGroup {
+        let params = FilterParams {
+            roughness,
+            source_size: ENVIRONMENT_SIZE as f32,
+            sample_count,
+            _padding: 0,
+        };
+        let params_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("IBL Filter Params Buffer"),
+            contents: bytemuck::cast_slice(&[params]),
+            usage: wgpu::BufferUsages::UNIFORM,
+        });
+
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &self.filter_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(source),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::TextureView(output),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: wgpu::BindingResource::Sampler(sampler),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: params_buffer.as_entire_binding(),
+                },
+            ],
+            label: Some("ibl_filter_bind_group"),
+        })
+    }
+}
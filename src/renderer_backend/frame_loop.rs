@@ -0,0 +1,213 @@
+use std::time::{Duration, Instant};
+
+#[derive(Copy, Clone, Debug)]
+pub struct FrameLoopSettings {
+    // Seconds simulated by each update callback
+    pub fixed_timestep: f32,
+    // Longest frame time fed to the updates, avoids a spiral of catch-up updates
+    // after a stall (window drag, breakpoint)
+    pub max_frame_time: f32,
+    // Frames per second the loop sleeps down to, on top of the present mode
+    pub frame_cap: Option<f32>,
+}
+
+impl Default for FrameLoopSettings {
+    fn default() -> Self {
+        Self {
+            fixed_timestep: 1.0 / 60.0,
+            max_frame_time: 0.25,
+            frame_cap: None,
+        }
+    }
+}
+
+/// Timing of one rendered frame.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct FrameTime {
+    // Seconds since the previous frame
+    pub delta: f32,
+    // Seconds since the loop started
+    pub elapsed: f64,
+    // Fixed updates run this frame
+    pub updates: u32,
+    // How far the frame is between the last two updates, for interpolating state
+    pub alpha: f32,
+    pub frame: u64,
+}
+
+/// Fixed-timestep updates with variable-rate rendering. Each frame the real
+/// elapsed time is accumulated and consumed in `fixed_timestep` steps; what is
+/// left over becomes the interpolation `alpha` for rendering.
+pub struct FrameLoop {
+    settings: FrameLoopSettings,
+    last_frame: Instant,
+    elapsed: f64,
+    accumulator: f32,
+    frame: u64,
+}
+
+impl FrameLoop {
+    /// A `fixed_timestep` or `max_frame_time` that isn't positive falls back to the
+    /// default, and a `frame_cap` that isn't positive means no cap.
+    pub fn new(mut settings: FrameLoopSettings) -> Self {
+        let defaults = FrameLoopSettings::default();
+        if !is_positive(settings.fixed_timestep) {
+            log::warn!(
+                "Invalid fixed timestep {}, using the default",
+                settings.fixed_timestep
+            );
+            settings.fixed_timestep = defaults.fixed_timestep;
+        }
+        if !is_positive(settings.max_frame_time) {
+            log::warn!(
+                "Invalid max frame time {}, using the default",
+                settings.max_frame_time
+            );
+            settings.max_frame_time = defaults.max_frame_time;
+        }
+        settings.frame_cap = settings
+            .frame_cap
+            .filter(|&frame_cap| is_positive(frame_cap));
+        Self {
+            settings,
+            last_frame: Instant::now(),
+            elapsed: 0.0,
+            accumulator: 0.0,
+            frame: 0,
+        }
+    }
+
+    pub fn settings(&self) -> &FrameLoopSettings {
+        &self.settings
+    }
+
+    /// Runs `advance` with the wall clock time since the previous frame.
+    pub fn tick(&mut self, timestep_override: Option<f32>, update: impl FnMut(f32)) -> FrameTime {
+        let now = Instant::now();
+        let real_delta = (now - self.last_frame).as_secs_f32();
+        self.last_frame = now;
+        self.advance(real_delta, timestep_override, update)
+    }
+
+    /// Runs the fixed updates due after `real_delta` seconds. `timestep_override`
+    /// replaces it, e.g. `State::recording_timestep` while recording a video.
+    pub fn advance(
+        &mut self,
+        real_delta: f32,
+        timestep_override: Option<f32>,
+        mut update: impl FnMut(f32),
+    ) -> FrameTime {
+        let delta = timestep_override.unwrap_or(real_delta.min(self.settings.max_frame_time));
+        self.elapsed += real_delta as f64;
+
+        self.accumulator += delta;
+        let mut updates = 0;
+        while self.accumulator >= self.settings.fixed_timestep {
+            update(self.settings.fixed_timestep);
+            self.accumulator -= self.settings.fixed_timestep;
+            updates += 1;
+        }
+
+        self.frame += 1;
+        FrameTime {
+            delta,
+            elapsed: self.elapsed,
+            updates,
+            alpha: self.accumulator / self.settings.fixed_timestep,
+            frame: self.frame,
+        }
+    }
+
+    // Sleeps for the rest of the frame when a frame cap is set
+    pub fn limit_frame_rate(&self) {
+        if let Some(frame_cap) = self.settings.frame_cap {
+            let frame_duration = Duration::from_secs_f32(1.0 / frame_cap);
+            let elapsed = self.last_frame.elapsed();
+            if elapsed < frame_duration {
+                std::thread::sleep(frame_duration - elapsed);
+            }
+        }
+    }
+}
+
+// False for zero, negative values, NaN and infinity
+fn is_positive(value: f32) -> bool {
+    value.is_finite() && value > 0.0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Timesteps are powers of two so the accumulator stays exact
+    fn frame_loop(fixed_timestep: f32, max_frame_time: f32) -> FrameLoop {
+        FrameLoop::new(FrameLoopSettings {
+            fixed_timestep,
+            max_frame_time,
+            frame_cap: None,
+        })
+    }
+
+    #[test]
+    fn accumulates_fixed_updates() {
+        let mut frame_loop = frame_loop(0.25, 1.0);
+        let mut steps = Vec::new();
+        let frame_time = frame_loop.advance(0.625, None, |dt| steps.push(dt));
+        assert_eq!(steps, [0.25, 0.25]);
+        assert_eq!(frame_time.updates, 2);
+        assert_eq!(frame_time.alpha, 0.5);
+
+        // The leftover 0.125 s carries over to the next frame
+        let frame_time = frame_loop.advance(0.375, None, |_| {});
+        assert_eq!(frame_time.updates, 2);
+        assert_eq!(frame_time.alpha, 0.0);
+        assert_eq!(frame_time.elapsed, 1.0);
+        assert_eq!(frame_time.frame, 2);
+
+        let frame_time = frame_loop.advance(0.125, None, |_| {});
+        assert_eq!(frame_time.updates, 0);
+        assert_eq!(frame_time.alpha, 0.5);
+    }
+
+    #[test]
+    fn clamps_long_frames() {
+        let mut frame_loop = frame_loop(0.25, 0.5);
+        let frame_time = frame_loop.advance(10.0, None, |_| {});
+        assert_eq!(frame_time.delta, 0.5);
+        assert_eq!(frame_time.updates, 2);
+        assert_eq!(frame_time.elapsed, 10.0);
+    }
+
+    #[test]
+    fn override_replaces_real_delta() {
+        let mut frame_loop = frame_loop(0.25, 0.5);
+        let frame_time = frame_loop.advance(0.0625, Some(0.75), |_| {});
+        assert_eq!(frame_time.delta, 0.75);
+        assert_eq!(frame_time.updates, 3);
+        assert_eq!(frame_time.alpha, 0.0);
+    }
+
+    #[test]
+    fn invalid_settings_fall_back() {
+        for invalid in [0.0, -1.0, f32::NAN] {
+            let mut frame_loop = FrameLoop::new(FrameLoopSettings {
+                fixed_timestep: invalid,
+                max_frame_time: invalid,
+                frame_cap: Some(invalid),
+            });
+            let defaults = FrameLoopSettings::default();
+            assert_eq!(
+                frame_loop.settings().fixed_timestep,
+                defaults.fixed_timestep
+            );
+            assert_eq!(
+                frame_loop.settings().max_frame_time,
+                defaults.max_frame_time
+            );
+            assert!(frame_loop.settings().frame_cap.is_none());
+
+            let frame_time = frame_loop.advance(0.1, None, |_| {});
+            assert!(frame_time.alpha.is_finite());
+        }
+    }
+}
@@ -0,0 +1,136 @@
+use std::rc::Rc;
+
+use glfw::{Context, fail_on_errors};
+
+use super::{
+    app::{App, Runner, RunnerSettings},
+    state::State,
+    window::{Key, Modifiers, MouseButton, RenderWindow, WindowEvent},
+};
+
+impl RenderWindow for glfw::PWindow {
+    fn size(&self) -> (u32, u32) {
+        let (width, height) = self.get_framebuffer_size();
+        (width as u32, height as u32)
+    }
+
+    fn scale_factor(&self) -> f32 {
+        self.get_content_scale().0
+    }
+}
+
+pub fn run(settings: RunnerSettings, app: impl App) {
+    let mut glfw = glfw::init(fail_on_errors!()).unwrap();
+    // Treat the requested size as logical pixels on every platform, macOS already does
+    glfw.window_hint(glfw::WindowHint::ScaleToMonitor(true));
+    let (mut window, events) = glfw
+        .create_window(
+            settings.width,
+            settings.height,
+            &settings.title,
+            glfw::WindowMode::Windowed,
+        )
+        .unwrap();
+
+    window.set_all_polling(true);
+    window.make_current();
+
+    // State keeps the window alive until the surface is dropped
+    let window = Rc::new(window);
+    let state = pollster::block_on(State::new(window.clone()));
+    let mut runner = Runner::new(&settings, app, state);
+
+    while !window.should_close() {
+        glfw.poll_events();
+        for (_, event) in glfw::flush_messages(&events) {
+            if let Some(event) = translate_event(&window, event) {
+                runner.handle_event(&event);
+            }
+        }
+        runner.frame();
+    }
+    runner.finish();
+}
+
+// `window` converts cursor positions from screen coordinates to pixels
+pub fn translate_event(window: &glfw::Window, event: glfw::WindowEvent) -> Option<WindowEvent> {
+    let event = match event {
+        // `Size` is in screen coordinates, the surface follows the framebuffer
+        glfw::WindowEvent::FramebufferSize(width, height) => WindowEvent::Resized {
+            width: width as u32,
+            height: height as u32,
+        },
+        glfw::WindowEvent::ContentScale(x_scale, _) => WindowEvent::ScaleFactorChanged(x_scale),
+        glfw::WindowEvent::Key(key, _, action, modifiers) => WindowEvent::Key {
+            key: translate_key(key),
+            pressed: action != glfw::Action::Release,
+            repeat: action == glfw::Action::Repeat,
+            modifiers: translate_modifiers(modifiers),
+        },
+        glfw::WindowEvent::Char(character) => WindowEvent::Char(character),
+        glfw::WindowEvent::CursorPos(x, y) => {
+            let (pixel_width, pixel_height) = window.get_framebuffer_size();
+            let (width, height) = window.get_size();
+            WindowEvent::CursorMoved {
+                x: x as f32 * pixel_width as f32 / width.max(1) as f32,
+                y: y as f32 * pixel_height as f32 / height.max(1) as f32,
+            }
+        }
+        glfw::WindowEvent::MouseButton(button, action, modifiers) => WindowEvent::MouseButton {
+            button: match button {
+                glfw::MouseButton::Button1 => MouseButton::Left,
+                glfw::MouseButton::Button2 => MouseButton::Right,
+                glfw::MouseButton::Button3 => MouseButton::Middle,
+                other => MouseButton::Other(other as u16),
+            },
+            pressed: action != glfw::Action::Release,
+            modifiers: translate_modifiers(modifiers),
+        },
+        glfw::WindowEvent::Scroll(x, y) => WindowEvent::Scroll {
+            x: x as f32,
+            y: y as f32,
+        },
+        glfw::WindowEvent::Focus(focused) => WindowEvent::Focused(focused),
+        glfw::WindowEvent::Close => WindowEvent::CloseRequested,
+        _ => return None,
+    };
+    Some(event)
+}
+
+fn translate_key(key: glfw::Key) -> Key {
+    match key {
+        glfw::Key::Escape => Key::Escape,
+        glfw::Key::Enter | glfw::Key::KpEnter => Key::Enter,
+        glfw::Key::Tab => Key::Tab,
+        glfw::Key::Backspace => Key::Backspace,
+        glfw::Key::Delete => Key::Delete,
+        glfw::Key::Insert => Key::Insert,
+        glfw::Key::Home => Key::Home,
+        glfw::Key::End => Key::End,
+        glfw::Key::PageUp => Key::PageUp,
+        glfw::Key::PageDown => Key::PageDown,
+        glfw::Key::Left => Key::Left,
+        glfw::Key::Right => Key::Right,
+        glfw::Key::Up => Key::Up,
+        glfw::Key::Down => Key::Down,
+        glfw::Key::Space => Key::Space,
+        _ => {
+            // glfw key codes of printable keys are their US layout ASCII code
+            let code = key as i32;
+            match code {
+                33..=96 => Key::Character((code as u8 as char).to_ascii_lowercase()),
+                290..=314 => Key::F((code - 289) as u8),
+                _ => Key::Unknown,
+            }
+        }
+    }
+}
+
+fn translate_modifiers(modifiers: glfw::Modifiers) -> Modifiers {
+    Modifiers {
+        shift: modifiers.contains(glfw::Modifiers::Shift),
+        control: modifiers.contains(glfw::Modifiers::Control),
+        alt: modifiers.contains(glfw::Modifiers::Alt),
+        super_key: modifiers.contains(glfw::Modifiers::Super),
+    }
+}
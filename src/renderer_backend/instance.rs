@@ -1,23 +1,49 @@
+use cgmath::{Matrix, SquareMatrix};
 use std::mem;
 use wgpu::VertexAttribute;
 
 pub struct Instance {
     pub position: cgmath::Vector3<f32>,
     pub rotation: cgmath::Quaternion<f32>,
+    pub scale: cgmath::Vector3<f32>,
 }
 
 #[repr(C)]
 #[derive(Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
 pub struct InstanceRaw {
     pub model: [[f32; 4]; 4],
+    // Inverse-transpose of the model's upper 3x3. Columns are padded to vec4
+    // so the struct keeps the same layout as WGSL's mat3x3<f32>.
+    pub normal: [[f32; 4]; 3],
+}
+
+impl InstanceRaw {
+    pub fn from_model(model: cgmath::Matrix4<f32>) -> Self {
+        let linear =
+            cgmath::Matrix3::from_cols(model.x.truncate(), model.y.truncate(), model.z.truncate());
+        // A degenerate (zero) scale has no inverse, fall back to identity
+        let normal = linear
+            .invert()
+            .unwrap_or(cgmath::Matrix3::identity())
+            .transpose();
+
+        InstanceRaw {
+            model: model.into(),
+            normal: [
+                normal.x.extend(0.0).into(),
+                normal.y.extend(0.0).into(),
+                normal.z.extend(0.0).into(),
+            ],
+        }
+    }
 }
 
 impl Instance {
     pub fn to_raw(&self) -> InstanceRaw {
-        let matrix = (cgmath::Matrix4::from_translation(self.position)
-            * cgmath::Matrix4::from(self.rotation))
-        .into();
-        InstanceRaw { model: matrix }
+        let matrix = cgmath::Matrix4::from_translation(self.position)
+            * cgmath::Matrix4::from(self.rotation)
+            * cgmath::Matrix4::from_nonuniform_scale(self.scale.x, self.scale.y, self.scale.z);
+        InstanceRaw::from_model(matrix)
     }
 
     pub fn desc() -> wgpu::VertexBufferLayout<'static> {
@@ -45,7 +71,176 @@ impl Instance {
                     offset: mem::size_of::<[f32; 12]>() as wgpu::BufferAddress,
                     shader_location: 8,
                 },
+                // Normal matrix columns
+                VertexAttribute {
+                    format: wgpu::VertexFormat::Float32x4,
+                    offset: mem::size_of::<[f32; 16]>() as wgpu::BufferAddress,
+                    shader_location: 9,
+                },
+                VertexAttribute {
+                    format: wgpu::VertexFormat::Float32x4,
+                    offset: mem::size_of::<[f32; 20]>() as wgpu::BufferAddress,
+                    shader_location: 10,
+                },
+                VertexAttribute {
+                    format: wgpu::VertexFormat::Float32x4,
+                    offset: mem::size_of::<[f32; 24]>() as wgpu::BufferAddress,
+                    shader_location: 11,
+                },
             ],
         }
     }
 }
+
+/// How per-instance data reaches the vertex shader, chosen when the pipeline is built.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum InstancingMode {
+    /// Instance data is a vertex buffer at slot 1, read through `Instance::desc`.
+    VertexBuffer,
+    /// Instance data is a storage buffer indexed with `@builtin(instance_index)`.
+    StorageBuffer,
+}
+
+/// GPU buffer holding per-instance data, usable both as an instanced vertex buffer
+/// and as a storage buffer. `T` can be any Pod struct as long as the shader declares
+/// a matching layout, so richer per-instance data is not limited to `InstanceRaw`.
+/// The storage views are only visible to the stages `mode` and the device support.
+pub struct InstanceStorage {
+    pub buffer: wgpu::Buffer,
+    pub capacity: wgpu::BufferAddress,
+    pub count: u32,
+    // Read-only view, visible to the vertex stage in `StorageBuffer` mode and to compute
+    pub bind_group_layout: wgpu::BindGroupLayout,
+    pub bind_group: wgpu::BindGroup,
+    // Read-write view so compute shaders can generate or animate instances
+    pub compute_bind_group_layout: wgpu::BindGroupLayout,
+    pub compute_bind_group: wgpu::BindGroup,
+}
+
+impl InstanceStorage {
+    pub fn new<T: bytemuck::Pod>(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        instances: &[T],
+        mode: InstancingMode,
+    ) -> Self {
+        let bind_group_layout = Self::create_layout(device, mode, true);
+        let compute_bind_group_layout = Self::create_layout(device, mode, false);
+        let capacity = (mem::size_of_val(instances) as wgpu::BufferAddress)
+            .max(mem::size_of::<InstanceRaw>() as wgpu::BufferAddress);
+        let buffer = Self::create_buffer(device, capacity);
+        let (bind_group, compute_bind_group) = Self::create_bind_groups(
+            device,
+            &buffer,
+            &bind_group_layout,
+            &compute_bind_group_layout,
+        );
+
+        let mut storage = Self {
+            buffer,
+            capacity,
+            count: 0,
+            bind_group_layout,
+            bind_group,
+            compute_bind_group_layout,
+            compute_bind_group,
+        };
+        storage.update(device, queue, instances);
+        storage
+    }
+
+    /// Uploads new instance data, growing the buffer (and its bind groups) if needed.
+    pub fn update<T: bytemuck::Pod>(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        instances: &[T],
+    ) {
+        let size = mem::size_of_val(instances) as wgpu::BufferAddress;
+        if size > self.capacity {
+            self.capacity = size.next_power_of_two();
+            self.buffer.destroy();
+            self.buffer = Self::create_buffer(device, self.capacity);
+            (self.bind_group, self.compute_bind_group) = Self::create_bind_groups(
+                device,
+                &self.buffer,
+                &self.bind_group_layout,
+                &self.compute_bind_group_layout,
+            );
+        }
+        if size > 0 {
+            queue.write_buffer(&self.buffer, 0, bytemuck::cast_slice(instances));
+        }
+        self.count = instances.len() as u32;
+    }
+
+    fn create_buffer(device: &wgpu::Device, size: wgpu::BufferAddress) -> wgpu::Buffer {
+        device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Instances Buffer"),
+            size,
+            usage: wgpu::BufferUsages::VERTEX
+                | wgpu::BufferUsages::STORAGE
+                | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        })
+    }
+
+    fn create_layout(
+        device: &wgpu::Device,
+        mode: InstancingMode,
+        read_only: bool,
+    ) -> wgpu::BindGroupLayout {
+        // Downlevel adapters may have no compute shaders or storage buffers at all
+        let limits = device.limits();
+        let mut visibility = wgpu::ShaderStages::NONE;
+        if limits.max_storage_buffers_per_shader_stage > 0
+            && limits.max_compute_workgroups_per_dimension > 0
+        {
+            visibility |= wgpu::ShaderStages::COMPUTE;
+        }
+        // Vertex shaders cannot write to storage buffers, so the writable view is compute only.
+        // `StorageBuffer` mode is only picked on adapters that can read them in the vertex stage.
+        if read_only && mode == InstancingMode::StorageBuffer {
+            visibility |= wgpu::ShaderStages::VERTEX;
+        }
+        device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: &[wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Storage { read_only },
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            }],
+            label: Some(if read_only {
+                "instance_storage_bind_group_layout"
+            } else {
+                "instance_storage_compute_bind_group_layout"
+            }),
+        })
+    }
+
+    fn create_bind_groups(
+        device: &wgpu::Device,
+        buffer: &wgpu::Buffer,
+        layout: &wgpu::BindGroupLayout,
+        compute_layout: &wgpu::BindGroupLayout,
+    ) -> (wgpu::BindGroup, wgpu::BindGroup) {
+        let create = |layout: &wgpu::BindGroupLayout, label: &str| {
+            device.create_bind_group(&wgpu::BindGroupDescriptor {
+                layout,
+                entries: &[wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: buffer.as_entire_binding(),
+                }],
+                label: Some(label),
+            })
+        };
+        (
+            create(layout, "instance_storage_bind_group"),
+            create(compute_layout, "instance_storage_compute_bind_group"),
+        )
+    }
+}
@@ -0,0 +1,391 @@
+use std::mem;
+
+use cgmath::InnerSpace;
+
+use super::{environment::Environment, shadow::ShadowResources};
+
+const LIGHT_DIRECTIONAL: u32 = 0;
+const LIGHT_POINT: u32 = 1;
+const LIGHT_SPOT: u32 = 2;
+
+/// Distance falloff `1 / (constant + linear * d + quadratic * d^2)`.
+#[derive(Copy, Clone, Debug)]
+pub struct Attenuation {
+    pub constant: f32,
+    pub linear: f32,
+    pub quadratic: f32,
+}
+
+impl From<Attenuation> for [f32; 3] {
+    fn from(attenuation: Attenuation) -> Self {
+        [
+            attenuation.constant,
+            attenuation.linear,
+            attenuation.quadratic,
+        ]
+    }
+}
+
+impl Default for Attenuation {
+    // Covers roughly 50 units
+    fn default() -> Self {
+        Self {
+            constant: 1.0,
+            linear: 0.09,
+            quadratic: 0.032,
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug)]
+pub enum Light {
+    Directional {
+        direction: cgmath::Vector3<f32>,
+        color: cgmath::Vector3<f32>,
+        intensity: f32,
+    },
+    Point {
+        position: cgmath::Vector3<f32>,
+        color: cgmath::Vector3<f32>,
+        intensity: f32,
+        attenuation: Attenuation,
+    },
+    // Full intensity inside `inner_cone`, fading out to zero at `outer_cone`
+    Spot {
+        position: cgmath::Vector3<f32>,
+        direction: cgmath::Vector3<f32>,
+        color: cgmath::Vector3<f32>,
+        intensity: f32,
+        attenuation: Attenuation,
+        inner_cone: cgmath::Deg<f32>,
+        outer_cone: cgmath::Deg<f32>,
+    },
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct LightRaw {
+    pub position: [f32; 3],
+    pub kind: u32,
+    pub direction: [f32; 3],
+    pub intensity: f32,
+    pub color: [f32; 3],
+    pub cos_inner_cone: f32,
+    pub attenuation: [f32; 3],
+    pub cos_outer_cone: f32,
+    // First layer in the shadow map array, -1 when the light casts no shadow
+    pub shadow_layer: i32,
+    pub _padding: [u32; 3],
+}
+
+impl Light {
+    pub fn to_raw(self) -> LightRaw {
+        let base = LightRaw {
+            attenuation: [1.0, 0.0, 0.0],
+            shadow_layer: -1,
+            ..bytemuck::Zeroable::zeroed()
+        };
+        match self {
+            Light::Directional {
+                direction,
+                color,
+                intensity,
+            } => LightRaw {
+                kind: LIGHT_DIRECTIONAL,
+                direction: direction.normalize().into(),
+                color: color.into(),
+                intensity,
+                ..base
+            },
+            Light::Point {
+                position,
+                color,
+                intensity,
+                attenuation,
+            } => LightRaw {
+                kind: LIGHT_POINT,
+                position: position.into(),
+                color: color.into(),
+                intensity,
+                attenuation: attenuation.into(),
+                ..base
+            },
+            Light::Spot {
+                position,
+                direction,
+                color,
+                intensity,
+                attenuation,
+                inner_cone,
+                outer_cone,
+            } => LightRaw {
+                kind: LIGHT_SPOT,
+                position: position.into(),
+                direction: direction.normalize().into(),
+                color: color.into(),
+                intensity,
+                attenuation: attenuation.into(),
+                cos_inner_cone: cgmath::Rad::from(inner_cone).0.cos(),
+                cos_outer_cone: cgmath::Rad::from(outer_cone).0.cos(),
+                ..base
+            },
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct LightingUniform {
+    pub ambient: [f32; 3],
+    pub light_count: u32,
+    // Blinn-Phong specular term shared by every surface
+    pub specular_strength: f32,
+    pub shininess: f32,
+    // Scales the image based ambient term, 0.0 leaves only the flat ambient
+    pub environment_intensity: f32,
+    // Mip of the prefiltered map matching roughness 1.0
+    pub max_reflection_lod: f32,
+}
+
+/// Lights live in a storage buffer so their number is only bounded by memory.
+/// The uniform carries the light count and the global shading parameters,
+/// the environment maps provide image based ambient lighting and the shadow
+/// resources are owned by `ShadowMaps`.
+pub struct LightBinding {
+    pub uniform_buffer: wgpu::Buffer,
+    pub light_buffer: wgpu::Buffer,
+    pub light_capacity: usize,
+    pub environment: Environment,
+    pub shadows: ShadowResources,
+    pub bind_group: wgpu::BindGroup,
+    pub bind_group_layout: wgpu::BindGroupLayout,
+}
+
+impl LightBinding {
+    pub fn new(
+        device: &wgpu::Device,
+        environment: &Environment,
+        shadows: &ShadowResources,
+    ) -> Self {
+        let cube_entry = |binding| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::FRAGMENT,
+            ty: wgpu::BindingType::Texture {
+                multisampled: false,
+                view_dimension: wgpu::TextureViewDimension::Cube,
+                sample_type: wgpu::TextureSampleType::Float { filterable: true },
+            },
+            count: None,
+        };
+
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: true },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                cube_entry(2),
+                cube_entry(3),
+                wgpu::BindGroupLayoutEntry {
+                    binding: 4,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        multisampled: false,
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 5,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 6,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        multisampled: false,
+                        view_dimension: wgpu::TextureViewDimension::D2Array,
+                        sample_type: wgpu::TextureSampleType::Depth,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 7,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Comparison),
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 8,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+            ],
+            label: Some("light_bind_group_layout"),
+        });
+
+        let uniform_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Lighting Uniform Buffer"),
+            size: mem::size_of::<LightingUniform>() as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let light_capacity = 4;
+        let light_buffer = Self::create_light_buffer(device, light_capacity);
+        let bind_group = Self::create_bind_group(
+            device,
+            &bind_group_layout,
+            &uniform_buffer,
+            &light_buffer,
+            environment,
+            shadows,
+        );
+
+        Self {
+            uniform_buffer,
+            light_buffer,
+            light_capacity,
+            environment: environment.clone(),
+            shadows: shadows.clone(),
+            bind_group,
+            bind_group_layout,
+        }
+    }
+
+    pub fn update(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        lights: &[Light],
+        shadow_layers: &[i32],
+        uniform: LightingUniform,
+    ) {
+        if lights.len() > self.light_capacity {
+            self.light_capacity = lights.len().next_power_of_two();
+            self.light_buffer.destroy();
+            self.light_buffer = Self::create_light_buffer(device, self.light_capacity);
+            self.bind_group = Self::create_bind_group(
+                device,
+                &self.bind_group_layout,
+                &self.uniform_buffer,
+                &self.light_buffer,
+                &self.environment,
+                &self.shadows,
+            );
+        }
+
+        let lights_raw = lights
+            .iter()
+            .enumerate()
+            .map(|(i, light)| LightRaw {
+                shadow_layer: shadow_layers.get(i).copied().unwrap_or(-1),
+                ..light.to_raw()
+            })
+            .collect::<Vec<_>>();
+        if !lights_raw.is_empty() {
+            queue.write_buffer(&self.light_buffer, 0, bytemuck::cast_slice(&lights_raw));
+        }
+        let uniform = LightingUniform {
+            light_count: lights.len() as u32,
+            ..uniform
+        };
+        queue.write_buffer(&self.uniform_buffer, 0, bytemuck::cast_slice(&[uniform]));
+    }
+
+    pub fn set_environment(&mut self, device: &wgpu::Device, environment: &Environment) {
+        self.environment = environment.clone();
+        self.bind_group = Self::create_bind_group(
+            device,
+            &self.bind_group_layout,
+            &self.uniform_buffer,
+            &self.light_buffer,
+            &self.environment,
+            &self.shadows,
+        );
+    }
+
+    fn create_light_buffer(device: &wgpu::Device, capacity: usize) -> wgpu::Buffer {
+        device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Light Buffer"),
+            size: (capacity * mem::size_of::<LightRaw>()) as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        })
+    }
+
+    fn create_bind_group(
+        device: &wgpu::Device,
+        layout: &wgpu::BindGroupLayout,
+        uniform_buffer: &wgpu::Buffer,
+        light_buffer: &wgpu::Buffer,
+        environment: &Environment,
+        shadows: &ShadowResources,
+    ) -> wgpu::BindGroup {
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: uniform_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: light_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: wgpu::BindingResource::TextureView(&environment.irradiance_view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: wgpu::BindingResource::TextureView(&environment.prefiltered_view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 4,
+                    resource: wgpu::BindingResource::TextureView(&environment.brdf_lut_view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 5,
+                    resource: wgpu::BindingResource::Sampler(&environment.sampler),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 6,
+                    resource: wgpu::BindingResource::TextureView(&shadows.array_view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 7,
+                    resource: wgpu::BindingResource::Sampler(&shadows.sampler),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 8,
+                    resource: shadows.uniform_buffer.as_entire_binding(),
+                },
+            ],
+            label: Some("light_bind_group"),
+        })
+    }
+}
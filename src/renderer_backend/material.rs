@@ -1,54 +1,182 @@
+use wgpu::util::DeviceExt;
+
+/// Scalar factors of the glTF metallic-roughness model. Each one multiplies the
+/// matching texture, so a material without textures is described by factors alone.
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct MaterialFactors {
+    pub base_color: [f32; 4],
+    pub emissive: [f32; 3],
+    pub metallic: f32,
+    pub roughness: f32,
+    pub normal_scale: f32,
+    pub occlusion_strength: f32,
+    // Set from the material's `AlphaMode`, zero disables the alpha test
+    pub alpha_cutoff: f32,
+}
+
+impl Default for MaterialFactors {
+    // glTF defaults
+    fn default() -> Self {
+        Self {
+            base_color: [1.0, 1.0, 1.0, 1.0],
+            emissive: [0.0, 0.0, 0.0],
+            metallic: 1.0,
+            roughness: 1.0,
+            normal_scale: 1.0,
+            occlusion_strength: 1.0,
+            alpha_cutoff: 0.0,
+        }
+    }
+}
+
+/// How the base color alpha is used, as in glTF.
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub enum AlphaMode {
+    // Alpha is ignored
+    #[default]
+    Opaque,
+    // Fragments below the cutoff are discarded, alpha-to-coverage smooths the
+    // edges when the scene is multisampled
+    Mask {
+        cutoff: f32,
+    },
+    // Blended over the opaque geometry in the transparent pass
+    Blend,
+}
+
+impl AlphaMode {
+    pub fn is_transparent(&self) -> bool {
+        *self == Self::Blend
+    }
+
+    fn cutoff(&self) -> f32 {
+        match self {
+            Self::Mask { cutoff } => *cutoff,
+            Self::Opaque | Self::Blend => 0.0,
+        }
+    }
+}
+
+/// Texture paths (relative to `src/`) of a material. Missing maps are replaced by
+/// 1x1 textures that leave the factors unchanged.
+#[derive(Default)]
+pub struct MaterialDescriptor<'a> {
+    pub base_color: Option<&'a str>,
+    // Roughness in G, metallic in B
+    pub metallic_roughness: Option<&'a str>,
+    pub normal: Option<&'a str>,
+    pub occlusion: Option<&'a str>,
+    pub emissive: Option<&'a str>,
+    pub factors: MaterialFactors,
+    pub alpha_mode: AlphaMode,
+}
+
 pub struct Material {
+    // Base color texture
     pub texture: wgpu::Texture,
     pub dimensions: (u32, u32),
+    pub factors: MaterialFactors,
+    pub alpha_mode: AlphaMode,
+    pub factors_buffer: wgpu::Buffer,
     pub bind_group: wgpu::BindGroup,
     pub bind_group_layout: wgpu::BindGroupLayout,
 }
 
 impl Material {
+    // Diffuse-only material, the texture is used as base color
     pub fn new(device: &wgpu::Device, queue: &wgpu::Queue, texture_path: &str) -> Self {
-        let full_path = format!("src/{}", texture_path);
-        let texture_bytes = std::fs::read(full_path).unwrap();
-        let image = image::load_from_memory(&texture_bytes).unwrap();
-        let rgba = image.to_rgba8();
-        let dimensions = rgba.dimensions();
-        let size = wgpu::Extent3d {
-            width: dimensions.0,
-            height: dimensions.1,
-            depth_or_array_layers: 1,
-        };
+        Self::from_descriptor(
+            device,
+            queue,
+            &MaterialDescriptor {
+                base_color: Some(texture_path),
+                factors: MaterialFactors {
+                    metallic: 0.0,
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+        )
+    }
 
-        let texture_descriptor = wgpu::TextureDescriptor {
-            label: Some(texture_path),
-            size: size,
-            mip_level_count: 1,
-            sample_count: 1,
-            dimension: wgpu::TextureDimension::D2,
-            format: wgpu::TextureFormat::Rgba8UnormSrgb,
-            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
-            view_formats: &[],
+    pub fn from_descriptor(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        descriptor: &MaterialDescriptor,
+    ) -> Self {
+        // Color maps are stored in sRGB, data maps are linear
+        let load = |path: Option<&str>, format, fallback: [u8; 4]| match path {
+            Some(path) => load_texture(device, queue, path, format),
+            None => create_solid_texture(device, queue, fallback, format),
         };
+        let srgb = wgpu::TextureFormat::Rgba8UnormSrgb;
+        let linear = wgpu::TextureFormat::Rgba8Unorm;
+        let (texture, dimensions) = load(descriptor.base_color, srgb, [255, 255, 255, 255]);
+        let (metallic_roughness, _) =
+            load(descriptor.metallic_roughness, linear, [255, 255, 255, 255]);
+        // Flat tangent-space normal
+        let (normal, _) = load(descriptor.normal, linear, [128, 128, 255, 255]);
+        let (occlusion, _) = load(descriptor.occlusion, linear, [255, 255, 255, 255]);
+        let (emissive, _) = load(descriptor.emissive, srgb, [255, 255, 255, 255]);
 
-        let texture = device.create_texture(&texture_descriptor);
+        Self::from_textures(
+            device,
+            [texture, metallic_roughness, normal, occlusion, emissive],
+            dimensions,
+            descriptor.factors,
+            descriptor.alpha_mode,
+        )
+    }
 
-        // Add to the render Queue
-        queue.write_texture(
-            wgpu::TexelCopyTextureInfo {
-                texture: &texture,
-                mip_level: 0,
-                origin: wgpu::Origin3d::ZERO,
-                aspect: wgpu::TextureAspect::All,
-            },
-            &rgba,
-            wgpu::TexelCopyBufferLayout {
-                offset: 0,
-                bytes_per_row: Some(4 * dimensions.0),
-                rows_per_image: Some(dimensions.1),
-            },
-            size,
+    /// Wraps an existing base color texture, e.g. a glyph atlas that is written
+    /// after the material is created. The other maps are left neutral.
+    pub fn from_texture(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        texture: wgpu::Texture,
+        factors: MaterialFactors,
+        alpha_mode: AlphaMode,
+    ) -> Self {
+        let linear = wgpu::TextureFormat::Rgba8Unorm;
+        let dimensions = (texture.width(), texture.height());
+        let (metallic_roughness, _) =
+            create_solid_texture(device, queue, [255, 255, 255, 255], linear);
+        let (normal, _) = create_solid_texture(device, queue, [128, 128, 255, 255], linear);
+        let (occlusion, _) = create_solid_texture(device, queue, [255, 255, 255, 255], linear);
+        let (emissive, _) = create_solid_texture(
+            device,
+            queue,
+            [255, 255, 255, 255],
+            wgpu::TextureFormat::Rgba8UnormSrgb,
         );
+        Self::from_textures(
+            device,
+            [texture, metallic_roughness, normal, occlusion, emissive],
+            dimensions,
+            factors,
+            alpha_mode,
+        )
+    }
+
+    // Base color, metallic-roughness, normal, occlusion and emissive maps
+    fn from_textures(
+        device: &wgpu::Device,
+        textures: [wgpu::Texture; 5],
+        dimensions: (u32, u32),
+        factors: MaterialFactors,
+        alpha_mode: AlphaMode,
+    ) -> Self {
+        let factors = MaterialFactors {
+            alpha_cutoff: alpha_mode.cutoff(),
+            ..factors
+        };
+        let factors_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Material Factors Buffer"),
+            contents: bytemuck::cast_slice(&[factors]),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
 
-        let texture_view = texture.create_view(&wgpu::TextureViewDescriptor::default());
         let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
             address_mode_u: wgpu::AddressMode::ClampToEdge,
             address_mode_v: wgpu::AddressMode::ClampToEdge,
@@ -59,41 +187,43 @@ impl Material {
             ..Default::default()
         });
 
-        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
-            entries: &[
-                wgpu::BindGroupLayoutEntry {
-                    binding: 0,
-                    visibility: wgpu::ShaderStages::FRAGMENT,
-                    ty: wgpu::BindingType::Texture {
-                        multisampled: false,
-                        view_dimension: wgpu::TextureViewDimension::D2,
-                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
-                    },
-                    count: None,
-                },
-                wgpu::BindGroupLayoutEntry {
-                    binding: 1,
-                    visibility: wgpu::ShaderStages::FRAGMENT,
-                    // This should match the filterable field of the
-                    // corresponding Texture entry above.
-                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
-                    count: None,
-                },
-            ],
-            label: Some("texture_bind_group_layout"),
-        });
+        let bind_group_layout = Self::create_bind_group_layout(device);
 
+        let views = textures
+            .each_ref()
+            .map(|texture| texture.create_view(&wgpu::TextureViewDescriptor::default()));
+        let [texture, ..] = textures;
         let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
             layout: &bind_group_layout,
             entries: &[
                 wgpu::BindGroupEntry {
                     binding: 0,
-                    resource: wgpu::BindingResource::TextureView(&texture_view),
+                    resource: wgpu::BindingResource::TextureView(&views[0]),
                 },
                 wgpu::BindGroupEntry {
                     binding: 1,
                     resource: wgpu::BindingResource::Sampler(&sampler),
                 },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: wgpu::BindingResource::TextureView(&views[1]),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: wgpu::BindingResource::TextureView(&views[2]),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 4,
+                    resource: wgpu::BindingResource::TextureView(&views[3]),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 5,
+                    resource: wgpu::BindingResource::TextureView(&views[4]),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 6,
+                    resource: factors_buffer.as_entire_binding(),
+                },
             ],
             label: Some("diffuse_bind_group"),
         });
@@ -101,8 +231,142 @@ impl Material {
         Self {
             texture,
             dimensions,
+            factors,
+            alpha_mode,
+            factors_buffer,
             bind_group,
             bind_group_layout,
         }
     }
+
+    // Upload `factors` after they have been modified
+    pub fn update_factors(&self, queue: &wgpu::Queue) {
+        let factors = MaterialFactors {
+            alpha_cutoff: self.alpha_mode.cutoff(),
+            ..self.factors
+        };
+        queue.write_buffer(&self.factors_buffer, 0, bytemuck::cast_slice(&[factors]));
+    }
+
+    pub fn create_bind_group_layout(device: &wgpu::Device) -> wgpu::BindGroupLayout {
+        let texture_entry = |binding| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::FRAGMENT,
+            ty: wgpu::BindingType::Texture {
+                multisampled: false,
+                view_dimension: wgpu::TextureViewDimension::D2,
+                sample_type: wgpu::TextureSampleType::Float { filterable: true },
+            },
+            count: None,
+        };
+
+        device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: &[
+                texture_entry(0),
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    // This should match the filterable field of the
+                    // corresponding Texture entry above.
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                    count: None,
+                },
+                texture_entry(2),
+                texture_entry(3),
+                texture_entry(4),
+                texture_entry(5),
+                wgpu::BindGroupLayoutEntry {
+                    binding: 6,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+            ],
+            label: Some("texture_bind_group_layout"),
+        })
+    }
+}
+
+pub fn load_texture(
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    texture_path: &str,
+    format: wgpu::TextureFormat,
+) -> (wgpu::Texture, (u32, u32)) {
+    let full_path = format!("src/{}", texture_path);
+    let texture_bytes = std::fs::read(full_path).unwrap();
+    let image = image::load_from_memory(&texture_bytes).unwrap();
+    let rgba = image.to_rgba8();
+    let dimensions = rgba.dimensions();
+    let texture = create_texture(device, queue, Some(texture_path), &rgba, dimensions, format);
+    (texture, dimensions)
+}
+
+// 1x1 texture used in place of a missing map
+pub fn create_solid_texture(
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    color: [u8; 4],
+    format: wgpu::TextureFormat,
+) -> (wgpu::Texture, (u32, u32)) {
+    let texture = create_texture(
+        device,
+        queue,
+        Some("fallback texture"),
+        &color,
+        (1, 1),
+        format,
+    );
+    (texture, (1, 1))
+}
+
+fn create_texture(
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    label: Option<&str>,
+    rgba: &[u8],
+    dimensions: (u32, u32),
+    format: wgpu::TextureFormat,
+) -> wgpu::Texture {
+    let size = wgpu::Extent3d {
+        width: dimensions.0,
+        height: dimensions.1,
+        depth_or_array_layers: 1,
+    };
+
+    let texture_descriptor = wgpu::TextureDescriptor {
+        label,
+        size,
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D2,
+        format,
+        usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+        view_formats: &[],
+    };
+
+    let texture = device.create_texture(&texture_descriptor);
+
+    // Add to the render Queue
+    queue.write_texture(
+        wgpu::TexelCopyTextureInfo {
+            texture: &texture,
+            mip_level: 0,
+            origin: wgpu::Origin3d::ZERO,
+            aspect: wgpu::TextureAspect::All,
+        },
+        rgba,
+        wgpu::TexelCopyBufferLayout {
+            offset: 0,
+            bytes_per_row: Some(4 * dimensions.0),
+            rows_per_image: Some(dimensions.1),
+        },
+        size,
+    );
+
+    texture
 }
@@ -1,15 +1,31 @@
-use glam::{Vec2, Vec3};
+use std::collections::HashMap;
+
+use glam::{Vec2, Vec3, Vec4};
 use wgpu::util::DeviceExt;
 
 #[repr(C)]
+#[derive(Copy, Clone, Debug)]
 pub struct Vertex {
-    position: Vec3,
-    texture_coords: Vec2,
+    pub position: Vec3,
+    pub texture_coords: Vec2,
+    pub normal: Vec3,
+    // xyz points along increasing u, w is the bitangent sign
+    pub tangent: Vec4,
 }
 impl Vertex {
+    pub fn new(position: Vec3, texture_coords: Vec2, normal: Vec3) -> Self {
+        Self {
+            position,
+            texture_coords,
+            normal,
+            tangent: Vec4::ZERO,
+        }
+    }
+
     pub fn desc() -> wgpu::VertexBufferLayout<'static> {
-        const ATTRIBUTES: [wgpu::VertexAttribute; 2] =
-            wgpu::vertex_attr_array![0 => Float32x3, 1 => Float32x2];
+        const ATTRIBUTES: [wgpu::VertexAttribute; 4] = wgpu::vertex_attr_array![
+            0 => Float32x3, 1 => Float32x2, 2 => Float32x3, 3 => Float32x4
+        ];
         wgpu::VertexBufferLayout {
             array_stride: std::mem::size_of::<Vertex>() as u64,
             step_mode: wgpu::VertexStepMode::Vertex,
@@ -22,37 +38,44 @@ pub struct Mesh {
     pub vertex_buffer: wgpu::Buffer,
     pub index_buffer: wgpu::Buffer,
     pub num_indices: usize,
+    // Radius of the bounding sphere around the local origin, used for culling
+    pub bounding_radius: f32,
 }
 
-unsafe fn any_as_u8_slice<T: Sized>(p: &T) -> &[u8] {
-    unsafe {
-        ::core::slice::from_raw_parts((p as *const T) as *const u8, ::core::mem::size_of::<T>())
-    }
+unsafe fn slice_as_u8_slice<T: Sized>(p: &[T]) -> &[u8] {
+    unsafe { ::core::slice::from_raw_parts(p.as_ptr() as *const u8, ::core::mem::size_of_val(p)) }
 }
 pub fn create_mesh(device: &wgpu::Device, size: &f32) -> Mesh {
     // Use mesh to avoid Vertexes duplicates (quad has one adjacent side- 2 vertices) with the 2 triangles composing it
-    let vertices: [Vertex; 4] = [
-        Vertex {
-            position: Vec3::new(-*size, -*size, 0.0),
-            texture_coords: Vec2::new(0.0, 1.0), // Bottom-left (UV coordinates are flipped vertically)
-        },
-        Vertex {
-            position: Vec3::new(*size, -*size, 0.0),
-            texture_coords: Vec2::new(1.0, 1.0), // Bottom-right
-        },
-        Vertex {
-            position: Vec3::new(-*size, *size, 0.0),
-            texture_coords: Vec2::new(0.0, 0.0), // Top-left
-        },
-        Vertex {
-            position: Vec3::new(*size, *size, 0.0),
-            texture_coords: Vec2::new(1.0, 0.0), // Top-right
-        },
+    let vertices = vec![
+        // Bottom-left (UV coordinates are flipped vertically)
+        Vertex::new(Vec3::new(-*size, -*size, 0.0), Vec2::new(0.0, 1.0), Vec3::Z),
+        // Bottom-right
+        Vertex::new(Vec3::new(*size, -*size, 0.0), Vec2::new(1.0, 1.0), Vec3::Z),
+        // Top-left
+        Vertex::new(Vec3::new(-*size, *size, 0.0), Vec2::new(0.0, 0.0), Vec3::Z),
+        // Top-right
+        Vertex::new(Vec3::new(*size, *size, 0.0), Vec2::new(1.0, 0.0), Vec3::Z),
     ];
     let indices = [0, 1, 2, 2, 1, 3]; // drawing order of each index (counter-clockwise)
+    build_mesh(device, vertices, &indices)
+}
+
+/// Uploads indexed triangles. Tangents are generated when no vertex supplies
+/// one, so importers can pass positions, UVs and normals only.
+pub fn build_mesh(device: &wgpu::Device, vertices: Vec<Vertex>, indices: &[u32]) -> Mesh {
+    let (vertices, indices) = if vertices.iter().all(|vertex| vertex.tangent == Vec4::ZERO) {
+        generate_tangents(&vertices, indices)
+    } else {
+        (vertices, indices.to_vec())
+    };
     let num_indices = indices.len();
+    let bounding_radius = vertices
+        .iter()
+        .map(|vertex| vertex.position.length())
+        .fold(0.0, f32::max);
 
-    let mut content_bytes = unsafe { any_as_u8_slice(&vertices) };
+    let mut content_bytes = unsafe { slice_as_u8_slice(&vertices) };
     let mut buffer_descriptor = wgpu::util::BufferInitDescriptor {
         label: Some("mesh vertex buffer descriptor"),
         contents: content_bytes,
@@ -60,7 +83,7 @@ pub fn create_mesh(device: &wgpu::Device, size: &f32) -> Mesh {
     };
     let vertex_buffer = device.create_buffer_init(&buffer_descriptor);
 
-    content_bytes = unsafe { any_as_u8_slice(&indices) };
+    content_bytes = unsafe { slice_as_u8_slice(&indices) };
     buffer_descriptor = wgpu::util::BufferInitDescriptor {
         label: Some("mesh index buffer descriptor"),
         contents: content_bytes,
@@ -68,9 +91,157 @@ pub fn create_mesh(device: &wgpu::Device, size: &f32) -> Mesh {
     };
     let index_buffer = device.create_buffer_init(&buffer_descriptor);
 
-    return Mesh {
+    Mesh {
         vertex_buffer,
         index_buffer,
         num_indices,
+        bounding_radius,
+    }
+}
+
+// Triangle corners handed to MikkTSpace, which writes one tangent per corner
+struct TangentGeometry<'a> {
+    vertices: &'a [Vertex],
+    indices: &'a [u32],
+    corners: Vec<Vertex>,
+}
+
+impl TangentGeometry<'_> {
+    fn vertex(&self, face: usize, vert: usize) -> &Vertex {
+        &self.vertices[self.indices[face * 3 + vert] as usize]
+    }
+}
+
+impl bevy_mikktspace::Geometry for TangentGeometry<'_> {
+    fn num_faces(&self) -> usize {
+        self.indices.len() / 3
+    }
+
+    fn num_vertices_of_face(&self, _face: usize) -> usize {
+        3
+    }
+
+    fn position(&self, face: usize, vert: usize) -> [f32; 3] {
+        self.vertex(face, vert).position.to_array()
+    }
+
+    fn normal(&self, face: usize, vert: usize) -> [f32; 3] {
+        self.vertex(face, vert).normal.to_array()
+    }
+
+    fn tex_coord(&self, face: usize, vert: usize) -> [f32; 2] {
+        self.vertex(face, vert).texture_coords.to_array()
+    }
+
+    fn set_tangent_encoded(&mut self, tangent: [f32; 4], face: usize, vert: usize) {
+        self.corners[face * 3 + vert].tangent = Vec4::from_array(tangent);
+    }
+}
+
+// Bit pattern of every attribute, equal only for identical vertices
+fn vertex_key(vertex: &Vertex) -> [u32; 12] {
+    let mut key = [0; 12];
+    let attributes = vertex
+        .position
+        .to_array()
+        .into_iter()
+        .chain(vertex.texture_coords.to_array())
+        .chain(vertex.normal.to_array())
+        .chain(vertex.tangent.to_array());
+    for (bits, attribute) in key.iter_mut().zip(attributes) {
+        *bits = attribute.to_bits();
+    }
+    key
+}
+
+/// MikkTSpace tangents, with w storing the handedness so the shader rebuilds the
+/// bitangent as `cross(normal, tangent.xyz) * tangent.w`. Tangents are generated
+/// per triangle corner, then identical corners are welded back into one vertex:
+/// a vertex shared across a UV seam or mirrored UVs is split into one copy per
+/// tangent, so the result matches normal maps baked against MikkTSpace.
+pub fn generate_tangents(vertices: &[Vertex], indices: &[u32]) -> (Vec<Vertex>, Vec<u32>) {
+    // Corners MikkTSpace skips, such as degenerate triangles, get any perpendicular axis
+    let corners = indices
+        .iter()
+        .map(|&index| {
+            let mut vertex = vertices[index as usize];
+            let normal = vertex.normal.normalize_or_zero();
+            vertex.tangent = normal.any_orthonormal_vector().extend(1.0);
+            vertex
+        })
+        .collect();
+    let mut geometry = TangentGeometry {
+        vertices,
+        indices,
+        corners,
     };
+    if !bevy_mikktspace::generate_tangents(&mut geometry) {
+        log::warn!(
+            "MikkTSpace could not generate tangents for {} triangles",
+            indices.len() / 3
+        );
+    }
+
+    let mut welded = Vec::new();
+    let mut welded_indices = HashMap::new();
+    let indices = geometry
+        .corners
+        .into_iter()
+        .map(|corner| {
+            *welded_indices
+                .entry(vertex_key(&corner))
+                .or_insert_with(|| {
+                    welded.push(corner);
+                    welded.len() as u32 - 1
+                })
+        })
+        .collect();
+    (welded, indices)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Two halves sharing the middle edge, with u mirrored there: 0 at both
+    // sides, 1 in the middle. The middle vertices need a tangent per half.
+    #[test]
+    fn mirrored_uvs_split_tangents() {
+        let vertices = [-1.0, 0.0, 1.0]
+            .into_iter()
+            .flat_map(|x: f32| {
+                [0.0, 1.0].map(|y| {
+                    Vertex::new(Vec3::new(x, y, 0.0), Vec2::new(1.0 - x.abs(), y), Vec3::Z)
+                })
+            })
+            .collect::<Vec<_>>();
+        // Counter-clockwise seen from +z
+        let indices = [0, 2, 1, 1, 2, 3, 2, 4, 3, 3, 4, 5];
+
+        let (vertices, indices) = generate_tangents(&vertices, &indices);
+        assert_eq!(vertices.len(), 8);
+        for triangle in indices.chunks_exact(3) {
+            let center = triangle
+                .iter()
+                .map(|&index| vertices[index as usize].position)
+                .sum::<Vec3>()
+                / 3.0;
+            // u grows along +x on the left half and along -x on the right half,
+            // v grows along +y on both, so the right half flips the handedness
+            let expected = if center.x < 0.0 {
+                Vec4::new(1.0, 0.0, 0.0, 1.0)
+            } else {
+                Vec4::new(-1.0, 0.0, 0.0, -1.0)
+            };
+            for &index in triangle {
+                let tangent = vertices[index as usize].tangent;
+                assert!(
+                    tangent.abs_diff_eq(expected, 1e-5),
+                    "{} != {}",
+                    tangent,
+                    expected
+                );
+            }
+        }
+    }
 }
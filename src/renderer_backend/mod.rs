@@ -1,5 +1,31 @@
+pub mod app;
+pub mod camera;
+pub mod capture;
+pub mod culling;
+pub mod debug_draw;
+pub mod deferred;
+pub mod depth;
+pub mod environment;
+pub mod frame_loop;
+#[cfg(feature = "glfw")]
+pub mod glfw_window;
 pub mod instance;
+pub mod light;
 pub mod material;
 pub mod mesh_builder;
 pub mod pipeline_builder;
+pub mod post_process;
+pub mod quad;
+pub mod render_graph;
+pub mod render_queue;
+pub mod scene;
+pub mod shadow;
+pub mod skybox;
+pub mod sprite;
 pub mod state;
+pub mod text;
+pub mod transparency;
+pub mod ui;
+pub mod window;
+#[cfg(feature = "winit")]
+pub mod winit_window;
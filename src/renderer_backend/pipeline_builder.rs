@@ -1,29 +1,131 @@
+use std::path::PathBuf;
+
+// Shaders compiled into the crate, by their path under `src/`
+const BUILTIN_SHADERS: [(&str, &str); 14] = [
+    ("shaders/cull.wgsl", include_str!("../shaders/cull.wgsl")),
+    (
+        "shaders/debug_draw.wgsl",
+        include_str!("../shaders/debug_draw.wgsl"),
+    ),
+    (
+        "shaders/ibl_brdf.wgsl",
+        include_str!("../shaders/ibl_brdf.wgsl"),
+    ),
+    (
+        "shaders/ibl_common.wgsl",
+        include_str!("../shaders/ibl_common.wgsl"),
+    ),
+    (
+        "shaders/ibl_equirect.wgsl",
+        include_str!("../shaders/ibl_equirect.wgsl"),
+    ),
+    (
+        "shaders/ibl_filter.wgsl",
+        include_str!("../shaders/ibl_filter.wgsl"),
+    ),
+    ("shaders/oit.wgsl", include_str!("../shaders/oit.wgsl")),
+    (
+        "shaders/post_process.wgsl",
+        include_str!("../shaders/post_process.wgsl"),
+    ),
+    (
+        "shaders/shader.wgsl",
+        include_str!("../shaders/shader.wgsl"),
+    ),
+    (
+        "shaders/shadow.wgsl",
+        include_str!("../shaders/shadow.wgsl"),
+    ),
+    (
+        "shaders/skybox.wgsl",
+        include_str!("../shaders/skybox.wgsl"),
+    ),
+    (
+        "shaders/sprite.wgsl",
+        include_str!("../shaders/sprite.wgsl"),
+    ),
+    ("shaders/text.wgsl", include_str!("../shaders/text.wgsl")),
+    ("shaders/ui.wgsl", include_str!("../shaders/ui.wgsl")),
+];
+
+/// Source of a built-in shader, e.g. `builtin_shader("shaders/shader.wgsl")`.
+pub fn builtin_shader(name: &str) -> &'static str {
+    BUILTIN_SHADERS
+        .iter()
+        .find(|(builtin, _)| *builtin == name)
+        .map(|(_, source)| *source)
+        .unwrap_or_else(|| panic!("No built-in shader {}", name))
+}
+
+/// Where a pipeline's WGSL comes from. Built-in shaders are embedded in the
+/// binary; a file is only read when the application passes one.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum ShaderSource {
+    // Name in the built-in table
+    Builtin(&'static str),
+    // Read when the pipeline is built, relative to the working directory
+    File(PathBuf),
+}
+
+impl ShaderSource {
+    pub fn load(&self) -> String {
+        match self {
+            Self::Builtin(name) => builtin_shader(name).to_string(),
+            Self::File(path) => std::fs::read_to_string(path).unwrap_or_else(|error| {
+                panic!("Failed to read shader {}: {}", path.display(), error)
+            }),
+        }
+    }
+}
+
 pub struct PipelineBuilder<'a> {
     pub device: &'a wgpu::Device,
-    pub shader_path: String,
+    pub shader: ShaderSource,
     pub vertex_entry: String,
     pub fragment_entry: String,
     pub pixel_format: wgpu::TextureFormat,
+    // Multiple render targets, replaces `pixel_format` when not empty
+    pub color_formats: Vec<wgpu::TextureFormat>,
     pub bind_group_layouts: Vec<&'a wgpu::BindGroupLayout>,
     pub vertex_buffer_layouts: Vec<wgpu::VertexBufferLayout<'a>>,
+    pub depth_stencil: Option<wgpu::DepthStencilState>,
+    pub topology: wgpu::PrimitiveTopology,
+    pub cull_mode: Option<wgpu::Face>,
+    // No fragment stage or color target, for depth-only passes
+    pub depth_only: bool,
+    // One per color target, or a single state shared by all of them
+    pub blend_states: Vec<wgpu::BlendState>,
+    pub sample_count: u32,
+    pub alpha_to_coverage: bool,
+    // Values of `override` declarations in the fragment stage
+    pub constants: Vec<(String, f64)>,
 }
 
 impl<'a> PipelineBuilder<'a> {
     pub fn new(
         device: &'a wgpu::Device,
-        shader_path: &str,
+        shader: ShaderSource,
         vertex_entry: &str,
         fragment_entry: &str,
         pixel_format: wgpu::TextureFormat,
     ) -> Self {
         Self {
-            device: device,
-            shader_path: shader_path.to_string(),
+            device,
+            shader,
             vertex_entry: vertex_entry.to_string(),
             fragment_entry: fragment_entry.to_string(),
-            pixel_format: pixel_format,
+            pixel_format,
+            color_formats: Vec::new(),
             bind_group_layouts: Vec::new(),
             vertex_buffer_layouts: Vec::new(),
+            depth_stencil: None,
+            topology: wgpu::PrimitiveTopology::TriangleList,
+            cull_mode: Some(wgpu::Face::Back),
+            depth_only: false,
+            blend_states: vec![wgpu::BlendState::REPLACE],
+            sample_count: 1,
+            alpha_to_coverage: false,
+            constants: Vec::new(),
         }
     }
 
@@ -35,6 +137,56 @@ impl<'a> PipelineBuilder<'a> {
         self.vertex_buffer_layouts.push(layout);
     }
 
+    pub fn set_color_formats(&mut self, formats: &[wgpu::TextureFormat]) {
+        self.color_formats = formats.to_vec();
+    }
+
+    pub fn set_depth_stencil(
+        &mut self,
+        format: wgpu::TextureFormat,
+        depth_write_enabled: bool,
+        depth_compare: wgpu::CompareFunction,
+    ) {
+        self.depth_stencil = Some(wgpu::DepthStencilState {
+            format,
+            depth_write_enabled,
+            depth_compare,
+            stencil: wgpu::StencilState::default(),
+            bias: wgpu::DepthBiasState::default(),
+        });
+    }
+
+    pub fn set_depth_bias(&mut self, bias: wgpu::DepthBiasState) {
+        if let Some(depth_stencil) = &mut self.depth_stencil {
+            depth_stencil.bias = bias;
+        }
+    }
+
+    pub fn set_depth_only(&mut self) {
+        self.depth_only = true;
+    }
+
+    pub fn set_topology(&mut self, topology: wgpu::PrimitiveTopology) {
+        self.topology = topology;
+    }
+
+    pub fn set_cull_mode(&mut self, cull_mode: Option<wgpu::Face>) {
+        self.cull_mode = cull_mode;
+    }
+
+    pub fn set_blend_states(&mut self, blend_states: &[wgpu::BlendState]) {
+        self.blend_states = blend_states.to_vec();
+    }
+
+    pub fn set_multisample(&mut self, sample_count: u32, alpha_to_coverage: bool) {
+        self.sample_count = sample_count;
+        self.alpha_to_coverage = alpha_to_coverage;
+    }
+
+    pub fn set_constant(&mut self, name: &str, value: f64) {
+        self.constants.push((name.to_string(), value));
+    }
+
     pub fn build_pipeline(&mut self, label: &str) -> wgpu::RenderPipeline {
         let pipeline_layout_descriptor = wgpu::PipelineLayoutDescriptor {
             label: Some("pipeline layout descriptor"),
@@ -45,22 +197,39 @@ impl<'a> PipelineBuilder<'a> {
             .device
             .create_pipeline_layout(&pipeline_layout_descriptor);
 
-        let mut filepath = std::env::current_dir().unwrap();
-        filepath.push("src/");
-        filepath.push(self.shader_path.as_str());
-        let filepath: String = filepath.into_os_string().into_string().unwrap();
-        let source_code = std::fs::read_to_string(filepath).expect("No shader found in that path");
+        let source_code = self.shader.load();
         let shader_module_descriptor = wgpu::ShaderModuleDescriptor {
             label: Some("Shader module descriptor"),
             source: wgpu::ShaderSource::Wgsl(source_code.into()),
         };
         let shader_module = self.device.create_shader_module(shader_module_descriptor);
 
-        let render_targets = [Some(wgpu::ColorTargetState {
-            format: self.pixel_format,
-            blend: Some(wgpu::BlendState::REPLACE),
-            write_mask: wgpu::ColorWrites::ALL,
-        })];
+        let color_formats = if self.color_formats.is_empty() {
+            vec![self.pixel_format]
+        } else {
+            self.color_formats.clone()
+        };
+        let render_targets = color_formats
+            .into_iter()
+            .enumerate()
+            .map(|(index, format)| {
+                let blend = self
+                    .blend_states
+                    .get(index)
+                    .or(self.blend_states.first())
+                    .copied();
+                Some(wgpu::ColorTargetState {
+                    format,
+                    blend,
+                    write_mask: wgpu::ColorWrites::ALL,
+                })
+            })
+            .collect::<Vec<_>>();
+        let constants = self
+            .constants
+            .iter()
+            .map(|(name, value)| (name.as_str(), *value))
+            .collect::<Vec<_>>();
 
         let render_pipeline_descriptor = wgpu::RenderPipelineDescriptor {
             label: Some(label),
@@ -71,34 +240,51 @@ impl<'a> PipelineBuilder<'a> {
                 compilation_options: wgpu::PipelineCompilationOptions::default(),
                 buffers: &self.vertex_buffer_layouts,
             },
-            fragment: Some(wgpu::FragmentState {
+            fragment: (!self.depth_only).then(|| wgpu::FragmentState {
                 module: &shader_module,
                 entry_point: Some(&self.fragment_entry),
-                compilation_options: wgpu::PipelineCompilationOptions::default(),
+                compilation_options: wgpu::PipelineCompilationOptions {
+                    constants: &constants,
+                    ..Default::default()
+                },
                 targets: &render_targets,
             }),
             primitive: wgpu::PrimitiveState {
-                topology: wgpu::PrimitiveTopology::TriangleList,
+                topology: self.topology,
                 strip_index_format: None,
                 front_face: wgpu::FrontFace::Ccw,
-                cull_mode: Some(wgpu::Face::Back),
+                cull_mode: self.cull_mode,
                 unclipped_depth: false,
                 polygon_mode: wgpu::PolygonMode::Fill,
                 conservative: false,
             },
-            depth_stencil: None,
+            depth_stencil: self.depth_stencil.clone(),
             multisample: wgpu::MultisampleState {
-                count: 1,
+                count: self.sample_count,
                 mask: !0,
-                alpha_to_coverage_enabled: false,
+                alpha_to_coverage_enabled: self.alpha_to_coverage,
             },
             multiview: None,
             cache: None,
         };
-        let render_pipeline = self
-            .device
-            .create_render_pipeline(&render_pipeline_descriptor);
+        self.device
+            .create_render_pipeline(&render_pipeline_descriptor)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-        render_pipeline
+    #[test]
+    fn builtin_table_lists_every_shader() {
+        let directory = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("src/shaders");
+        let mut files = std::fs::read_dir(directory)
+            .unwrap()
+            .map(|entry| format!("shaders/{}", entry.unwrap().file_name().to_str().unwrap()))
+            .collect::<Vec<_>>();
+        files.sort();
+        let builtins = BUILTIN_SHADERS.map(|(name, _)| name.to_string());
+        assert_eq!(files, builtins);
     }
 }
@@ -0,0 +1,514 @@
+use std::{
+    cell::{Cell, RefCell},
+    mem,
+};
+
+use super::pipeline_builder;
+
+// Scene color is rendered in linear HDR and only encoded for display at the end
+pub const HDR_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba16Float;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum ToneMapping {
+    None,
+    Reinhard,
+    Aces,
+    // Hable's Uncharted 2 curve
+    Filmic,
+}
+
+impl ToneMapping {
+    fn to_raw(self) -> u32 {
+        match self {
+            ToneMapping::None => 0,
+            ToneMapping::Reinhard => 1,
+            ToneMapping::Aces => 2,
+            ToneMapping::Filmic => 3,
+        }
+    }
+}
+
+/// One step of the post-process chain, run in order on the HDR scene color.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum PostEffect {
+    // Adds a blurred copy of everything brighter than `threshold`
+    Bloom { threshold: f32, intensity: f32 },
+    // Scales by `PostProcessor::exposure` before mapping to 0..1
+    ToneMapping(ToneMapping),
+    Vignette { strength: f32, radius: f32 },
+    Fxaa,
+}
+
+pub fn default_chain() -> Vec<PostEffect> {
+    vec![
+        PostEffect::Bloom {
+            threshold: 1.0,
+            intensity: 0.3,
+        },
+        PostEffect::ToneMapping(ToneMapping::Aces),
+        PostEffect::Vignette {
+            strength: 0.3,
+            radius: 0.6,
+        },
+        PostEffect::Fxaa,
+    ]
+}
+
+const ENCODE_LINEAR: u32 = 0;
+const ENCODE_SRGB_SURFACE: u32 = 1;
+const ENCODE_LINEAR_SURFACE: u32 = 2;
+
+#[repr(C)]
+#[derive(Copy, Clone, PartialEq, bytemuck::Pod, bytemuck::Zeroable)]
+struct PostParams {
+    texel_size: [f32; 2],
+    direction: [f32; 2],
+    exposure: f32,
+    gamma: f32,
+    tone_mapping: u32,
+    encode: u32,
+    threshold: f32,
+    intensity: f32,
+    vignette_strength: f32,
+    vignette_radius: f32,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+enum Slot {
+    // HDR scene color handed to `render`
+    Scene,
+    // Full size ping-pong targets
+    Hdr(usize),
+    // Half size targets for the bloom blur
+    Bloom(usize),
+    Surface,
+}
+
+// Bind group of a pass and the views it was created with
+struct PassBindGroup {
+    input: wgpu::TextureView,
+    secondary: wgpu::TextureView,
+    bind_group: wgpu::BindGroup,
+}
+
+struct PostPass {
+    pipeline: wgpu::RenderPipeline,
+    uniform_buffer: wgpu::Buffer,
+    params: PostParams,
+    input: Slot,
+    secondary: Option<Slot>,
+    output: Slot,
+    // Cached across frames, `render` only takes `&self` as it runs inside the render graph.
+    // Uniforms are written when they differ from the last upload, the bind group is
+    // rebuilt when an input view changes (resize, or a new scene color target).
+    uploaded_params: Cell<Option<PostParams>>,
+    bind_group: RefCell<Option<PassBindGroup>>,
+}
+
+struct RenderTarget {
+    texture: wgpu::Texture,
+    view: wgpu::TextureView,
+}
+
+impl RenderTarget {
+    fn new(device: &wgpu::Device, label: &str, width: u32, height: u32) -> Self {
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some(label),
+            size: wgpu::Extent3d {
+                width: width.max(1),
+                height: height.max(1),
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: HDR_FORMAT,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
+            view_formats: &[],
+        });
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        Self { texture, view }
+    }
+
+    fn texel_size(&self) -> [f32; 2] {
+        [
+            1.0 / self.texture.width() as f32,
+            1.0 / self.texture.height() as f32,
+        ]
+    }
+}
+
+/// Runs the post-process chain over the HDR scene color as fullscreen passes,
+/// the last one writing to the surface. The chain is compiled into
+/// passes with fixed inputs and outputs whenever it changes.
+pub struct PostProcessor {
+    pub exposure: f32,
+    pub gamma: f32,
+    chain: Vec<PostEffect>,
+    surface_format: wgpu::TextureFormat,
+    hdr_targets: [RenderTarget; 2],
+    bloom_targets: [RenderTarget; 2],
+    sampler: wgpu::Sampler,
+    bind_group_layout: wgpu::BindGroupLayout,
+    passes: Vec<PostPass>,
+}
+
+impl PostProcessor {
+    pub fn new(
+        device: &wgpu::Device,
+        surface_format: wgpu::TextureFormat,
+        width: u32,
+        height: u32,
+        chain: Vec<PostEffect>,
+    ) -> Self {
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("Post Process Sampler"),
+            address_mode_u: wgpu::AddressMode::ClampToEdge,
+            address_mode_v: wgpu::AddressMode::ClampToEdge,
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
+        });
+
+        let texture_entry = |binding| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::FRAGMENT,
+            ty: wgpu::BindingType::Texture {
+                multisampled: false,
+                view_dimension: wgpu::TextureViewDimension::D2,
+                sample_type: wgpu::TextureSampleType::Float { filterable: true },
+            },
+            count: None,
+        };
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: &[
+                texture_entry(0),
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                texture_entry(3),
+            ],
+            label: Some("post_process_bind_group_layout"),
+        });
+
+        let (hdr_targets, bloom_targets) = create_targets(device, width, height);
+        let mut post_processor = Self {
+            exposure: 1.0,
+            gamma: 2.2,
+            chain: Vec::new(),
+            surface_format,
+            hdr_targets,
+            bloom_targets,
+            sampler,
+            bind_group_layout,
+            passes: Vec::new(),
+        };
+        post_processor.set_chain(device, chain);
+        post_processor
+    }
+
+    pub fn chain(&self) -> &[PostEffect] {
+        &self.chain
+    }
+
+    pub fn set_chain(&mut self, device: &wgpu::Device, chain: Vec<PostEffect>) {
+        self.chain = chain;
+        self.passes.clear();
+
+        let mut input = Slot::Scene;
+        for (i, effect) in self.chain.clone().into_iter().enumerate() {
+            let output = if i + 1 == self.chain.len() {
+                Slot::Surface
+            } else {
+                match input {
+                    Slot::Hdr(index) => Slot::Hdr(1 - index),
+                    _ => Slot::Hdr(0),
+                }
+            };
+            let params = PostParams {
+                tone_mapping: ToneMapping::None.to_raw(),
+                ..bytemuck::Zeroable::zeroed()
+            };
+            match effect {
+                PostEffect::Bloom {
+                    threshold,
+                    intensity,
+                } => {
+                    let bloom = PostParams {
+                        threshold,
+                        intensity,
+                        ..params
+                    };
+                    self.add_pass(
+                        device,
+                        "fs_bloom_extract",
+                        bloom,
+                        input,
+                        None,
+                        Slot::Bloom(0),
+                    );
+                    let horizontal = PostParams {
+                        direction: [1.0, 0.0],
+                        ..bloom
+                    };
+                    self.add_pass(
+                        device,
+                        "fs_blur",
+                        horizontal,
+                        Slot::Bloom(0),
+                        None,
+                        Slot::Bloom(1),
+                    );
+                    let vertical = PostParams {
+                        direction: [0.0, 1.0],
+                        ..bloom
+                    };
+                    self.add_pass(
+                        device,
+                        "fs_blur",
+                        vertical,
+                        Slot::Bloom(1),
+                        None,
+                        Slot::Bloom(0),
+                    );
+                    self.add_pass(
+                        device,
+                        "fs_bloom_composite",
+                        bloom,
+                        input,
+                        Some(Slot::Bloom(0)),
+                        output,
+                    );
+                }
+                PostEffect::ToneMapping(tone_mapping) => {
+                    let params = PostParams {
+                        tone_mapping: tone_mapping.to_raw(),
+                        ..params
+                    };
+                    self.add_pass(device, "fs_tone_map", params, input, None, output);
+                }
+                PostEffect::Vignette { strength, radius } => {
+                    let params = PostParams {
+                        vignette_strength: strength,
+                        vignette_radius: radius,
+                        ..params
+                    };
+                    self.add_pass(device, "fs_vignette", params, input, None, output);
+                }
+                PostEffect::Fxaa => {
+                    self.add_pass(device, "fs_fxaa", params, input, None, output);
+                }
+            }
+            input = output;
+        }
+
+        // An empty chain still has to move the scene to the surface
+        if self.passes.is_empty() {
+            let params = bytemuck::Zeroable::zeroed();
+            self.add_pass(device, "fs_copy", params, input, None, Slot::Surface);
+        }
+    }
+
+    fn add_pass(
+        &mut self,
+        device: &wgpu::Device,
+        fragment_entry: &str,
+        params: PostParams,
+        input: Slot,
+        secondary: Option<Slot>,
+        output: Slot,
+    ) {
+        let format = match output {
+            Slot::Surface => self.surface_format,
+            _ => HDR_FORMAT,
+        };
+        let mut pipeline_builder = pipeline_builder::PipelineBuilder::new(
+            device,
+            pipeline_builder::ShaderSource::Builtin("shaders/post_process.wgsl"),
+            "vs_fullscreen",
+            fragment_entry,
+            format,
+        );
+        pipeline_builder.add_bind_group_layout(&self.bind_group_layout);
+        pipeline_builder.set_cull_mode(None);
+        let pipeline = pipeline_builder.build_pipeline(fragment_entry);
+
+        let uniform_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Post Process Params Buffer"),
+            size: mem::size_of::<PostParams>() as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        self.passes.push(PostPass {
+            pipeline,
+            uniform_buffer,
+            params,
+            input,
+            secondary,
+            output,
+            uploaded_params: Cell::new(None),
+            bind_group: RefCell::new(None),
+        });
+    }
+
+    pub fn resize(&mut self, device: &wgpu::Device, width: u32, height: u32) {
+        (self.hdr_targets, self.bloom_targets) = create_targets(device, width, height);
+        // Drops the bind groups holding on to the old targets
+        for pass in &self.passes {
+            pass.bind_group.replace(None);
+        }
+    }
+
+    pub fn render(
+        &self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        command_encoder: &mut wgpu::CommandEncoder,
+        scene_view: &wgpu::TextureView,
+        surface_view: &wgpu::TextureView,
+    ) {
+        let final_encode = if self.surface_format.is_srgb() {
+            ENCODE_SRGB_SURFACE
+        } else {
+            ENCODE_LINEAR_SURFACE
+        };
+
+        for pass in &self.passes {
+            let params = PostParams {
+                texel_size: self.texel_size(pass.input),
+                exposure: self.exposure,
+                gamma: self.gamma,
+                encode: if pass.output == Slot::Surface {
+                    final_encode
+                } else {
+                    ENCODE_LINEAR
+                },
+                ..pass.params
+            };
+            if pass.uploaded_params.get() != Some(params) {
+                queue.write_buffer(&pass.uniform_buffer, 0, bytemuck::cast_slice(&[params]));
+                pass.uploaded_params.set(Some(params));
+            }
+
+            let input_view = self.view(pass.input, scene_view, surface_view);
+            let secondary_view = pass
+                .secondary
+                .map_or(input_view, |slot| self.view(slot, scene_view, surface_view));
+            let mut cached = pass.bind_group.borrow_mut();
+            let outdated = cached.as_ref().is_none_or(|cached| {
+                cached.input != *input_view || cached.secondary != *secondary_view
+            });
+            if outdated {
+                *cached = Some(PassBindGroup {
+                    input: input_view.clone(),
+                    secondary: secondary_view.clone(),
+                    bind_group: self.create_bind_group(device, pass, input_view, secondary_view),
+                });
+            }
+            let bind_group = &cached.as_ref().unwrap().bind_group;
+
+            let output_view = self.view(pass.output, scene_view, surface_view);
+            let mut render_pass = command_encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("Post Process Pass"),
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view: output_view,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+                        store: wgpu::StoreOp::Store,
+                    },
+                    depth_slice: None,
+                })],
+                depth_stencil_attachment: None,
+                occlusion_query_set: None,
+                timestamp_writes: None,
+            });
+            render_pass.set_pipeline(&pass.pipeline);
+            render_pass.set_bind_group(0, bind_group, &[]);
+            render_pass.draw(0..3, 0..1);
+        }
+    }
+
+    fn create_bind_group(
+        &self,
+        device: &wgpu::Device,
+        pass: &PostPass,
+        input_view: &wgpu::TextureView,
+        secondary_view: &wgpu::TextureView,
+    ) -> wgpu::BindGroup {
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &self.bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(input_view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Sampler(&self.sampler),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: pass.uniform_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: wgpu::BindingResource::TextureView(secondary_view),
+                },
+            ],
+            label: Some("post_process_bind_group"),
+        })
+    }
+
+    fn view<'b>(
+        &'b self,
+        slot: Slot,
+        scene_view: &'b wgpu::TextureView,
+        surface_view: &'b wgpu::TextureView,
+    ) -> &'b wgpu::TextureView {
+        match slot {
+            Slot::Scene => scene_view,
+            Slot::Hdr(index) => &self.hdr_targets[index].view,
+            Slot::Bloom(index) => &self.bloom_targets[index].view,
+            Slot::Surface => surface_view,
+        }
+    }
+
+    // The scene color matches the surface size like the full size targets
+    fn texel_size(&self, slot: Slot) -> [f32; 2] {
+        match slot {
+            Slot::Scene | Slot::Hdr(_) | Slot::Surface => self.hdr_targets[0].texel_size(),
+            Slot::Bloom(index) => self.bloom_targets[index].texel_size(),
+        }
+    }
+}
+
+fn create_targets(
+    device: &wgpu::Device,
+    width: u32,
+    height: u32,
+) -> ([RenderTarget; 2], [RenderTarget; 2]) {
+    let hdr_targets = [
+        RenderTarget::new(device, "HDR Target 0", width, height),
+        RenderTarget::new(device, "HDR Target 1", width, height),
+    ];
+    let bloom_targets = [
+        RenderTarget::new(device, "Bloom Target 0", width / 2, height / 2),
+        RenderTarget::new(device, "Bloom Target 1", width / 2, height / 2),
+    ];
+    (hdr_targets, bloom_targets)
+}
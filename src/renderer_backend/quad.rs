@@ -0,0 +1,115 @@
+use std::ops::Range;
+
+use cgmath::{Matrix4, Vector3};
+
+use super::{
+    camera::{Camera, CameraBinding},
+    instance::{InstanceStorage, InstancingMode},
+    mesh_builder::{self, Mesh, Vertex},
+    pipeline_builder::{PipelineBuilder, ShaderSource},
+};
+
+/// Per-quad instance data: the model matrix of the unit quad, the rectangle
+/// sampled from the texture and a color the texture is multiplied with.
+#[repr(C)]
+#[derive(Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct QuadInstance {
+    pub model: [[f32; 4]; 4],
+    pub uv_rect: [f32; 4],
+    pub color: [f32; 4],
+}
+
+impl QuadInstance {
+    // Same locations as `Instance::desc`, followed by the UV rectangle and color
+    pub fn desc() -> wgpu::VertexBufferLayout<'static> {
+        const ATTRIBUTES: [wgpu::VertexAttribute; 6] = wgpu::vertex_attr_array![
+            5 => Float32x4, 6 => Float32x4, 7 => Float32x4, 8 => Float32x4,
+            9 => Float32x4, 10 => Float32x4
+        ];
+        wgpu::VertexBufferLayout {
+            array_stride: std::mem::size_of::<QuadInstance>() as wgpu::BufferAddress,
+            step_mode: wgpu::VertexStepMode::Instance,
+            attributes: &ATTRIBUTES,
+        }
+    }
+}
+
+/// Scales the unit quad to `size` around `center`. The quad's top edge has v = 0
+/// and points to +y, it is flipped to -y, the top in pixel space and text layout.
+pub fn quad_model(center: Vector3<f32>, size: [f32; 2]) -> Matrix4<f32> {
+    Matrix4::from_translation(center) * Matrix4::from_nonuniform_scale(size[0], -size[1], 1.0)
+}
+
+/// Unit quad drawn instanced from `QuadInstance`s, textured through a material
+/// bound at group 0 with the camera at group 1.
+pub struct InstancedQuad {
+    mesh: Mesh,
+}
+
+impl InstancedQuad {
+    pub fn new(device: &wgpu::Device) -> Self {
+        Self {
+            mesh: mesh_builder::create_mesh(device, &0.5),
+        }
+    }
+
+    /// Pipeline with the bind group and vertex layouts the quads expect. Instances
+    /// flip the quad and may mirror it again, so nothing is culled.
+    pub fn pipeline_builder<'a>(
+        device: &'a wgpu::Device,
+        shader: ShaderSource,
+        vertex_entry: &str,
+        fragment_entry: &str,
+        format: wgpu::TextureFormat,
+        material_layout: &'a wgpu::BindGroupLayout,
+        camera_layout: &'a wgpu::BindGroupLayout,
+    ) -> PipelineBuilder<'a> {
+        let mut pipeline_builder =
+            PipelineBuilder::new(device, shader, vertex_entry, fragment_entry, format);
+        pipeline_builder.add_bind_group_layout(material_layout);
+        pipeline_builder.add_bind_group_layout(camera_layout);
+        pipeline_builder.add_vertex_buffer_layout(Vertex::desc());
+        pipeline_builder.add_vertex_buffer_layout(QuadInstance::desc());
+        pipeline_builder.set_cull_mode(None);
+        pipeline_builder.set_blend_states(&[wgpu::BlendState::ALPHA_BLENDING]);
+        pipeline_builder
+    }
+
+    // Read as vertex buffer 1, refilled every frame
+    pub fn create_instances(device: &wgpu::Device, queue: &wgpu::Queue) -> InstanceStorage {
+        InstanceStorage::new::<QuadInstance>(device, queue, &[], InstancingMode::VertexBuffer)
+    }
+
+    // Binds the quad and `instances`, the pipeline and bind groups are up to the caller
+    pub fn bind(&self, render_pass: &mut wgpu::RenderPass, instances: &InstanceStorage) {
+        render_pass.set_vertex_buffer(0, self.mesh.vertex_buffer.slice(..));
+        render_pass.set_vertex_buffer(1, instances.buffer.slice(..));
+        render_pass.set_index_buffer(self.mesh.index_buffer.slice(..), wgpu::IndexFormat::Uint32);
+    }
+
+    pub fn draw(&self, render_pass: &mut wgpu::RenderPass, instances: Range<u32>) {
+        render_pass.draw_indexed(0..self.mesh.num_indices as u32, 0, instances);
+    }
+}
+
+/// Camera binding of `Camera::pixel_space`, for quads drawn over the final image.
+pub struct PixelCamera {
+    pub binding: CameraBinding,
+}
+
+impl PixelCamera {
+    pub fn new(device: &wgpu::Device, size: (f32, f32)) -> Self {
+        Self {
+            binding: CameraBinding::new(device, &Camera::pixel_space(size.0, size.1)),
+        }
+    }
+
+    // `origin` is the pixel shown at the top-left corner of the `size` target
+    pub fn update(&self, queue: &wgpu::Queue, size: (f32, f32), origin: [f32; 2]) {
+        let mut camera = Camera::pixel_space(size.0, size.1);
+        let offset = Vector3::new(origin[0], origin[1], 0.0);
+        camera.eye += offset;
+        camera.target += offset;
+        self.binding.update(queue, &camera);
+    }
+}
@@ -0,0 +1,783 @@
+use std::collections::VecDeque;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct TextureHandle(usize);
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct BufferHandle(usize);
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum TextureSize {
+    // Follows the surface, reallocated when it is resized
+    Surface,
+    Fixed { width: u32, height: u32 },
+}
+
+/// Description of a transient texture. Usages implied by how passes access the
+/// texture (attachment, sampled) are added by the graph.
+#[derive(Copy, Clone, Debug)]
+pub struct TextureDesc {
+    pub label: &'static str,
+    pub format: wgpu::TextureFormat,
+    pub size: TextureSize,
+    pub usage: wgpu::TextureUsages,
+    // More than one for multisampled attachments, see `PassBuilder::resolve_color`
+    pub sample_count: u32,
+}
+
+enum TextureResource<'a> {
+    Transient(TextureDesc),
+    Imported(&'a wgpu::TextureView),
+}
+
+/// Reads and writes a pass declares. Render passes get their color and depth
+/// attachments from `write_color`/`write_depth`, encoder passes record their
+/// own work and declare what it touches with `write_texture`.
+pub struct PassBuilder {
+    pub name: String,
+    pub texture_reads: Vec<TextureHandle>,
+    pub texture_writes: Vec<TextureHandle>,
+    pub buffer_reads: Vec<BufferHandle>,
+    pub buffer_writes: Vec<BufferHandle>,
+    // `None` keeps earlier contents, or clears when there are none
+    pub color_attachments: Vec<(TextureHandle, Option<wgpu::Color>)>,
+    // Multisampled color attachment and the texture it resolves into
+    pub resolve_targets: Vec<(TextureHandle, TextureHandle)>,
+    pub depth_attachment: Option<(TextureHandle, Option<f32>)>,
+}
+
+impl PassBuilder {
+    pub fn new(name: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            texture_reads: Vec::new(),
+            texture_writes: Vec::new(),
+            buffer_reads: Vec::new(),
+            buffer_writes: Vec::new(),
+            color_attachments: Vec::new(),
+            resolve_targets: Vec::new(),
+            depth_attachment: None,
+        }
+    }
+
+    pub fn read_texture(&mut self, texture: TextureHandle) {
+        self.texture_reads.push(texture);
+    }
+
+    pub fn write_texture(&mut self, texture: TextureHandle) {
+        self.texture_writes.push(texture);
+    }
+
+    pub fn read_buffer(&mut self, buffer: BufferHandle) {
+        self.buffer_reads.push(buffer);
+    }
+
+    pub fn write_buffer(&mut self, buffer: BufferHandle) {
+        self.buffer_writes.push(buffer);
+    }
+
+    pub fn write_color(&mut self, texture: TextureHandle, clear: Option<wgpu::Color>) {
+        self.color_attachments.push((texture, clear));
+    }
+
+    pub fn write_depth(&mut self, texture: TextureHandle, clear: Option<f32>) {
+        self.depth_attachment = Some((texture, clear));
+    }
+
+    /// Resolves the multisampled color attachment `texture` into `resolve_target`
+    /// at the end of the pass. The previous contents of the target are replaced.
+    pub fn resolve_color(&mut self, texture: TextureHandle, resolve_target: TextureHandle) {
+        self.resolve_targets.push((texture, resolve_target));
+    }
+
+    fn writes_texture(&self, texture: TextureHandle) -> bool {
+        self.texture_writes.contains(&texture)
+            || self.color_attachments.iter().any(|(t, _)| *t == texture)
+            || self.resolve_targets.iter().any(|(_, t)| *t == texture)
+            || self.depth_attachment.is_some_and(|(t, _)| t == texture)
+    }
+
+    fn uses_texture(&self, texture: TextureHandle) -> bool {
+        self.texture_reads.contains(&texture) || self.writes_texture(texture)
+    }
+}
+
+type RenderPassFn<'a> = Box<dyn FnOnce(&mut wgpu::RenderPass, &PassResources) + 'a>;
+type EncoderPassFn<'a> = Box<dyn FnOnce(&mut wgpu::CommandEncoder, &PassResources) + 'a>;
+
+enum PassExecute<'a> {
+    Render(RenderPassFn<'a>),
+    Encoder(EncoderPassFn<'a>),
+}
+
+struct Pass<'a> {
+    builder: PassBuilder,
+    execute: PassExecute<'a>,
+}
+
+/// Views and buffers of the graph resources, as seen by a pass while it runs.
+pub struct PassResources<'a> {
+    texture_views: Vec<Option<&'a wgpu::TextureView>>,
+    buffers: Vec<&'a wgpu::Buffer>,
+}
+
+impl PassResources<'_> {
+    pub fn texture_view(&self, texture: TextureHandle) -> &wgpu::TextureView {
+        self.texture_views[texture.0].expect("Texture is not used by any scheduled pass")
+    }
+
+    pub fn buffer(&self, buffer: BufferHandle) -> &wgpu::Buffer {
+        self.buffers[buffer.0]
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+struct TextureKey {
+    format: wgpu::TextureFormat,
+    width: u32,
+    height: u32,
+    usage: wgpu::TextureUsages,
+    sample_count: u32,
+}
+
+struct PooledTexture {
+    key: TextureKey,
+    view: wgpu::TextureView,
+    used_this_frame: bool,
+}
+
+// Positions in the schedule of the first and last pass using each texture
+// (`usize::MAX` when unused), and the usages implied by the accesses
+struct TextureLifetimes {
+    first_use: Vec<usize>,
+    last_use: Vec<usize>,
+    usages: Vec<wgpu::TextureUsages>,
+}
+
+/// Physical textures backing transient graph textures, kept across frames.
+/// Entries a frame did not use (e.g. the old size after a resize) are dropped.
+#[derive(Default)]
+pub struct TransientPool {
+    textures: Vec<PooledTexture>,
+}
+
+/// Per-frame graph of passes. Passes touching the same resource keep their
+/// declaration order (read after write, write after read, write after write),
+/// independent passes are free to move. The graph drops passes whose results
+/// nobody uses, shares physical textures between transient textures whose
+/// lifetimes don't overlap, and picks the attachment load/store ops.
+pub struct RenderGraph<'a> {
+    textures: Vec<TextureResource<'a>>,
+    buffers: Vec<&'a wgpu::Buffer>,
+    passes: Vec<Pass<'a>>,
+}
+
+impl<'a> Default for RenderGraph<'a> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<'a> RenderGraph<'a> {
+    pub fn new() -> Self {
+        Self {
+            textures: Vec::new(),
+            buffers: Vec::new(),
+            passes: Vec::new(),
+        }
+    }
+
+    pub fn create_texture(&mut self, desc: TextureDesc) -> TextureHandle {
+        self.textures.push(TextureResource::Transient(desc));
+        TextureHandle(self.textures.len() - 1)
+    }
+
+    /// External textures (the surface, shadow maps) always keep their contents.
+    pub fn import_texture(&mut self, view: &'a wgpu::TextureView) -> TextureHandle {
+        self.textures.push(TextureResource::Imported(view));
+        TextureHandle(self.textures.len() - 1)
+    }
+
+    pub fn import_buffer(&mut self, buffer: &'a wgpu::Buffer) -> BufferHandle {
+        self.buffers.push(buffer);
+        BufferHandle(self.buffers.len() - 1)
+    }
+
+    pub fn add_render_pass(
+        &mut self,
+        builder: PassBuilder,
+        execute: impl FnOnce(&mut wgpu::RenderPass, &PassResources) + 'a,
+    ) {
+        self.passes.push(Pass {
+            builder,
+            execute: PassExecute::Render(Box::new(execute)),
+        });
+    }
+
+    pub fn add_encoder_pass(
+        &mut self,
+        builder: PassBuilder,
+        execute: impl FnOnce(&mut wgpu::CommandEncoder, &PassResources) + 'a,
+    ) {
+        self.passes.push(Pass {
+            builder,
+            execute: PassExecute::Encoder(Box::new(execute)),
+        });
+    }
+
+    pub fn execute(
+        self,
+        device: &wgpu::Device,
+        command_encoder: &mut wgpu::CommandEncoder,
+        pool: &mut TransientPool,
+        surface_size: (u32, u32),
+    ) {
+        let order = self.schedule();
+        let lifetimes = self.texture_lifetimes(&order);
+        let TextureLifetimes {
+            first_use,
+            last_use,
+            ..
+        } = &lifetimes;
+
+        for pooled in &mut pool.textures {
+            pooled.used_this_frame = false;
+        }
+        let mut transients = (0..self.textures.len())
+            .filter(|&texture| first_use[texture] != usize::MAX)
+            .filter_map(|texture| match &self.textures[texture] {
+                TextureResource::Transient(desc) => Some((texture, *desc)),
+                TextureResource::Imported(_) => None,
+            })
+            .collect::<Vec<_>>();
+        transients.sort_by_key(|(texture, _)| first_use[*texture]);
+        let requests = transients
+            .iter()
+            .map(|&(texture, desc)| {
+                let (width, height) = match desc.size {
+                    TextureSize::Surface => surface_size,
+                    TextureSize::Fixed { width, height } => (width, height),
+                };
+                let key = TextureKey {
+                    format: desc.format,
+                    width: width.max(1),
+                    height: height.max(1),
+                    usage: desc.usage | lifetimes.usages[texture],
+                    sample_count: desc.sample_count,
+                };
+                (key, first_use[texture], last_use[texture])
+            })
+            .collect::<Vec<_>>();
+        let mut pooled_keys = pool.textures.iter().map(|pooled| pooled.key).collect();
+        let slots = alias_transients(&mut pooled_keys, &requests);
+        let mut physical = vec![None; self.textures.len()];
+        for ((texture, desc), slot) in transients.into_iter().zip(slots) {
+            // New slots are handed out in order, one past the end of the pool
+            if slot == pool.textures.len() {
+                pool.textures
+                    .push(create_pooled_texture(device, desc.label, pooled_keys[slot]));
+            }
+            pool.textures[slot].used_this_frame = true;
+            physical[texture] = Some(slot);
+        }
+
+        let texture_views = self
+            .textures
+            .iter()
+            .enumerate()
+            .map(|(texture, resource)| match resource {
+                TextureResource::Imported(view) => Some(*view),
+                TextureResource::Transient(_) => physical[texture].map(|i| &pool.textures[i].view),
+            })
+            .collect::<Vec<_>>();
+        let resources = PassResources {
+            texture_views,
+            buffers: self.buffers.clone(),
+        };
+
+        let mut passes = self.passes.into_iter().map(Some).collect::<Vec<_>>();
+        let scheduled = order
+            .iter()
+            .map(|&index| passes[index].as_ref().unwrap().builder.name.clone())
+            .collect::<Vec<_>>();
+        log::trace!("Render graph order: {:?}", scheduled);
+
+        for (position, &pass_index) in order.iter().enumerate() {
+            let pass = passes[pass_index].take().unwrap();
+            let is_imported = |texture: TextureHandle| {
+                matches!(self.textures[texture.0], TextureResource::Imported(_))
+            };
+            // Earlier contents matter if an earlier pass wrote them or they come from outside
+            let has_contents =
+                |texture: TextureHandle| is_imported(texture) || first_use[texture.0] < position;
+            let store_op = |texture: TextureHandle| {
+                if is_imported(texture) || last_use[texture.0] > position {
+                    wgpu::StoreOp::Store
+                } else {
+                    wgpu::StoreOp::Discard
+                }
+            };
+
+            match pass.execute {
+                PassExecute::Render(execute) => {
+                    let color_attachments = pass
+                        .builder
+                        .color_attachments
+                        .iter()
+                        .map(|&(texture, clear)| {
+                            let load = match clear {
+                                Some(color) => wgpu::LoadOp::Clear(color),
+                                None if has_contents(texture) => wgpu::LoadOp::Load,
+                                None => wgpu::LoadOp::Clear(wgpu::Color::TRANSPARENT),
+                            };
+                            let resolve_target = pass
+                                .builder
+                                .resolve_targets
+                                .iter()
+                                .find(|(color, _)| *color == texture)
+                                .map(|(_, target)| resources.texture_view(*target));
+                            Some(wgpu::RenderPassColorAttachment {
+                                view: resources.texture_view(texture),
+                                resolve_target,
+                                ops: wgpu::Operations {
+                                    load,
+                                    store: store_op(texture),
+                                },
+                                depth_slice: None,
+                            })
+                        })
+                        .collect::<Vec<_>>();
+                    let depth_stencil_attachment =
+                        pass.builder.depth_attachment.map(|(texture, clear)| {
+                            let load = match clear {
+                                Some(depth) => wgpu::LoadOp::Clear(depth),
+                                None if has_contents(texture) => wgpu::LoadOp::Load,
+                                None => wgpu::LoadOp::Clear(1.0),
+                            };
+                            wgpu::RenderPassDepthStencilAttachment {
+                                view: resources.texture_view(texture),
+                                depth_ops: Some(wgpu::Operations {
+                                    load,
+                                    store: store_op(texture),
+                                }),
+                                stencil_ops: None,
+                            }
+                        });
+
+                    let mut render_pass =
+                        command_encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                            label: Some(&pass.builder.name),
+                            color_attachments: &color_attachments,
+                            depth_stencil_attachment,
+                            occlusion_query_set: None,
+                            timestamp_writes: None,
+                        });
+                    execute(&mut render_pass, &resources);
+                }
+                PassExecute::Encoder(execute) => {
+                    command_encoder.push_debug_group(&pass.builder.name);
+                    execute(command_encoder, &resources);
+                    command_encoder.pop_debug_group();
+                }
+            }
+        }
+
+        pool.textures.retain(|pooled| pooled.used_this_frame);
+    }
+
+    fn texture_lifetimes(&self, order: &[usize]) -> TextureLifetimes {
+        let mut lifetimes = TextureLifetimes {
+            first_use: vec![usize::MAX; self.textures.len()],
+            last_use: vec![0; self.textures.len()],
+            usages: vec![wgpu::TextureUsages::empty(); self.textures.len()],
+        };
+        for (position, &pass_index) in order.iter().enumerate() {
+            let builder = &self.passes[pass_index].builder;
+            let attachments = builder
+                .color_attachments
+                .iter()
+                .map(|(texture, _)| *texture)
+                .chain(builder.resolve_targets.iter().map(|(_, target)| *target))
+                .chain(builder.depth_attachment.map(|(texture, _)| texture));
+            for texture in attachments {
+                lifetimes.usages[texture.0] |= wgpu::TextureUsages::RENDER_ATTACHMENT;
+            }
+            for texture in &builder.texture_reads {
+                lifetimes.usages[texture.0] |= wgpu::TextureUsages::TEXTURE_BINDING;
+            }
+            for texture in 0..self.textures.len() {
+                if builder.uses_texture(TextureHandle(texture)) {
+                    lifetimes.first_use[texture] = lifetimes.first_use[texture].min(position);
+                    lifetimes.last_use[texture] = position;
+                }
+            }
+        }
+        lifetimes
+    }
+
+    // Kahn's algorithm over the hazards of each resource, ties broken by
+    // declaration order so independent passes run in the order they were added
+    fn schedule(&self) -> Vec<usize> {
+        let live = self.live_passes();
+        let pass_count = self.passes.len();
+        let mut dependents = vec![Vec::new(); pass_count];
+        let mut dependency_count = vec![0; pass_count];
+
+        let mut add_edge = |from: usize, to: usize| {
+            if from != to && !dependents[from].contains(&to) {
+                dependents[from].push(to);
+                dependency_count[to] += 1;
+            }
+        };
+        // Walks the accesses of one resource in declaration order. Readers wait
+        // for the last writer, writers wait for it and for the readers since.
+        let mut add_hazards = |accesses: &mut dyn Iterator<Item = (usize, bool, bool)>| {
+            let mut last_writer = None;
+            let mut readers = Vec::new();
+            for (pass, reads, writes) in accesses {
+                if reads {
+                    if let Some(writer) = last_writer {
+                        add_edge(writer, pass);
+                    }
+                    readers.push(pass);
+                }
+                if writes {
+                    for &reader in &readers {
+                        add_edge(reader, pass);
+                    }
+                    if let Some(writer) = last_writer {
+                        add_edge(writer, pass);
+                    }
+                    last_writer = Some(pass);
+                    readers.clear();
+                }
+            }
+        };
+        for texture in 0..self.textures.len() {
+            let texture = TextureHandle(texture);
+            add_hazards(&mut (0..pass_count).filter(|&p| live[p]).map(|p| {
+                let builder = &self.passes[p].builder;
+                (
+                    p,
+                    builder.texture_reads.contains(&texture),
+                    builder.writes_texture(texture),
+                )
+            }));
+        }
+        for buffer in 0..self.buffers.len() {
+            let buffer = BufferHandle(buffer);
+            add_hazards(&mut (0..pass_count).filter(|&p| live[p]).map(|p| {
+                let builder = &self.passes[p].builder;
+                (
+                    p,
+                    builder.buffer_reads.contains(&buffer),
+                    builder.buffer_writes.contains(&buffer),
+                )
+            }));
+        }
+
+        let mut ready = (0..pass_count)
+            .filter(|&p| live[p] && dependency_count[p] == 0)
+            .collect::<VecDeque<_>>();
+        let mut order = Vec::with_capacity(pass_count);
+        while let Some(pass) = ready.pop_front() {
+            order.push(pass);
+            for &dependent in &dependents[pass] {
+                dependency_count[dependent] -= 1;
+                if dependency_count[dependent] == 0 {
+                    let at = ready.partition_point(|&p| p < dependent);
+                    ready.insert(at, dependent);
+                }
+            }
+        }
+        let live_count = live.iter().filter(|&&l| l).count();
+        assert_eq!(
+            order.len(),
+            live_count,
+            "Render graph has a dependency cycle"
+        );
+        order
+    }
+
+    // A pass is live when it writes an imported resource, has no declared
+    // outputs (side effects only), or writes something a live pass reads
+    fn live_passes(&self) -> Vec<bool> {
+        let is_imported = |texture: &TextureHandle| {
+            matches!(self.textures[texture.0], TextureResource::Imported(_))
+        };
+        let mut live = self
+            .passes
+            .iter()
+            .map(|pass| {
+                let builder = &pass.builder;
+                let no_texture_outputs = builder.texture_writes.is_empty()
+                    && builder.color_attachments.is_empty()
+                    && builder.depth_attachment.is_none();
+                no_texture_outputs
+                    || !builder.buffer_writes.is_empty()
+                    || (0..self.textures.len())
+                        .map(TextureHandle)
+                        .any(|texture| is_imported(&texture) && builder.writes_texture(texture))
+            })
+            .collect::<Vec<_>>();
+
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for writer in 0..self.passes.len() {
+                if live[writer] {
+                    continue;
+                }
+                let feeds_live_pass = (0..self.passes.len()).any(|reader| {
+                    live[reader]
+                        && self.passes[reader]
+                            .builder
+                            .texture_reads
+                            .iter()
+                            .any(|&texture| self.passes[writer].builder.writes_texture(texture))
+                });
+                if feeds_live_pass {
+                    live[writer] = true;
+                    changed = true;
+                }
+            }
+        }
+
+        for (pass, &is_live) in self.passes.iter().zip(&live) {
+            if !is_live {
+                log::debug!("Render graph culled unused pass {}", pass.builder.name);
+            }
+        }
+        live
+    }
+}
+
+// Aliases transient textures, given as their key and first and last position in
+// the schedule, sorted by first use. A physical texture is free again once the
+// last pass using its previous owner has been scheduled. Returns the index in
+// `pooled` of each texture, appending keys that need a new physical texture.
+fn alias_transients(
+    pooled: &mut Vec<TextureKey>,
+    transients: &[(TextureKey, usize, usize)],
+) -> Vec<usize> {
+    let mut busy_until = vec![None; pooled.len()];
+    transients
+        .iter()
+        .map(|&(key, first_use, last_use)| {
+            let free = pooled
+                .iter()
+                .zip(&busy_until)
+                .position(|(pooled_key, busy)| {
+                    *pooled_key == key && busy.is_none_or(|end| end < first_use)
+                });
+            let index = free.unwrap_or_else(|| {
+                pooled.push(key);
+                busy_until.push(None);
+                pooled.len() - 1
+            });
+            busy_until[index] = Some(last_use);
+            index
+        })
+        .collect()
+}
+
+fn create_pooled_texture(device: &wgpu::Device, label: &str, key: TextureKey) -> PooledTexture {
+    let texture = device.create_texture(&wgpu::TextureDescriptor {
+        label: Some(label),
+        size: wgpu::Extent3d {
+            width: key.width,
+            height: key.height,
+            depth_or_array_layers: 1,
+        },
+        mip_level_count: 1,
+        sample_count: key.sample_count,
+        dimension: wgpu::TextureDimension::D2,
+        format: key.format,
+        usage: key.usage,
+        view_formats: &[],
+    });
+    let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+    PooledTexture {
+        key,
+        view,
+        used_this_frame: false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn transient(graph: &mut RenderGraph) -> TextureHandle {
+        graph.create_texture(TextureDesc {
+            label: "Test",
+            format: wgpu::TextureFormat::Rgba8Unorm,
+            size: TextureSize::Surface,
+            usage: wgpu::TextureUsages::empty(),
+            sample_count: 1,
+        })
+    }
+
+    fn key(width: u32) -> TextureKey {
+        TextureKey {
+            format: wgpu::TextureFormat::Rgba8Unorm,
+            width,
+            height: 1,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+            sample_count: 1,
+        }
+    }
+
+    fn add_pass(graph: &mut RenderGraph, builder: PassBuilder) {
+        graph.add_encoder_pass(builder, |_, _| {});
+    }
+
+    // Reads the texture without declaring outputs, which keeps it live
+    fn add_present(graph: &mut RenderGraph, texture: TextureHandle) {
+        let mut present = PassBuilder::new("Present");
+        present.read_texture(texture);
+        add_pass(graph, present);
+    }
+
+    #[test]
+    fn reads_run_after_writes() {
+        let mut graph = RenderGraph::new();
+        let color = transient(&mut graph);
+        let mut scene = PassBuilder::new("Scene");
+        scene.write_color(color, None);
+        add_pass(&mut graph, scene);
+        add_present(&mut graph, color);
+        assert_eq!(graph.schedule(), vec![0, 1]);
+    }
+
+    #[test]
+    fn writes_after_reads_keep_declaration_order() {
+        // Lighting reads the depth a later pass writes, both write the color
+        let mut graph = RenderGraph::new();
+        let gbuffer = transient(&mut graph);
+        let depth = transient(&mut graph);
+        let color = transient(&mut graph);
+        let mut gbuffer_pass = PassBuilder::new("G-Buffer");
+        gbuffer_pass.write_color(gbuffer, Some(wgpu::Color::TRANSPARENT));
+        gbuffer_pass.write_depth(depth, Some(1.0));
+        add_pass(&mut graph, gbuffer_pass);
+        let mut lighting = PassBuilder::new("Lighting");
+        lighting.read_texture(gbuffer);
+        lighting.read_texture(depth);
+        lighting.write_color(color, None);
+        add_pass(&mut graph, lighting);
+        let mut skybox = PassBuilder::new("Skybox");
+        skybox.write_color(color, None);
+        skybox.write_depth(depth, None);
+        add_pass(&mut graph, skybox);
+        add_present(&mut graph, color);
+        assert_eq!(graph.schedule(), vec![0, 1, 2, 3]);
+    }
+
+    #[test]
+    fn writers_of_one_texture_keep_declaration_order() {
+        let mut graph = RenderGraph::new();
+        let color = transient(&mut graph);
+        for name in ["First", "Second", "Third"] {
+            let mut pass = PassBuilder::new(name);
+            pass.write_color(color, None);
+            add_pass(&mut graph, pass);
+        }
+        add_present(&mut graph, color);
+        assert_eq!(graph.schedule(), vec![0, 1, 2, 3]);
+    }
+
+    #[test]
+    fn unused_passes_are_culled() {
+        let mut graph = RenderGraph::new();
+        let unused = transient(&mut graph);
+        let intermediate = transient(&mut graph);
+        let color = transient(&mut graph);
+        let mut dead = PassBuilder::new("Dead");
+        dead.write_color(unused, None);
+        add_pass(&mut graph, dead);
+        let mut first = PassBuilder::new("First");
+        first.write_color(intermediate, None);
+        add_pass(&mut graph, first);
+        let mut second = PassBuilder::new("Second");
+        second.read_texture(intermediate);
+        second.write_color(color, None);
+        add_pass(&mut graph, second);
+        add_present(&mut graph, color);
+        assert_eq!(graph.live_passes(), vec![false, true, true, true]);
+        assert_eq!(graph.schedule(), vec![1, 2, 3]);
+    }
+
+    #[test]
+    fn passes_feeding_only_culled_passes_are_culled() {
+        let mut graph = RenderGraph::new();
+        let intermediate = transient(&mut graph);
+        let unused = transient(&mut graph);
+        let mut first = PassBuilder::new("First");
+        first.write_color(intermediate, None);
+        add_pass(&mut graph, first);
+        let mut second = PassBuilder::new("Second");
+        second.read_texture(intermediate);
+        second.write_color(unused, None);
+        add_pass(&mut graph, second);
+        add_pass(&mut graph, PassBuilder::new("Side Effects"));
+        assert_eq!(graph.live_passes(), vec![false, false, true]);
+        assert_eq!(graph.schedule(), vec![2]);
+    }
+
+    #[test]
+    fn lifetimes_span_first_to_last_scheduled_use() {
+        let mut graph = RenderGraph::new();
+        let unused = transient(&mut graph);
+        let color = transient(&mut graph);
+        let mut dead = PassBuilder::new("Dead");
+        dead.write_color(unused, None);
+        add_pass(&mut graph, dead);
+        let mut scene = PassBuilder::new("Scene");
+        scene.write_color(color, None);
+        add_pass(&mut graph, scene);
+        add_pass(&mut graph, PassBuilder::new("Side Effects"));
+        add_present(&mut graph, color);
+
+        let order = graph.schedule();
+        let lifetimes = graph.texture_lifetimes(&order);
+        assert_eq!(lifetimes.first_use, vec![usize::MAX, 0]);
+        assert_eq!(lifetimes.last_use[color.0], 2);
+        assert_eq!(
+            lifetimes.usages[color.0],
+            wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING
+        );
+    }
+
+    #[test]
+    fn disjoint_lifetimes_share_a_texture() {
+        let mut pooled = Vec::new();
+        let slots = alias_transients(&mut pooled, &[(key(1), 0, 1), (key(1), 2, 3)]);
+        assert_eq!(slots, vec![0, 0]);
+        assert_eq!(pooled, vec![key(1)]);
+    }
+
+    #[test]
+    fn overlapping_lifetimes_get_separate_textures() {
+        // Used by the same pass at position 1
+        let mut pooled = Vec::new();
+        let slots = alias_transients(&mut pooled, &[(key(1), 0, 1), (key(1), 1, 2)]);
+        assert_eq!(slots, vec![0, 1]);
+    }
+
+    #[test]
+    fn different_keys_are_not_aliased() {
+        let mut pooled = Vec::new();
+        let slots = alias_transients(&mut pooled, &[(key(1), 0, 0), (key(2), 1, 1)]);
+        assert_eq!(slots, vec![0, 1]);
+        assert_eq!(pooled, vec![key(1), key(2)]);
+    }
+
+    #[test]
+    fn pooled_textures_are_reused_across_frames() {
+        let mut pooled = vec![key(2), key(1)];
+        let slots = alias_transients(&mut pooled, &[(key(1), 0, 0), (key(3), 1, 1)]);
+        assert_eq!(slots, vec![1, 2]);
+        assert_eq!(pooled, vec![key(2), key(1), key(3)]);
+    }
+}
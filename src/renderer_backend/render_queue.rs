@@ -0,0 +1,150 @@
+use std::ops::Range;
+
+use cgmath::MetricSpace;
+
+use crate::renderer_backend::{
+    instance::InstanceRaw,
+    scene::{MaterialId, MeshId},
+};
+
+// Index into the render pipelines owned by the renderer
+pub type PipelineId = usize;
+
+pub struct DrawItem {
+    pub mesh: MeshId,
+    pub material: MaterialId,
+    pub pipeline: PipelineId,
+    pub instances: Vec<InstanceRaw>,
+}
+
+/// One instanced draw. `instances` is the range of this batch inside the instance
+/// buffer produced by `RenderQueue::build_batches`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DrawBatch {
+    pub pipeline: PipelineId,
+    pub material: MaterialId,
+    pub mesh: MeshId,
+    pub instances: Range<u32>,
+    // Blended after the opaque geometry, these batches come last
+    pub transparent: bool,
+}
+
+/// Collects draw items for a frame, sorts them by pipeline, then material, then mesh
+/// so state changes are minimal, and merges items sharing all three into one batch.
+/// Transparent items are split into single instances sorted back to front instead,
+/// only neighbours in that order are merged, and only when the caller allows it.
+#[derive(Default)]
+pub struct RenderQueue {
+    items: Vec<DrawItem>,
+}
+
+impl RenderQueue {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn submit(&mut self, item: DrawItem) {
+        self.items.push(item);
+    }
+
+    pub fn submit_instance(
+        &mut self,
+        mesh: MeshId,
+        material: MaterialId,
+        pipeline: PipelineId,
+        instance: InstanceRaw,
+    ) {
+        self.items.push(DrawItem {
+            mesh,
+            material,
+            pipeline,
+            instances: vec![instance],
+        });
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.items.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.items.clear();
+    }
+
+    /// Drains the queue into a contiguous instance array and the batches drawing it.
+    /// Items whose material `is_transparent` are ordered by the distance of their
+    /// instance origin to `view_position`, farthest first. Without `merge_transparent`
+    /// each of them gets its own batch, for when the draw doesn't keep the order of
+    /// the instances inside a batch (GPU culling compacts them in any order).
+    pub fn build_batches(
+        &mut self,
+        view_position: cgmath::Point3<f32>,
+        is_transparent: impl Fn(MaterialId) -> bool,
+        merge_transparent: bool,
+    ) -> (Vec<InstanceRaw>, Vec<DrawBatch>) {
+        let (transparent_items, mut opaque_items): (Vec<_>, Vec<_>) = self
+            .items
+            .drain(..)
+            .partition(|item| is_transparent(item.material));
+        // Stable so instances keep their submission order inside a batch
+        opaque_items.sort_by_key(|item| (item.pipeline, item.material, item.mesh));
+
+        let mut transparent_instances = transparent_items
+            .into_iter()
+            .flat_map(|item| {
+                item.instances.into_iter().map(move |instance| {
+                    let origin = cgmath::Point3::new(
+                        instance.model[3][0],
+                        instance.model[3][1],
+                        instance.model[3][2],
+                    );
+                    let distance = origin.distance2(view_position);
+                    (distance, item.pipeline, item.material, item.mesh, instance)
+                })
+            })
+            .collect::<Vec<_>>();
+        transparent_instances.sort_by(|a, b| b.0.total_cmp(&a.0));
+
+        let opaque = opaque_items.into_iter().map(|item| {
+            let key = (item.pipeline, item.material, item.mesh, false);
+            (key, item.instances)
+        });
+        let transparent =
+            transparent_instances
+                .into_iter()
+                .map(|(_, pipeline, material, mesh, instance)| {
+                    ((pipeline, material, mesh, true), vec![instance])
+                });
+
+        let mut instances = Vec::new();
+        let mut batches: Vec<DrawBatch> = Vec::new();
+        for ((pipeline, material, mesh, transparent), item_instances) in opaque.chain(transparent) {
+            if item_instances.is_empty() {
+                continue;
+            }
+            let start = instances.len() as u32;
+            instances.extend(item_instances);
+            let end = instances.len() as u32;
+
+            match batches.last_mut() {
+                Some(batch)
+                    if batch.pipeline == pipeline
+                        && batch.material == material
+                        && batch.mesh == mesh
+                        && batch.transparent == transparent
+                        && (!transparent || merge_transparent) =>
+                {
+                    batch.instances.end = end;
+                }
+                _ => batches.push(DrawBatch {
+                    pipeline,
+                    material,
+                    mesh,
+                    instances: start..end,
+                    transparent,
+                }),
+            }
+        }
+
+        (instances, batches)
+    }
+}
@@ -0,0 +1,207 @@
+use cgmath::SquareMatrix;
+
+use crate::renderer_backend::{instance::InstanceRaw, render_queue::PipelineId};
+
+// Indices into the meshes and materials owned by the renderer
+pub type MeshId = usize;
+pub type MaterialId = usize;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct NodeId(usize);
+
+#[derive(Copy, Clone, Debug)]
+pub struct Transform {
+    pub translation: cgmath::Vector3<f32>,
+    pub rotation: cgmath::Quaternion<f32>,
+    pub scale: cgmath::Vector3<f32>,
+}
+
+impl Transform {
+    pub fn to_matrix(self) -> cgmath::Matrix4<f32> {
+        cgmath::Matrix4::from_translation(self.translation)
+            * cgmath::Matrix4::from(self.rotation)
+            * cgmath::Matrix4::from_nonuniform_scale(self.scale.x, self.scale.y, self.scale.z)
+    }
+}
+
+impl Default for Transform {
+    fn default() -> Self {
+        Self {
+            translation: cgmath::Vector3::new(0.0, 0.0, 0.0),
+            rotation: cgmath::Quaternion::new(1.0, 0.0, 0.0, 0.0),
+            scale: cgmath::Vector3::new(1.0, 1.0, 1.0),
+        }
+    }
+}
+
+pub struct Node {
+    pub name: String,
+    pub mesh: Option<MeshId>,
+    pub material: Option<MaterialId>,
+    // Pipeline the node is drawn with, `None` uses the renderer's default
+    pub pipeline: Option<PipelineId>,
+    local: Transform,
+    world: cgmath::Matrix4<f32>,
+    parent: Option<NodeId>,
+    children: Vec<NodeId>,
+    dirty: bool,
+}
+
+impl Node {
+    pub fn local_transform(&self) -> &Transform {
+        &self.local
+    }
+
+    /// World matrix as of the last `Scene::update_world_transforms`.
+    pub fn world_matrix(&self) -> cgmath::Matrix4<f32> {
+        self.world
+    }
+
+    pub fn parent(&self) -> Option<NodeId> {
+        self.parent
+    }
+
+    pub fn children(&self) -> &[NodeId] {
+        &self.children
+    }
+}
+
+/// Hierarchy of transforms. World matrices are cached per node and only recomputed
+/// for nodes whose local transform (or one of their ancestors') changed.
+#[derive(Default)]
+pub struct Scene {
+    nodes: Vec<Node>,
+    roots: Vec<NodeId>,
+}
+
+impl Scene {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn add_node(&mut self, name: &str, local: Transform, parent: Option<NodeId>) -> NodeId {
+        let id = NodeId(self.nodes.len());
+        self.nodes.push(Node {
+            name: name.to_string(),
+            mesh: None,
+            material: None,
+            pipeline: None,
+            local,
+            world: cgmath::Matrix4::identity(),
+            parent: None,
+            children: Vec::new(),
+            dirty: true,
+        });
+        self.roots.push(id);
+        if parent.is_some() {
+            self.set_parent(id, parent);
+        }
+        id
+    }
+
+    /// Adds a node that is drawn with `mesh` and `material`.
+    pub fn add_renderable(
+        &mut self,
+        name: &str,
+        local: Transform,
+        parent: Option<NodeId>,
+        mesh: MeshId,
+        material: MaterialId,
+    ) -> NodeId {
+        let id = self.add_node(name, local, parent);
+        let node = self.node_mut(id);
+        node.mesh = Some(mesh);
+        node.material = Some(material);
+        id
+    }
+
+    pub fn node(&self, id: NodeId) -> &Node {
+        &self.nodes[id.0]
+    }
+
+    pub fn node_mut(&mut self, id: NodeId) -> &mut Node {
+        &mut self.nodes[id.0]
+    }
+
+    pub fn find(&self, name: &str) -> Option<NodeId> {
+        self.nodes
+            .iter()
+            .position(|node| node.name == name)
+            .map(NodeId)
+    }
+
+    pub fn set_transform(&mut self, id: NodeId, local: Transform) {
+        let node = self.node_mut(id);
+        node.local = local;
+        node.dirty = true;
+    }
+
+    pub fn update_transform(&mut self, id: NodeId, update: impl FnOnce(&mut Transform)) {
+        let node = self.node_mut(id);
+        update(&mut node.local);
+        node.dirty = true;
+    }
+
+    /// Re-parents `child`, `None` makes it a root. Parenting a node to one of its own
+    /// descendants would create a cycle and is ignored.
+    pub fn set_parent(&mut self, child: NodeId, parent: Option<NodeId>) {
+        if let Some(parent) = parent {
+            let mut ancestor = Some(parent);
+            while let Some(id) = ancestor {
+                if id == child {
+                    return;
+                }
+                ancestor = self.node(id).parent;
+            }
+        }
+
+        match self.node(child).parent {
+            Some(old_parent) => self.node_mut(old_parent).children.retain(|&id| id != child),
+            None => self.roots.retain(|&id| id != child),
+        }
+        match parent {
+            Some(new_parent) => self.node_mut(new_parent).children.push(child),
+            None => self.roots.push(child),
+        }
+
+        let node = self.node_mut(child);
+        node.parent = parent;
+        node.dirty = true;
+    }
+
+    /// Recomputes the world matrices of dirty nodes and their descendants.
+    /// Returns true if any world matrix changed.
+    pub fn update_world_transforms(&mut self) -> bool {
+        let mut changed = false;
+        let mut stack = self
+            .roots
+            .iter()
+            .map(|&id| (id, cgmath::Matrix4::identity(), false))
+            .collect::<Vec<_>>();
+
+        while let Some((id, parent_world, parent_changed)) = stack.pop() {
+            let node = &mut self.nodes[id.0];
+            let recompute = node.dirty || parent_changed;
+            if recompute {
+                node.world = parent_world * node.local.to_matrix();
+                node.dirty = false;
+                changed = true;
+            }
+            let world = node.world;
+            stack.extend(node.children.iter().map(|&child| (child, world, recompute)));
+        }
+
+        changed
+    }
+
+    /// Nodes that reference a mesh, in insertion order.
+    pub fn renderables(&self) -> impl Iterator<Item = &Node> {
+        self.nodes.iter().filter(|node| node.mesh.is_some())
+    }
+
+    pub fn instances(&self) -> Vec<InstanceRaw> {
+        self.renderables()
+            .map(|node| InstanceRaw::from_model(node.world))
+            .collect()
+    }
+}
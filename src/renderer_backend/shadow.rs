@@ -0,0 +1,457 @@
+use std::mem;
+
+use cgmath::{EuclideanSpace, InnerSpace, Matrix4, Point3, SquareMatrix, Transform, Vector3};
+
+use super::{
+    camera::{Camera, OPENGL_TO_WGPU_MATRIX},
+    depth::DEPTH_FORMAT,
+    instance::{Instance, InstanceStorage, InstancingMode},
+    light::Light,
+    mesh_builder::{Mesh, Vertex},
+    pipeline_builder,
+    render_queue::DrawBatch,
+};
+
+pub const SHADOW_MAP_SIZE: u32 = 1024;
+pub const CASCADE_COUNT: usize = 4;
+pub const MAX_SPOT_SHADOWS: usize = 4;
+// Cascades of the first directional light, then one layer per spot light
+pub const SHADOW_LAYERS: usize = CASCADE_COUNT + MAX_SPOT_SHADOWS;
+// Cascade splits are packed four per vec4 in the uniform
+pub const CASCADE_SPLIT_VECTORS: usize = CASCADE_COUNT.div_ceil(4);
+
+#[derive(Copy, Clone, Debug)]
+pub struct ShadowSettings {
+    // Cascades cover the view from the near plane up to this distance
+    pub shadow_distance: f32,
+    // Blend between uniform (0.0) and logarithmic (1.0) cascade splits
+    pub split_lambda: f32,
+    // Casters this far behind a cascade towards the light still cast shadows
+    pub caster_extension: f32,
+    // PCF kernel is (2 * radius + 1)^2 taps
+    pub pcf_radius: u32,
+    pub depth_bias: f32,
+    // World space offset along the normal before the shadow lookup
+    pub normal_offset: f32,
+    pub spot_range: f32,
+}
+
+impl Default for ShadowSettings {
+    fn default() -> Self {
+        Self {
+            shadow_distance: 50.0,
+            split_lambda: 0.75,
+            caster_extension: 50.0,
+            pcf_radius: 1,
+            depth_bias: 0.0005,
+            normal_offset: 0.02,
+            spot_range: 50.0,
+        }
+    }
+}
+
+/// Same layout as `Shadows` in shader.wgsl
+#[repr(C)]
+#[derive(Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct ShadowUniform {
+    pub light_view_proj: [[[f32; 4]; 4]; SHADOW_LAYERS],
+    // Far view depth of each cascade
+    pub cascade_splits: [[f32; 4]; CASCADE_SPLIT_VECTORS],
+    // xyz is the camera forward axis, used to measure view depth
+    pub camera_forward: [f32; 4],
+    pub pcf_radius: u32,
+    pub depth_bias: f32,
+    pub normal_offset: f32,
+    pub cascade_count: u32,
+}
+
+/// Shadow map resources sampled by the lighting shader.
+#[derive(Clone)]
+pub struct ShadowResources {
+    pub array_view: wgpu::TextureView,
+    pub sampler: wgpu::Sampler,
+    pub uniform_buffer: wgpu::Buffer,
+}
+
+/// Depth-only rendering of the scene from each shadow caster into one layer of
+/// a texture array. The directional light uses cascades fitted to slices of the
+/// camera frustum, spot lights a perspective projection over their outer cone.
+pub struct ShadowMaps {
+    pub settings: ShadowSettings,
+    pub texture: wgpu::Texture,
+    pub layer_views: Vec<wgpu::TextureView>,
+    pub resources: ShadowResources,
+    pub uniform: ShadowUniform,
+    // Layers rendered this frame
+    pub active_layers: Vec<usize>,
+    pub pipeline: wgpu::RenderPipeline,
+    pub instancing_mode: InstancingMode,
+    pass_buffers: Vec<wgpu::Buffer>,
+    pass_bind_groups: Vec<wgpu::BindGroup>,
+}
+
+impl ShadowMaps {
+    pub fn new(
+        device: &wgpu::Device,
+        instancing_mode: InstancingMode,
+        instance_storage: &InstanceStorage,
+    ) -> Self {
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("Shadow Maps"),
+            size: wgpu::Extent3d {
+                width: SHADOW_MAP_SIZE,
+                height: SHADOW_MAP_SIZE,
+                depth_or_array_layers: SHADOW_LAYERS as u32,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: DEPTH_FORMAT,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
+            view_formats: &[],
+        });
+        let layer_views = (0..SHADOW_LAYERS as u32)
+            .map(|layer| {
+                texture.create_view(&wgpu::TextureViewDescriptor {
+                    label: Some("Shadow Map Layer"),
+                    dimension: Some(wgpu::TextureViewDimension::D2),
+                    base_array_layer: layer,
+                    array_layer_count: Some(1),
+                    ..Default::default()
+                })
+            })
+            .collect();
+        let array_view = texture.create_view(&wgpu::TextureViewDescriptor {
+            dimension: Some(wgpu::TextureViewDimension::D2Array),
+            ..Default::default()
+        });
+
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("Shadow Sampler"),
+            address_mode_u: wgpu::AddressMode::ClampToEdge,
+            address_mode_v: wgpu::AddressMode::ClampToEdge,
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            compare: Some(wgpu::CompareFunction::LessEqual),
+            ..Default::default()
+        });
+
+        let uniform_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Shadow Uniform Buffer"),
+            size: mem::size_of::<ShadowUniform>() as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        let pass_bind_group_layout =
+            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                entries: &[wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::VERTEX,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                }],
+                label: Some("shadow_pass_bind_group_layout"),
+            });
+        let pass_buffers = (0..SHADOW_LAYERS)
+            .map(|_| {
+                device.create_buffer(&wgpu::BufferDescriptor {
+                    label: Some("Shadow Pass Buffer"),
+                    size: mem::size_of::<[[f32; 4]; 4]>() as wgpu::BufferAddress,
+                    usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+                    mapped_at_creation: false,
+                })
+            })
+            .collect::<Vec<_>>();
+        let pass_bind_groups = pass_buffers
+            .iter()
+            .map(|buffer| {
+                device.create_bind_group(&wgpu::BindGroupDescriptor {
+                    layout: &pass_bind_group_layout,
+                    entries: &[wgpu::BindGroupEntry {
+                        binding: 0,
+                        resource: buffer.as_entire_binding(),
+                    }],
+                    label: Some("shadow_pass_bind_group"),
+                })
+            })
+            .collect();
+
+        // Shadow casters outside the camera frustum still matter, so the
+        // unculled instance data is used on the storage buffer path
+        let vertex_entry = match instancing_mode {
+            InstancingMode::VertexBuffer => "vs_shadow",
+            InstancingMode::StorageBuffer => "vs_shadow_storage",
+        };
+        let mut pipeline_builder = pipeline_builder::PipelineBuilder::new(
+            device,
+            pipeline_builder::ShaderSource::Builtin("shaders/shadow.wgsl"),
+            vertex_entry,
+            "",
+            DEPTH_FORMAT,
+        );
+        pipeline_builder.add_vertex_buffer_layout(Vertex::desc());
+        pipeline_builder.add_bind_group_layout(&pass_bind_group_layout);
+        match instancing_mode {
+            InstancingMode::VertexBuffer => {
+                pipeline_builder.add_vertex_buffer_layout(Instance::desc());
+            }
+            InstancingMode::StorageBuffer => {
+                pipeline_builder.add_bind_group_layout(&instance_storage.bind_group_layout);
+            }
+        }
+        pipeline_builder.set_depth_stencil(DEPTH_FORMAT, true, wgpu::CompareFunction::Less);
+        // Slope scaled bias against shadow acne, single sided quads need both faces
+        pipeline_builder.set_depth_bias(wgpu::DepthBiasState {
+            constant: 2,
+            slope_scale: 2.0,
+            clamp: 0.0,
+        });
+        pipeline_builder.set_cull_mode(None);
+        pipeline_builder.set_depth_only();
+        let pipeline = pipeline_builder.build_pipeline("Shadow Pipeline");
+
+        Self {
+            settings: ShadowSettings::default(),
+            texture,
+            layer_views,
+            resources: ShadowResources {
+                array_view,
+                sampler,
+                uniform_buffer,
+            },
+            uniform: bytemuck::Zeroable::zeroed(),
+            active_layers: Vec::new(),
+            pipeline,
+            instancing_mode,
+            pass_buffers,
+            pass_bind_groups,
+        }
+    }
+
+    /// Fits the light-space matrices for this frame and returns the first
+    /// shadow layer of each light, -1 for lights without a shadow map.
+    pub fn update(&mut self, queue: &wgpu::Queue, camera: &Camera, lights: &[Light]) -> Vec<i32> {
+        let settings = self.settings;
+        let mut uniform = ShadowUniform {
+            pcf_radius: settings.pcf_radius,
+            depth_bias: settings.depth_bias,
+            normal_offset: settings.normal_offset,
+            cascade_count: CASCADE_COUNT as u32,
+            camera_forward: (camera.target - camera.eye).normalize().extend(0.0).into(),
+            ..bytemuck::Zeroable::zeroed()
+        };
+        self.active_layers.clear();
+
+        let mut has_directional = false;
+        let mut spot_count = 0;
+        let shadow_layers = lights
+            .iter()
+            .map(|light| match *light {
+                Light::Directional { direction, .. } if !has_directional => {
+                    has_directional = true;
+                    let splits = cascade_splits(camera, &settings);
+                    let mut near = camera.znear;
+                    for (cascade, far) in splits.into_iter().enumerate() {
+                        uniform.light_view_proj[cascade] =
+                            cascade_matrix(camera, direction, near, far, &settings).into();
+                        uniform.cascade_splits[cascade / 4][cascade % 4] = far;
+                        self.active_layers.push(cascade);
+                        near = far;
+                    }
+                    0
+                }
+                Light::Spot {
+                    position,
+                    direction,
+                    outer_cone,
+                    ..
+                } if spot_count < MAX_SPOT_SHADOWS => {
+                    let layer = CASCADE_COUNT + spot_count;
+                    spot_count += 1;
+                    let view = Matrix4::look_at_rh(
+                        Point3::from_vec(position),
+                        Point3::from_vec(position + direction),
+                        up_vector(direction),
+                    );
+                    let projection =
+                        cgmath::perspective(outer_cone * 2.0, 1.0, 0.1, settings.spot_range);
+                    uniform.light_view_proj[layer] =
+                        (OPENGL_TO_WGPU_MATRIX * projection * view).into();
+                    self.active_layers.push(layer);
+                    layer as i32
+                }
+                _ => -1,
+            })
+            .collect();
+
+        for &layer in &self.active_layers {
+            queue.write_buffer(
+                &self.pass_buffers[layer],
+                0,
+                bytemuck::cast_slice(&[uniform.light_view_proj[layer]]),
+            );
+        }
+        queue.write_buffer(
+            &self.resources.uniform_buffer,
+            0,
+            bytemuck::cast_slice(&[uniform]),
+        );
+        self.uniform = uniform;
+        shadow_layers
+    }
+
+    /// Renders every batch into the active shadow layers. Uses the unculled
+    /// instance ranges of `batches`, so it ignores the camera frustum.
+    pub fn render(
+        &self,
+        command_encoder: &mut wgpu::CommandEncoder,
+        instance_storage: &InstanceStorage,
+        meshes: &[Mesh],
+        batches: &[DrawBatch],
+    ) {
+        for &layer in &self.active_layers {
+            let mut render_pass = command_encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("Shadow Pass"),
+                color_attachments: &[],
+                depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                    view: &self.layer_views[layer],
+                    depth_ops: Some(wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(1.0),
+                        store: wgpu::StoreOp::Store,
+                    }),
+                    stencil_ops: None,
+                }),
+                occlusion_query_set: None,
+                timestamp_writes: None,
+            });
+
+            render_pass.set_pipeline(&self.pipeline);
+            render_pass.set_bind_group(0, &self.pass_bind_groups[layer], &[]);
+            match self.instancing_mode {
+                InstancingMode::VertexBuffer => {
+                    render_pass.set_vertex_buffer(1, instance_storage.buffer.slice(..));
+                }
+                InstancingMode::StorageBuffer => {
+                    render_pass.set_bind_group(1, &instance_storage.bind_group, &[]);
+                }
+            }
+            for batch in batches {
+                let mesh = &meshes[batch.mesh];
+                render_pass.set_vertex_buffer(0, mesh.vertex_buffer.slice(..));
+                render_pass
+                    .set_index_buffer(mesh.index_buffer.slice(..), wgpu::IndexFormat::Uint32);
+                render_pass.draw_indexed(0..mesh.num_indices as u32, 0, batch.instances.clone());
+            }
+        }
+    }
+}
+
+// Practical split scheme, mixing logarithmic and uniform distributions
+fn cascade_splits(camera: &Camera, settings: &ShadowSettings) -> [f32; CASCADE_COUNT] {
+    let near = camera.znear;
+    let far = settings.shadow_distance.min(camera.zfar);
+    std::array::from_fn(|i| {
+        let ratio = (i + 1) as f32 / CASCADE_COUNT as f32;
+        let logarithmic = near * (far / near).powf(ratio);
+        let uniform = near + (far - near) * ratio;
+        settings.split_lambda * logarithmic + (1.0 - settings.split_lambda) * uniform
+    })
+}
+
+// Orthographic projection around the bounding sphere of one frustum slice.
+// The sphere keeps the extent constant while the camera rotates, and snapping
+// to whole texels stops the shadow edges from shimmering as it moves.
+fn cascade_matrix(
+    camera: &Camera,
+    direction: Vector3<f32>,
+    near: f32,
+    far: f32,
+    settings: &ShadowSettings,
+) -> Matrix4<f32> {
+    let mut slice = camera.clone();
+    slice.znear = near;
+    slice.zfar = far;
+    let inverse = slice
+        .build_view_projection_matrix()
+        .invert()
+        .unwrap_or(Matrix4::identity());
+
+    let mut corners = Vec::with_capacity(8);
+    for x in [-1.0, 1.0] {
+        for y in [-1.0, 1.0] {
+            for z in [0.0, 1.0] {
+                corners.push(inverse.transform_point(Point3::new(x, y, z)));
+            }
+        }
+    }
+    let center = Point3::centroid(&corners);
+    let radius = corners
+        .iter()
+        .map(|corner| (corner - center).magnitude())
+        .fold(0.0, f32::max)
+        .ceil();
+
+    let direction = direction.normalize();
+    let light_view = Matrix4::look_at_rh(
+        Point3::origin() - direction,
+        Point3::origin(),
+        up_vector(direction),
+    );
+    let center = light_view.transform_point(center);
+    let texel = 2.0 * radius / SHADOW_MAP_SIZE as f32;
+    let x = (center.x / texel).floor() * texel;
+    let y = (center.y / texel).floor() * texel;
+    let projection = cgmath::ortho(
+        x - radius,
+        x + radius,
+        y - radius,
+        y + radius,
+        -center.z - radius - settings.caster_extension,
+        -center.z + radius,
+    );
+    OPENGL_TO_WGPU_MATRIX * projection * light_view
+}
+
+fn up_vector(direction: Vector3<f32>) -> Vector3<f32> {
+    if direction.normalize().y.abs() > 0.99 {
+        Vector3::unit_z()
+    } else {
+        Vector3::unit_y()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Value of a `u32` constant declared in `module`
+    fn wgsl_constant(module: &naga::Module, name: &str) -> u32 {
+        let (_, constant) = module
+            .constants
+            .iter()
+            .find(|(_, constant)| constant.name.as_deref() == Some(name))
+            .unwrap_or_else(|| panic!("{} is not declared", name));
+        match module.global_expressions[constant.init] {
+            naga::Expression::Literal(naga::Literal::U32(value)) => value,
+            ref init => panic!("{} is not a u32 literal: {:?}", name, init),
+        }
+    }
+
+    #[test]
+    fn shader_constants_match() {
+        let module = naga::front::wgsl::parse_str(include_str!("../shaders/shader.wgsl")).unwrap();
+        assert_eq!(
+            wgsl_constant(&module, "CASCADE_COUNT"),
+            CASCADE_COUNT as u32
+        );
+        assert_eq!(
+            wgsl_constant(&module, "SHADOW_LAYERS"),
+            SHADOW_LAYERS as u32
+        );
+    }
+}
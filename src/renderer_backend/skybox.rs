@@ -0,0 +1,190 @@
+use wgpu::util::DeviceExt;
+
+use super::{
+    camera::CameraBinding, depth::DEPTH_FORMAT, environment::Environment, pipeline_builder,
+};
+
+/// Cubemap drawn behind all geometry. The fullscreen triangle sits on the far
+/// plane and is depth tested with `LessEqual`, so it only fills uncovered pixels.
+pub struct Skybox {
+    pub view: wgpu::TextureView,
+    pub sampler: wgpu::Sampler,
+    pub bind_group: wgpu::BindGroup,
+    pub bind_group_layout: wgpu::BindGroupLayout,
+    pub pipeline: wgpu::RenderPipeline,
+    // For the multisampled forward scene pass, the deferred path draws single sampled
+    pub multisampled_pipeline: Option<(u32, wgpu::RenderPipeline)>,
+}
+
+impl Skybox {
+    /// Six face images in +X, -X, +Y, -Y, +Z, -Z order, paths relative to `src/`.
+    pub fn from_faces(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        format: wgpu::TextureFormat,
+        sample_count: u32,
+        camera_binding: &CameraBinding,
+        face_paths: [&str; 6],
+    ) -> Self {
+        let mut dimensions = (0, 0);
+        let mut pixels = Vec::new();
+        for face_path in face_paths {
+            let full_path = format!("src/{}", face_path);
+            let texture_bytes = std::fs::read(full_path).unwrap();
+            let rgba = image::load_from_memory(&texture_bytes).unwrap().to_rgba8();
+            if pixels.is_empty() {
+                dimensions = rgba.dimensions();
+            }
+            assert_eq!(
+                rgba.dimensions(),
+                dimensions,
+                "Skybox faces must share the same size"
+            );
+            pixels.extend_from_slice(&rgba);
+        }
+
+        let texture = device.create_texture_with_data(
+            queue,
+            &wgpu::TextureDescriptor {
+                label: Some("Skybox Texture"),
+                size: wgpu::Extent3d {
+                    width: dimensions.0,
+                    height: dimensions.1,
+                    depth_or_array_layers: 6,
+                },
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: wgpu::TextureDimension::D2,
+                format: wgpu::TextureFormat::Rgba8UnormSrgb,
+                usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+                view_formats: &[],
+            },
+            wgpu::util::TextureDataOrder::LayerMajor,
+            &pixels,
+        );
+        let view = texture.create_view(&wgpu::TextureViewDescriptor {
+            dimension: Some(wgpu::TextureViewDimension::Cube),
+            ..Default::default()
+        });
+
+        Self::new(device, format, sample_count, camera_binding, view)
+    }
+
+    /// Reuses the cubemap converted from an equirectangular image for lighting.
+    pub fn from_environment(
+        device: &wgpu::Device,
+        format: wgpu::TextureFormat,
+        sample_count: u32,
+        camera_binding: &CameraBinding,
+        environment: &Environment,
+    ) -> Self {
+        Self::new(
+            device,
+            format,
+            sample_count,
+            camera_binding,
+            environment.environment_view.clone(),
+        )
+    }
+
+    fn new(
+        device: &wgpu::Device,
+        format: wgpu::TextureFormat,
+        sample_count: u32,
+        camera_binding: &CameraBinding,
+        view: wgpu::TextureView,
+    ) -> Self {
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("Skybox Sampler"),
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
+        });
+
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        multisampled: false,
+                        view_dimension: wgpu::TextureViewDimension::Cube,
+                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                    count: None,
+                },
+            ],
+            label: Some("skybox_bind_group_layout"),
+        });
+
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Sampler(&sampler),
+                },
+            ],
+            label: Some("skybox_bind_group"),
+        });
+
+        let build_pipeline = |sample_count| {
+            let mut pipeline_builder = pipeline_builder::PipelineBuilder::new(
+                device,
+                pipeline_builder::ShaderSource::Builtin("shaders/skybox.wgsl"),
+                "vs_skybox",
+                "fs_skybox",
+                format,
+            );
+            pipeline_builder.add_bind_group_layout(&bind_group_layout);
+            pipeline_builder.add_bind_group_layout(&camera_binding.bind_group_layout);
+            pipeline_builder.set_depth_stencil(
+                DEPTH_FORMAT,
+                false,
+                wgpu::CompareFunction::LessEqual,
+            );
+            pipeline_builder.set_cull_mode(None);
+            pipeline_builder.set_multisample(sample_count, false);
+            pipeline_builder.build_pipeline("Skybox Pipeline")
+        };
+        let pipeline = build_pipeline(1);
+        let multisampled_pipeline =
+            (sample_count > 1).then(|| (sample_count, build_pipeline(sample_count)));
+
+        Self {
+            view,
+            sampler,
+            bind_group,
+            bind_group_layout,
+            pipeline,
+            multisampled_pipeline,
+        }
+    }
+
+    // `sample_count` of the attachments in `render_pass`
+    pub fn draw(
+        &self,
+        render_pass: &mut wgpu::RenderPass,
+        camera_binding: &CameraBinding,
+        sample_count: u32,
+    ) {
+        let pipeline = match &self.multisampled_pipeline {
+            Some((count, pipeline)) if *count == sample_count => pipeline,
+            _ => &self.pipeline,
+        };
+        render_pass.set_pipeline(pipeline);
+        render_pass.set_bind_group(0, &self.bind_group, &[]);
+        render_pass.set_bind_group(1, &camera_binding.bind_group, &[]);
+        render_pass.draw(0..3, 0..1);
+    }
+}
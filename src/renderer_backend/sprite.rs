@@ -0,0 +1,200 @@
+use std::ops::Range;
+
+use cgmath::{Deg, Matrix4, Vector3};
+
+use super::{
+    instance::InstanceStorage,
+    material::Material,
+    pipeline_builder::ShaderSource,
+    quad::{InstancedQuad, PixelCamera, QuadInstance, quad_model},
+};
+
+pub type SpriteTextureId = usize;
+
+/// A textured quad in pixel space. Its size is the pixel size of `uv_rect` in the
+/// texture times `scale`, placed so the `pivot` lands on `position`.
+#[derive(Copy, Clone, Debug)]
+pub struct Sprite {
+    pub texture: SpriteTextureId,
+    // Logical pixels from the top-left corner of the view, y down
+    pub position: [f32; 2],
+    // Clockwise on screen, around the pivot
+    pub rotation: Deg<f32>,
+    // Negative values mirror the sprite
+    pub scale: [f32; 2],
+    // Normalized point of the sprite, (0, 0) is the top-left corner and (1, 1) the bottom-right
+    pub pivot: [f32; 2],
+    // Normalized offset and size in the texture, for sprite sheets and atlases
+    pub uv_rect: [f32; 4],
+    // Linear RGBA multiplied with the texture
+    pub tint: [f32; 4],
+    // Higher is drawn on top
+    pub z_order: i32,
+}
+
+impl Sprite {
+    // The whole texture at its pixel size, centered on `position`
+    pub fn new(texture: SpriteTextureId, position: [f32; 2]) -> Self {
+        Self {
+            texture,
+            position,
+            rotation: Deg(0.0),
+            scale: [1.0, 1.0],
+            pivot: [0.5, 0.5],
+            uv_rect: [0.0, 0.0, 1.0, 1.0],
+            tint: [1.0, 1.0, 1.0, 1.0],
+            z_order: 0,
+        }
+    }
+}
+
+// Consecutive instances sharing a texture, drawn with one instanced call
+struct SpriteBatch {
+    texture: SpriteTextureId,
+    instances: Range<u32>,
+}
+
+/// Batches sprites by texture into instanced draws of a unit quad, in z-order,
+/// through an orthographic camera in logical pixels that scrolls with
+/// `camera_position`. Sprites are drawn over the post-processed image, below
+/// HUD text and the debug UI. Submit them every frame with `sprite`, they are
+/// dropped by `end_frame`.
+pub struct SpriteBatcher {
+    pub textures: Vec<Material>,
+    // World pixel shown at the top-left corner of the surface
+    pub camera_position: [f32; 2],
+    sprites: Vec<Sprite>,
+    batches: Vec<SpriteBatch>,
+    quad: InstancedQuad,
+    pipeline: wgpu::RenderPipeline,
+    camera: PixelCamera,
+    // Sorted like `sprites`, each batch is a range of them
+    instances: InstanceStorage,
+}
+
+impl SpriteBatcher {
+    pub fn new(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        surface_format: wgpu::TextureFormat,
+        logical_size: (f32, f32),
+        material_layout: &wgpu::BindGroupLayout,
+    ) -> Self {
+        let camera = PixelCamera::new(device, logical_size);
+
+        // Straight alpha blending, sprites are sorted by z-order rather than depth tested
+        let mut pipeline_builder = InstancedQuad::pipeline_builder(
+            device,
+            ShaderSource::Builtin("shaders/sprite.wgsl"),
+            "vs_sprite",
+            "fs_sprite",
+            surface_format,
+            material_layout,
+            &camera.binding.bind_group_layout,
+        );
+        let pipeline = pipeline_builder.build_pipeline("Sprite Pipeline");
+
+        Self {
+            textures: Vec::new(),
+            camera_position: [0.0, 0.0],
+            sprites: Vec::new(),
+            batches: Vec::new(),
+            quad: InstancedQuad::new(device),
+            pipeline,
+            camera,
+            instances: InstancedQuad::create_instances(device, queue),
+        }
+    }
+
+    // Any material works, its base color texture and factor are used
+    pub fn add_texture(&mut self, material: Material) -> SpriteTextureId {
+        self.textures.push(material);
+        self.textures.len() - 1
+    }
+
+    pub fn texture_size(&self, texture: SpriteTextureId) -> [f32; 2] {
+        let (width, height) = self.textures[texture].dimensions;
+        [width as f32, height as f32]
+    }
+
+    pub fn sprite(&mut self, sprite: Sprite) {
+        self.sprites.push(sprite);
+    }
+
+    /// Sorts this frame's sprites by z-order and texture and uploads their instances.
+    /// Sprites with the same z-order and texture keep the order they were added in.
+    pub fn prepare(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        logical_size: (f32, f32),
+    ) {
+        self.camera
+            .update(queue, logical_size, self.camera_position);
+
+        self.sprites
+            .sort_by_key(|sprite| (sprite.z_order, sprite.texture));
+        self.batches.clear();
+        let mut instances = Vec::with_capacity(self.sprites.len());
+        for sprite in &self.sprites {
+            let index = instances.len() as u32;
+            match self.batches.last_mut() {
+                Some(batch) if batch.texture == sprite.texture => batch.instances.end = index + 1,
+                _ => self.batches.push(SpriteBatch {
+                    texture: sprite.texture,
+                    instances: index..index + 1,
+                }),
+            }
+            instances.push(self.instance(sprite));
+        }
+        self.instances.update(device, queue, &instances);
+    }
+
+    fn instance(&self, sprite: &Sprite) -> QuadInstance {
+        let [width, height] = self.texture_size(sprite.texture);
+        // Negative scales mirror the quad, and the pivot offset with it
+        let size = [
+            width * sprite.uv_rect[2] * sprite.scale[0],
+            height * sprite.uv_rect[3] * sprite.scale[1],
+        ];
+        // Centered so the pivot sits at the origin, which rotation keeps in place
+        let center = Vector3::new(
+            size[0] * (0.5 - sprite.pivot[0]),
+            size[1] * (0.5 - sprite.pivot[1]),
+            0.0,
+        );
+        let model =
+            Matrix4::from_translation(Vector3::new(sprite.position[0], sprite.position[1], 0.0))
+                * Matrix4::from_angle_z(sprite.rotation)
+                * quad_model(center, size);
+        QuadInstance {
+            model: model.into(),
+            uv_rect: sprite.uv_rect,
+            color: sprite.tint,
+        }
+    }
+
+    pub fn has_sprites(&self) -> bool {
+        !self.batches.is_empty()
+    }
+
+    // Into the surface, after post-processing
+    pub fn draw(&self, render_pass: &mut wgpu::RenderPass) {
+        if self.batches.is_empty() {
+            return;
+        }
+        render_pass.set_pipeline(&self.pipeline);
+        render_pass.set_bind_group(1, &self.camera.binding.bind_group, &[]);
+        self.quad.bind(render_pass, &self.instances);
+        // Only the texture changes between batches
+        for batch in &self.batches {
+            render_pass.set_bind_group(0, &self.textures[batch.texture].bind_group, &[]);
+            self.quad.draw(render_pass, batch.instances.clone());
+        }
+    }
+
+    // Clears the queue for the next frame, call after the frame is submitted
+    pub fn end_frame(&mut self) {
+        self.sprites.clear();
+    }
+}
@@ -1,34 +1,153 @@
-use cgmath::Rotation3;
-use renderer_backend::instance::Instance;
-use wgpu::util::BufferInitDescriptor;
-use wgpu::util::DeviceExt;
+use std::{collections::HashMap, ops::Range, path::Path, rc::Rc};
+
+use wgpu::rwh::{HasDisplayHandle, HasWindowHandle};
+
+use renderer_backend::instance::{Instance, InstanceRaw, InstanceStorage, InstancingMode};
 
 use crate::renderer_backend::{
     self,
-    material::{self, Material},
-    mesh_builder::{self, Mesh, Vertex},
-    pipeline_builder,
+    camera::{Camera, CameraBinding, Projection},
+    capture::{CaptureFormat, FrameCapture},
+    culling::{CullBatch, CullingMode, Frustum, InstanceCuller},
+    debug_draw::DebugDraw,
+    deferred::{DeferredLighting, GBUFFER_FORMATS, RenderPath},
+    depth::DEPTH_FORMAT,
+    environment::{Environment, PREFILTERED_MIP_LEVELS},
+    light::{Light, LightBinding, LightingUniform},
+    material::{AlphaMode, Material},
+    mesh_builder::{Mesh, Vertex},
+    pipeline_builder::{self, ShaderSource},
+    post_process::{self, HDR_FORMAT, PostProcessor},
+    render_graph::{PassBuilder, RenderGraph, TextureDesc, TextureSize, TransientPool},
+    render_queue::{DrawBatch, DrawItem, PipelineId, RenderQueue},
+    scene::{MaterialId, MeshId, Scene},
+    shadow::ShadowMaps,
+    skybox::Skybox,
+    sprite::SpriteBatcher,
+    text::TextRenderer,
+    transparency::{OIT_BLEND_STATES, OIT_CLEAR, OIT_FORMATS, OitCompositor, TransparencyMode},
+    ui::UiOverlay,
+    window::RenderWindow,
 };
 
-pub struct State<'a> {
-    pub window: &'a mut glfw::Window,
+pub struct State {
     pub device: wgpu::Device,
     pub queue: wgpu::Queue,
-    pub surface: wgpu::Surface<'a>,
+    pub surface: wgpu::Surface<'static>,
+    // Sized in framebuffer pixels, see `RenderWindow::size`
     pub config: wgpu::SurfaceConfiguration,
-    pub render_pipeline: wgpu::RenderPipeline,
-    pub mesh: Mesh,
-    pub material: Material,
+    // Physical pixels per logical pixel of the window, UI and text are laid out in
+    // logical pixels and scaled by it
+    pub scale_factor: f32,
+    // Supported by the surface, see `set_present_mode`
+    pub present_modes: Vec<wgpu::PresentMode>,
+    // Transient attachments of the render graph, reused across frames
+    pub transient_pool: TransientPool,
+    // Background when no skybox is set
+    pub clear_color: wgpu::Color,
+    pub skybox: Option<Skybox>,
+    // Scene passes render into its HDR target, the chain then writes the surface
+    pub post_processor: PostProcessor,
+    // Index 0 of each list is the default used by `instances` and scene nodes,
+    // meshes and materials are added by the application
+    pub pipeline_sources: Vec<PipelineSource>,
+    // Variants of the batch pipelines, built the first time a frame needs them
+    pub pipeline_cache: HashMap<PipelineKey, wgpu::RenderPipeline>,
+    // Sample count of the forward path, the deferred path is single sampled
+    pub msaa_samples: u32,
+    pub transparency_mode: TransparencyMode,
+    pub oit_compositor: OitCompositor,
+    pub meshes: Vec<Mesh>,
+    pub materials: Vec<Material>,
+    // Shared by every material, the batch pipelines are built against it
+    pub material_bind_group_layout: wgpu::BindGroupLayout,
+    // Flat instances, submitted with the default mesh, material and pipeline
     pub instances: Vec<Instance>,
-    pub instances_buffer: wgpu::Buffer,
+    pub scene: Scene,
+    // Extra draw items for the next frame, drained by `render`
+    pub render_queue: RenderQueue,
+    pub instance_storage: InstanceStorage,
+    pub instancing_mode: InstancingMode,
+    // Only available on the storage buffer path
+    pub instance_culler: Option<InstanceCuller>,
+    pub camera: Camera,
+    pub camera_binding: CameraBinding,
+    pub lights: Vec<Light>,
+    // light_count is filled in from `lights` every frame
+    pub lighting: LightingUniform,
+    pub light_binding: LightBinding,
+    pub shadow_maps: ShadowMaps,
+    pub render_path: RenderPath,
+    // Replaces the batch pipelines when drawing into the G-buffer
+    pub gbuffer_pipeline: wgpu::RenderPipeline,
+    pub deferred_lighting: DeferredLighting,
+    pub frame_capture: FrameCapture,
+    // Lines, boxes, spheres, axes and grids drawn on top of the scene
+    pub debug_draw: DebugDraw,
+    // Screen and world space text, fonts are added by the application
+    pub text: TextRenderer,
+    // Textured quads in pixel space, batched by texture
+    pub sprites: SpriteBatcher,
+    // egui windows drawn over the final image
+    pub ui: UiOverlay,
+    // Last so the surface is dropped before the window it was created from
+    pub window: Rc<dyn RenderWindow>,
+}
+
+// The storage buffer path is required for frustum culling
+const INSTANCING_MODE: InstancingMode = InstancingMode::StorageBuffer;
+const FRUSTUM_CULLING: bool = true;
+
+const MSAA_SAMPLES: u32 = 4;
+
+// Pipelines registered by `State::new`
+pub const PHONG_PIPELINE: PipelineId = 0;
+pub const PBR_PIPELINE: PipelineId = 1;
+
+// Shader and fragment entry of a batch pipeline
+pub struct PipelineSource {
+    pub shader: ShaderSource,
+    pub fragment_entry: String,
+}
+
+/// How a batch pipeline is built, picked from the pass and the material's alpha mode.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum PipelineVariant {
+    Opaque,
+    // Masked materials in a multisampled pass
+    AlphaToCoverage,
+    // Sorted transparency, depth tested without writing
+    Blend,
+    // Weighted blended OIT, uses the `<fragment entry>_oit` entry point
+    OitAccumulate,
 }
 
-impl<'a> State<'a> {
-    pub async fn new(window: &'a mut glfw::PWindow) -> Self {
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct PipelineKey {
+    pub pipeline: PipelineId,
+    pub variant: PipelineVariant,
+    pub sample_count: u32,
+}
+
+// Pass a range of batches is drawn in by `draw_batches`
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum BatchPass {
+    Opaque { sample_count: u32 },
+    GBuffer,
+    Transparent { sample_count: u32 },
+}
+
+impl State {
+    pub async fn new(window: Rc<dyn RenderWindow>) -> Self {
         // Standard Device and Surface configuration //
-        let size = window.get_size();
+        let size = window.size();
+        let scale_factor = window.scale_factor();
         let instance = wgpu::Instance::new(&wgpu::InstanceDescriptor::default());
-        let target = unsafe { wgpu::SurfaceTargetUnsafe::from_window(&window) }.unwrap();
+        // Safe as long as the window outlives the surface, `State` holds on to it
+        let target = wgpu::SurfaceTargetUnsafe::RawHandle {
+            raw_display_handle: window.display_handle().unwrap().as_raw(),
+            raw_window_handle: window.window_handle().unwrap().as_raw(),
+        };
         let surface = unsafe { instance.create_surface_unsafe(target) }.unwrap();
 
         let adapter = instance
@@ -40,10 +159,12 @@ impl<'a> State<'a> {
             .await
             .unwrap();
 
+        // Only request what the adapter has, culling falls back to the CPU without it
+        let optional_features = adapter.features() & wgpu::Features::INDIRECT_FIRST_INSTANCE;
         let (device, queue) = adapter
             .request_device(&wgpu::DeviceDescriptor {
                 label: Some("GPU Device"),
-                required_features: wgpu::Features::default(),
+                required_features: wgpu::Features::default() | optional_features,
                 required_limits: wgpu::Limits::defaults(),
                 memory_hints: wgpu::MemoryHints::MemoryUsage,
                 trace: wgpu::Trace::Off,
@@ -56,15 +177,17 @@ impl<'a> State<'a> {
             .formats
             .iter()
             .copied()
-            .filter(|f| f.is_srgb())
-            .next()
+            .find(|f| f.is_srgb())
             .unwrap_or(surface_capabilities.formats[0]);
 
+        // Copying the surface is needed for PNG captures
+        let surface_usage = wgpu::TextureUsages::RENDER_ATTACHMENT
+            | (surface_capabilities.usages & wgpu::TextureUsages::COPY_SRC);
         let config = wgpu::SurfaceConfiguration {
-            usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+            usage: surface_usage,
             format: surface_format,
-            width: size.0 as u32,
-            height: size.1 as u32,
+            width: size.0,
+            height: size.1,
             present_mode: wgpu::PresentMode::Fifo,
             desired_maximum_frame_latency: 2,
             alpha_mode: surface_capabilities.alpha_modes[0],
@@ -72,79 +195,190 @@ impl<'a> State<'a> {
         };
 
         surface.configure(&device, &config);
+        let present_modes = surface_capabilities.present_modes.clone();
+        let post_processor = PostProcessor::new(
+            &device,
+            config.format,
+            config.width,
+            config.height,
+            post_process::default_chain(),
+        );
         // ------------------------------------ //
 
-        let mesh_size: f32 = 0.1;
-        let mesh = mesh_builder::create_mesh(&device, &mesh_size);
-        let material = Material::new(&device, &queue, "textures/texture_diamond.jpg");
+        let material_bind_group_layout = Material::create_bind_group_layout(&device);
 
-        // Create Instances of the mesh
-        const NUM_INSTANCES: u16 = 8;
+        // Reading storage buffers from the vertex stage is not available on every adapter
+        let downlevel_flags = adapter.get_downlevel_capabilities().flags;
+        let instancing_mode = if downlevel_flags.contains(wgpu::DownlevelFlags::VERTEX_STORAGE) {
+            INSTANCING_MODE
+        } else {
+            InstancingMode::VertexBuffer
+        };
+        let instance_storage =
+            InstanceStorage::new::<InstanceRaw>(&device, &queue, &[], instancing_mode);
 
-        let mut init_position = -mesh_size * (NUM_INSTANCES - 1) as f32;
-        let instances = (0..NUM_INSTANCES)
-            .map(|i| {
-                let position = cgmath::Vector3 {
-                    x: init_position,
-                    y: 0.0,
-                    z: 0.0,
-                };
-                let rotation = cgmath::Quaternion::from_axis_angle(
-                    cgmath::Vector3 {
-                        x: 0.0,
-                        y: 0.0,
-                        z: 1.0,
-                    },
-                    cgmath::Deg(30.0 * (i as f32)),
-                );
-                init_position = init_position + 2.0 * mesh_size;
-                Instance { position, rotation }
-            })
-            .collect::<Vec<_>>();
+        let instance_culler = if FRUSTUM_CULLING && instancing_mode == InstancingMode::StorageBuffer
+        {
+            let culling_mode = if downlevel_flags.contains(wgpu::DownlevelFlags::COMPUTE_SHADERS) {
+                CullingMode::for_device(&device)
+            } else {
+                CullingMode::Cpu
+            };
+            Some(InstanceCuller::new(&device, culling_mode))
+        } else {
+            None
+        };
 
-        let instances_raw = instances
-            .iter()
-            .map(|inst| inst.to_raw())
-            .collect::<Vec<_>>();
+        let camera = Camera {
+            eye: (0.0, 0.0, 2.0).into(),
+            target: (0.0, 0.0, 0.0).into(),
+            up: cgmath::Vector3::unit_y(),
+            aspect: config.width as f32 / config.height as f32,
+            znear: 0.1,
+            zfar: 100.0,
+            projection: Projection::Perspective {
+                fovy: cgmath::Deg(45.0),
+            },
+        };
+        let camera_binding = CameraBinding::new(&device, &camera);
 
-        let instances_buffer = device.create_buffer_init(&BufferInitDescriptor {
-            label: Some("Instances Buffer"),
-            contents: bytemuck::cast_slice(&instances_raw),
-            usage: wgpu::BufferUsages::VERTEX,
-        });
+        let lights = vec![Light::Directional {
+            direction: cgmath::Vector3::new(0.0, -0.3, -1.0),
+            color: cgmath::Vector3::new(1.0, 1.0, 1.0),
+            intensity: 0.9,
+        }];
+        let lighting = LightingUniform {
+            ambient: [0.15, 0.15, 0.15],
+            light_count: 0,
+            specular_strength: 0.5,
+            shininess: 32.0,
+            environment_intensity: 1.0,
+            max_reflection_lod: (PREFILTERED_MIP_LEVELS - 1) as f32,
+        };
+        // Plain sky tint until an HDR map is loaded with `set_environment_map`
+        let environment = Environment::from_color(&device, &queue, [0.2, 0.22, 0.25]);
+        let shadow_maps = ShadowMaps::new(&device, instancing_mode, &instance_storage);
+        let light_binding = LightBinding::new(&device, &environment, &shadow_maps.resources);
 
-        // Create Render Pipeline
-        let render_pipeline: wgpu::RenderPipeline;
-        {
-            let mut pipeline_builder = pipeline_builder::PipelineBuilder::new(
-                &device,
-                "shaders/shader.wgsl",
-                "vs_main",
-                "fs_main",
-                config.format,
-            );
-            pipeline_builder.add_vertex_buffer_layout(Vertex::desc());
-            pipeline_builder.add_bind_group_layout(&material.bind_group_layout);
-            pipeline_builder.add_vertex_buffer_layout(Instance::desc());
-            render_pipeline = pipeline_builder.build_pipeline("Render Pipeline");
-        }
+        let pipeline_sources = ["fs_main", "fs_pbr"]
+            .map(|fragment_entry| PipelineSource {
+                shader: ShaderSource::Builtin("shaders/shader.wgsl"),
+                fragment_entry: fragment_entry.to_string(),
+            })
+            .into();
+        let gbuffer_pipeline = build_render_pipeline(
+            &device,
+            &GBUFFER_FORMATS,
+            &ShaderSource::Builtin("shaders/shader.wgsl"),
+            "fs_gbuffer",
+            PipelineVariant::Opaque,
+            1,
+            &material_bind_group_layout,
+            &camera_binding,
+            &light_binding,
+            instancing_mode,
+            &instance_storage,
+            instance_culler.as_ref(),
+        );
+        let deferred_lighting = DeferredLighting::new(&device, &camera_binding, &light_binding);
+        let oit_compositor = OitCompositor::new(&device, MSAA_SAMPLES);
+        let debug_draw = DebugDraw::new(&device, HDR_FORMAT, MSAA_SAMPLES, &camera_binding);
+        let text = TextRenderer::new(
+            &device,
+            &queue,
+            HDR_FORMAT,
+            config.format,
+            MSAA_SAMPLES,
+            (config.width, config.height),
+            &material_bind_group_layout,
+            &camera_binding,
+        );
+        let sprites = SpriteBatcher::new(
+            &device,
+            &queue,
+            config.format,
+            (
+                config.width as f32 / scale_factor,
+                config.height as f32 / scale_factor,
+            ),
+            &material_bind_group_layout,
+        );
+        let ui = UiOverlay::new(&device, config.format);
 
-        Self {
-            window,
+        let mut state = Self {
             device,
             queue,
             surface,
             config,
-            render_pipeline,
-            mesh,
-            material,
-            instances,
-            instances_buffer,
+            scale_factor,
+            present_modes,
+            transient_pool: TransientPool::default(),
+            clear_color: wgpu::Color {
+                r: 0.1,
+                g: 0.1,
+                b: 0.9,
+                a: 0.0,
+            },
+            skybox: None,
+            post_processor,
+            pipeline_sources,
+            pipeline_cache: HashMap::new(),
+            msaa_samples: MSAA_SAMPLES,
+            transparency_mode: TransparencyMode::Sorted,
+            oit_compositor,
+            meshes: Vec::new(),
+            materials: Vec::new(),
+            material_bind_group_layout,
+            instances: Vec::new(),
+            scene: Scene::new(),
+            render_queue: RenderQueue::new(),
+            instance_storage,
+            instancing_mode,
+            instance_culler,
+            camera,
+            camera_binding,
+            lights,
+            lighting,
+            light_binding,
+            shadow_maps,
+            render_path: RenderPath::Forward,
+            gbuffer_pipeline,
+            deferred_lighting,
+            frame_capture: FrameCapture::default(),
+            debug_draw,
+            text,
+            sprites,
+            ui,
+            window,
+        };
+        for pipeline in [PHONG_PIPELINE, PBR_PIPELINE] {
+            state.prepare_pipeline(PipelineKey {
+                pipeline,
+                variant: PipelineVariant::Opaque,
+                sample_count: state.msaa_samples,
+            });
         }
+        state
     }
 
     pub fn render(&mut self) {
-        let drawable = self.surface.get_current_texture().unwrap();
+        let drawable = match self.surface.get_current_texture() {
+            Ok(drawable) => drawable,
+            // The framebuffer changed size before the resize event arrived
+            Err(wgpu::SurfaceError::Outdated | wgpu::SurfaceError::Lost) => {
+                let (width, height) = self.window.size();
+                self.resize(width, height);
+                return;
+            }
+            // Usually transient (e.g. a minimized or occluded window), try again next frame
+            Err(error @ (wgpu::SurfaceError::Timeout | wgpu::SurfaceError::Other)) => {
+                log::warn!("Skipped a frame: {}", error);
+                return;
+            }
+            Err(error @ wgpu::SurfaceError::OutOfMemory) => {
+                panic!("Failed to acquire the surface texture: {}", error)
+            }
+        };
 
         let command_encoder_descriptor = wgpu::CommandEncoderDescriptor {
             label: Some("Command Encoder"),
@@ -153,59 +387,764 @@ impl<'a> State<'a> {
             .device
             .create_command_encoder(&command_encoder_descriptor);
 
+        self.camera_binding.update(&self.queue, &self.camera);
+        let shadow_layers = self
+            .shadow_maps
+            .update(&self.queue, &self.camera, &self.lights);
+        self.light_binding.update(
+            &self.device,
+            &self.queue,
+            &self.lights,
+            &shadow_layers,
+            self.lighting,
+        );
+        self.scene.update_world_transforms();
+        self.queue_frame_items();
+
+        // GPU culling compacts the visible instances of a batch in any order, sorted
+        // transparent instances then need a batch each to stay back to front
+        let merge_transparent = self.transparency_mode == TransparencyMode::WeightedBlended
+            || self
+                .instance_culler
+                .as_ref()
+                .is_none_or(|culler| culler.mode != CullingMode::Gpu);
+        let materials = &self.materials;
+        let (instances_raw, batches) = self.render_queue.build_batches(
+            self.camera.eye,
+            |material| materials[material].alpha_mode.is_transparent(),
+            merge_transparent,
+        );
+        self.instance_storage
+            .update(&self.device, &self.queue, &instances_raw);
+        self.debug_draw.prepare(&self.device, &self.queue);
+        self.text.prepare(
+            &self.device,
+            &self.queue,
+            (self.config.width, self.config.height),
+            self.scale_factor,
+        );
+        self.sprites
+            .prepare(&self.device, &self.queue, self.logical_size());
+        self.ui.prepare(
+            &self.device,
+            &self.queue,
+            (self.config.width, self.config.height),
+        );
+
+        // Transparent batches come last, after the opaque and masked ones
+        let opaque = 0..batches.partition_point(|batch| !batch.transparent);
+        let transparent = opaque.end..batches.len();
+        let (opaque_pass, sample_count) = match self.render_path {
+            RenderPath::Forward => (
+                BatchPass::Opaque {
+                    sample_count: self.msaa_samples,
+                },
+                self.msaa_samples,
+            ),
+            RenderPath::Deferred => (BatchPass::GBuffer, 1),
+        };
+        let transparent_pass = BatchPass::Transparent { sample_count };
+        for (range, pass) in [(&opaque, opaque_pass), (&transparent, transparent_pass)] {
+            for batch in &batches[range.clone()] {
+                if let Some(key) = self.pipeline_key(batch, pass) {
+                    self.prepare_pipeline(key);
+                }
+            }
+        }
+        let weighted_blended =
+            self.transparency_mode == TransparencyMode::WeightedBlended && !transparent.is_empty();
+
+        if let Some(culler) = &mut self.instance_culler {
+            let cull_batches = batches
+                .iter()
+                .map(|batch| {
+                    let mesh = &self.meshes[batch.mesh];
+                    CullBatch {
+                        index_count: mesh.num_indices as u32,
+                        bounding_radius: mesh.bounding_radius,
+                        instances: batch.instances.clone(),
+                    }
+                })
+                .collect::<Vec<_>>();
+            let frustum = Frustum::from_matrix(self.camera.build_view_projection_matrix());
+            match culler.mode {
+                CullingMode::Gpu => culler.cull_gpu(
+                    &self.device,
+                    &self.queue,
+                    &mut command_encoder,
+                    &self.instance_storage,
+                    &cull_batches,
+                    &frustum,
+                ),
+                CullingMode::Cpu => culler.cull_cpu(
+                    &self.device,
+                    &self.queue,
+                    &self.instance_storage,
+                    &instances_raw,
+                    &cull_batches,
+                    &frustum,
+                ),
+            }
+        }
+
         let image_view_descriptor = wgpu::TextureViewDescriptor {
             dimension: Some(wgpu::TextureViewDimension::D2),
             ..Default::default()
         };
         let image_view = drawable.texture.create_view(&image_view_descriptor);
 
-        let color_attachment = wgpu::RenderPassColorAttachment {
-            view: &image_view,
-            resolve_target: None,
-            ops: wgpu::Operations {
-                load: wgpu::LoadOp::Clear(wgpu::Color {
-                    r: 0.1,
-                    g: 0.1,
-                    b: 0.9,
-                    a: 0.0,
-                }),
-                store: wgpu::StoreOp::Store,
+        // The pool and captures are moved out so the pass closures can borrow the rest of `self`
+        let mut transient_pool = std::mem::take(&mut self.transient_pool);
+        let mut frame_capture = std::mem::take(&mut self.frame_capture);
+        let exr_captures = frame_capture.take_requests(CaptureFormat::Exr);
+        {
+            let mut graph = RenderGraph::new();
+            let surface = graph.import_texture(&image_view);
+            let shadow_maps = graph.import_texture(&self.shadow_maps.resources.array_view);
+            let instances = graph.import_buffer(&self.instance_storage.buffer);
+            let scene_color = graph.create_texture(TextureDesc {
+                label: "Scene Color",
+                format: HDR_FORMAT,
+                size: TextureSize::Surface,
+                usage: if exr_captures.is_empty() {
+                    wgpu::TextureUsages::empty()
+                } else {
+                    wgpu::TextureUsages::COPY_SRC
+                },
+                sample_count: 1,
+            });
+            // Multisampled color of the forward path, resolved into scene_color
+            let scene_target = if sample_count > 1 {
+                graph.create_texture(TextureDesc {
+                    label: "Multisampled Scene Color",
+                    format: HDR_FORMAT,
+                    size: TextureSize::Surface,
+                    usage: wgpu::TextureUsages::empty(),
+                    sample_count,
+                })
+            } else {
+                scene_color
+            };
+            let depth = graph.create_texture(TextureDesc {
+                label: "Depth",
+                format: DEPTH_FORMAT,
+                size: TextureSize::Surface,
+                usage: wgpu::TextureUsages::empty(),
+                sample_count,
+            });
+
+            let mut shadow_pass = PassBuilder::new("Shadow Pass");
+            shadow_pass.read_buffer(instances);
+            shadow_pass.write_texture(shadow_maps);
+            // Transparent batches don't cast shadows
+            graph.add_encoder_pass(shadow_pass, |command_encoder, _| {
+                self.shadow_maps.render(
+                    command_encoder,
+                    &self.instance_storage,
+                    &self.meshes,
+                    &batches[opaque.clone()],
+                );
+            });
+
+            match self.render_path {
+                RenderPath::Forward => {
+                    let mut scene_pass = PassBuilder::new("Scene Pass");
+                    scene_pass.read_buffer(instances);
+                    scene_pass.read_texture(shadow_maps);
+                    scene_pass.write_color(scene_target, Some(self.clear_color));
+                    // With OIT the resolve waits for the world overlays after the composite
+                    if scene_target != scene_color && !weighted_blended {
+                        scene_pass.resolve_color(scene_target, scene_color);
+                    }
+                    scene_pass.write_depth(depth, Some(1.0));
+                    graph.add_render_pass(scene_pass, |render_pass, _| {
+                        self.draw_batches(render_pass, &batches, opaque.clone(), opaque_pass);
+                        self.draw_after_opaque(
+                            render_pass,
+                            &batches,
+                            transparent.clone(),
+                            sample_count,
+                        );
+                        if !weighted_blended {
+                            self.draw_world_overlays(render_pass, sample_count);
+                        }
+                    });
+                }
+                RenderPath::Deferred => {
+                    let gbuffer = GBUFFER_FORMATS.map(|format| {
+                        graph.create_texture(TextureDesc {
+                            label: "G-Buffer",
+                            format,
+                            size: TextureSize::Surface,
+                            usage: wgpu::TextureUsages::empty(),
+                            sample_count: 1,
+                        })
+                    });
+
+                    let mut gbuffer_pass = PassBuilder::new("G-Buffer Pass");
+                    gbuffer_pass.read_buffer(instances);
+                    for texture in gbuffer {
+                        gbuffer_pass.write_color(texture, Some(wgpu::Color::TRANSPARENT));
+                    }
+                    gbuffer_pass.write_depth(depth, Some(1.0));
+                    graph.add_render_pass(gbuffer_pass, |render_pass, _| {
+                        self.draw_batches(render_pass, &batches, opaque.clone(), opaque_pass);
+                    });
+
+                    self.deferred_lighting.update(&self.queue, self.clear_color);
+                    let mut lighting_pass = PassBuilder::new("Deferred Lighting Pass");
+                    for texture in gbuffer.into_iter().chain([depth]) {
+                        lighting_pass.read_texture(texture);
+                    }
+                    lighting_pass.read_texture(shadow_maps);
+                    lighting_pass.write_color(scene_color, None);
+                    let state = &*self;
+                    graph.add_render_pass(lighting_pass, move |render_pass, resources| {
+                        let [albedo, normal, material, emissive] = gbuffer;
+                        state.deferred_lighting.draw(
+                            &state.device,
+                            render_pass,
+                            [albedo, normal, material, emissive, depth]
+                                .map(|texture| resources.texture_view(texture)),
+                            &state.camera_binding,
+                            &state.light_binding,
+                        );
+                    });
+
+                    let mut forward_pass = PassBuilder::new("Forward Pass");
+                    forward_pass.read_buffer(instances);
+                    forward_pass.read_texture(shadow_maps);
+                    forward_pass.write_color(scene_color, None);
+                    forward_pass.write_depth(depth, None);
+                    graph.add_render_pass(forward_pass, |render_pass, _| {
+                        self.draw_after_opaque(render_pass, &batches, transparent.clone(), 1);
+                        if !weighted_blended {
+                            self.draw_world_overlays(render_pass, 1);
+                        }
+                    });
+                }
+            }
+
+            if weighted_blended {
+                let targets = OIT_FORMATS.map(|format| {
+                    graph.create_texture(TextureDesc {
+                        label: "OIT Target",
+                        format,
+                        size: TextureSize::Surface,
+                        usage: wgpu::TextureUsages::empty(),
+                        sample_count,
+                    })
+                });
+                let resolved = if sample_count > 1 {
+                    OIT_FORMATS.map(|format| {
+                        graph.create_texture(TextureDesc {
+                            label: "OIT Resolve",
+                            format,
+                            size: TextureSize::Surface,
+                            usage: wgpu::TextureUsages::empty(),
+                            sample_count: 1,
+                        })
+                    })
+                } else {
+                    targets
+                };
+
+                let mut accumulate_pass = PassBuilder::new("OIT Accumulate Pass");
+                accumulate_pass.read_buffer(instances);
+                accumulate_pass.read_texture(shadow_maps);
+                for ((target, resolve_target), clear) in
+                    targets.into_iter().zip(resolved).zip(OIT_CLEAR)
+                {
+                    accumulate_pass.write_color(target, Some(clear));
+                    if resolve_target != target {
+                        accumulate_pass.resolve_color(target, resolve_target);
+                    }
+                }
+                accumulate_pass.write_depth(depth, None);
+                graph.add_render_pass(accumulate_pass, |render_pass, _| {
+                    self.draw_batches(render_pass, &batches, transparent.clone(), transparent_pass);
+                });
+
+                let mut composite_pass = PassBuilder::new("OIT Composite Pass");
+                for texture in resolved {
+                    composite_pass.read_texture(texture);
+                }
+                composite_pass.write_color(scene_target, None);
+                let state = &*self;
+                graph.add_render_pass(composite_pass, move |render_pass, resources| {
+                    let [accum, revealage] =
+                        resolved.map(|texture| resources.texture_view(texture));
+                    state.oit_compositor.draw(
+                        &state.device,
+                        render_pass,
+                        accum,
+                        revealage,
+                        sample_count,
+                    );
+                });
+
+                // World text and debug lines go over the composited transparent surfaces
+                let mut world_overlay_pass = PassBuilder::new("World Overlay Pass");
+                world_overlay_pass.write_color(scene_target, None);
+                if scene_target != scene_color {
+                    world_overlay_pass.resolve_color(scene_target, scene_color);
+                }
+                world_overlay_pass.write_depth(depth, None);
+                graph.add_render_pass(world_overlay_pass, |render_pass, _| {
+                    self.draw_world_overlays(render_pass, sample_count);
+                });
+            }
+
+            if !exr_captures.is_empty() {
+                let mut capture_pass = PassBuilder::new("Capture Pass");
+                capture_pass.read_texture(scene_color);
+                let frame_capture = &mut frame_capture;
+                let device = &self.device;
+                graph.add_encoder_pass(capture_pass, move |command_encoder, resources| {
+                    frame_capture.copy_texture(
+                        device,
+                        command_encoder,
+                        resources.texture_view(scene_color).texture(),
+                        CaptureFormat::Exr,
+                        exr_captures,
+                    );
+                });
+            }
+
+            let mut post_pass = PassBuilder::new("Post Process Pass");
+            post_pass.read_texture(scene_color);
+            post_pass.write_texture(surface);
+            graph.add_encoder_pass(post_pass, |command_encoder, resources| {
+                self.post_processor.render(
+                    &self.device,
+                    &self.queue,
+                    command_encoder,
+                    resources.texture_view(scene_color),
+                    resources.texture_view(surface),
+                );
+            });
+
+            if self.sprites.has_sprites() {
+                let mut sprite_pass = PassBuilder::new("Sprite Pass");
+                sprite_pass.write_color(surface, None);
+                graph.add_render_pass(sprite_pass, |render_pass, _| {
+                    self.sprites.draw(render_pass);
+                });
+            }
+
+            // HUD text goes on top of the tone mapped image
+            if self.text.has_screen_text() {
+                let mut overlay_pass = PassBuilder::new("Overlay Pass");
+                overlay_pass.write_color(surface, None);
+                graph.add_render_pass(overlay_pass, |render_pass, _| {
+                    self.text.draw_screen(render_pass);
+                });
+            }
+
+            if self.ui.has_draws() {
+                let mut ui_pass = PassBuilder::new("UI Pass");
+                ui_pass.write_color(surface, None);
+                graph.add_render_pass(ui_pass, |render_pass, _| {
+                    self.ui
+                        .draw(render_pass, (self.config.width, self.config.height));
+                });
+            }
+
+            graph.execute(
+                &self.device,
+                &mut command_encoder,
+                &mut transient_pool,
+                (self.config.width, self.config.height),
+            );
+        }
+        self.transient_pool = transient_pool;
+
+        let png_captures = frame_capture.take_requests(CaptureFormat::Png);
+        if !png_captures.is_empty() {
+            if self.config.usage.contains(wgpu::TextureUsages::COPY_SRC) {
+                frame_capture.copy_texture(
+                    &self.device,
+                    &mut command_encoder,
+                    &drawable.texture,
+                    CaptureFormat::Png,
+                    png_captures,
+                );
+            } else {
+                log::warn!("The surface can't be copied, PNG capture skipped");
+            }
+        }
+
+        self.queue.submit(std::iter::once(command_encoder.finish()));
+        drawable.present();
+
+        frame_capture.after_submit();
+        frame_capture.poll(&self.device);
+        self.frame_capture = frame_capture;
+        self.debug_draw.end_frame();
+        self.text.end_frame();
+        self.sprites.end_frame();
+        self.ui.end_frame();
+    }
+
+    /// Saves the next frame to `path`: `.png` for the final image, `.exr` for the
+    /// linear HDR scene before post-processing. Files are written a few frames later.
+    pub fn capture_frame(&mut self, path: impl AsRef<Path>) -> anyhow::Result<()> {
+        self.frame_capture.request(path)
+    }
+
+    /// Writes every following frame into `directory`. Advance the animation by
+    /// `recording_timestep` instead of the wall clock to get a steady video.
+    pub fn start_recording(
+        &mut self,
+        directory: impl AsRef<Path>,
+        format: CaptureFormat,
+        frames_per_second: f32,
+    ) -> anyhow::Result<()> {
+        self.frame_capture
+            .start_recording(directory, format, frames_per_second)
+    }
+
+    pub fn stop_recording(&mut self) {
+        self.frame_capture.stop_recording(&self.device);
+    }
+
+    pub fn recording_timestep(&self) -> Option<f32> {
+        self.frame_capture.timestep()
+    }
+
+    pub fn add_mesh(&mut self, mesh: Mesh) -> MeshId {
+        self.meshes.push(mesh);
+        self.meshes.len() - 1
+    }
+
+    pub fn add_material(&mut self, material: Material) -> MaterialId {
+        self.materials.push(material);
+        self.materials.len() - 1
+    }
+
+    // Pipelines share the default pipeline's bind group and instancing layout.
+    // The opaque variant is built right away, the others when first drawn.
+    pub fn add_pipeline(&mut self, shader: ShaderSource, fragment_entry: &str) -> PipelineId {
+        self.pipeline_sources.push(PipelineSource {
+            shader,
+            fragment_entry: fragment_entry.to_string(),
+        });
+        let pipeline = self.pipeline_sources.len() - 1;
+        self.prepare_pipeline(PipelineKey {
+            pipeline,
+            variant: PipelineVariant::Opaque,
+            sample_count: self.msaa_samples,
+        });
+        pipeline
+    }
+
+    // Variant of the batch's pipeline drawn in `pass`, `None` for the G-buffer pipeline
+    fn pipeline_key(&self, batch: &DrawBatch, pass: BatchPass) -> Option<PipelineKey> {
+        let (variant, sample_count) = match pass {
+            BatchPass::GBuffer => return None,
+            BatchPass::Opaque { sample_count } => match self.materials[batch.material].alpha_mode {
+                AlphaMode::Mask { .. } if sample_count > 1 => {
+                    (PipelineVariant::AlphaToCoverage, sample_count)
+                }
+                _ => (PipelineVariant::Opaque, sample_count),
+            },
+            BatchPass::Transparent { sample_count } => match self.transparency_mode {
+                TransparencyMode::Sorted => (PipelineVariant::Blend, sample_count),
+                TransparencyMode::WeightedBlended => (PipelineVariant::OitAccumulate, sample_count),
             },
-            depth_slice: None,
         };
+        Some(PipelineKey {
+            pipeline: batch.pipeline,
+            variant,
+            sample_count,
+        })
+    }
 
-        let render_pass_descriptor = wgpu::RenderPassDescriptor {
-            label: Some("Render Pass"),
-            color_attachments: &[Some(color_attachment)],
-            depth_stencil_attachment: None,
-            occlusion_query_set: None,
-            timestamp_writes: None,
+    // Builds a pipeline variant if it is not cached yet, the render graph
+    // closures only borrow `self` so this runs before the graph is built
+    fn prepare_pipeline(&mut self, key: PipelineKey) {
+        if self.pipeline_cache.contains_key(&key) {
+            return;
+        }
+        let source = &self.pipeline_sources[key.pipeline];
+        let color_formats: &[wgpu::TextureFormat] = match key.variant {
+            PipelineVariant::OitAccumulate => &OIT_FORMATS,
+            _ => &[HDR_FORMAT],
         };
+        let render_pipeline = build_render_pipeline(
+            &self.device,
+            color_formats,
+            &source.shader,
+            &source.fragment_entry,
+            key.variant,
+            key.sample_count,
+            &self.material_bind_group_layout,
+            &self.camera_binding,
+            &self.light_binding,
+            self.instancing_mode,
+            &self.instance_storage,
+            self.instance_culler.as_ref(),
+        );
+        self.pipeline_cache.insert(key, render_pipeline);
+    }
 
+    // Submits the flat instances and the renderable scene nodes for this frame. The
+    // flat instances and nodes without their own mesh or material use the first one,
+    // and are skipped while there is none. Both are shaded with PBR like the deferred
+    // path unless a node picks another pipeline.
+    fn queue_frame_items(&mut self) {
+        let default_mesh = (!self.meshes.is_empty()).then_some(0);
+        let default_material = (!self.materials.is_empty()).then_some(0);
+        if let (Some(mesh), Some(material)) = (default_mesh, default_material)
+            && !self.instances.is_empty()
         {
-            let mut render_pass = command_encoder.begin_render_pass(&render_pass_descriptor);
-            render_pass.set_pipeline(&self.render_pipeline);
-            render_pass.set_vertex_buffer(0, self.mesh.vertex_buffer.slice(..));
-            render_pass
-                .set_index_buffer(self.mesh.index_buffer.slice(..), wgpu::IndexFormat::Uint32);
-            render_pass.set_vertex_buffer(1, self.instances_buffer.slice(..));
-            render_pass.set_bind_group(0, &self.material.bind_group, &[]);
-            render_pass.draw_indexed(
-                0..self.mesh.num_indices as u32,
-                0,
-                0..self.instances.len() as u32,
+            self.render_queue.submit(DrawItem {
+                mesh,
+                material,
+                pipeline: PBR_PIPELINE,
+                instances: self.instances.iter().map(|inst| inst.to_raw()).collect(),
+            });
+        }
+        for node in self.scene.renderables() {
+            let (Some(mesh), Some(material)) = (
+                node.mesh.or(default_mesh),
+                node.material.or(default_material),
+            ) else {
+                continue;
+            };
+            self.render_queue.submit_instance(
+                mesh,
+                material,
+                node.pipeline.unwrap_or(PBR_PIPELINE),
+                InstanceRaw::from_model(node.world_matrix()),
             );
         }
+    }
 
-        self.queue.submit(std::iter::once(command_encoder.finish()));
-        drawable.present();
+    // Draws `range` of `batches` with the pipeline variants `pass` needs
+    fn draw_batches(
+        &self,
+        render_pass: &mut wgpu::RenderPass,
+        batches: &[DrawBatch],
+        range: Range<usize>,
+        pass: BatchPass,
+    ) {
+        render_pass.set_bind_group(1, &self.camera_binding.bind_group, &[]);
+        render_pass.set_bind_group(2, &self.light_binding.bind_group, &[]);
+        match (self.instancing_mode, &self.instance_culler) {
+            (InstancingMode::VertexBuffer, _) => {
+                render_pass.set_vertex_buffer(1, self.instance_storage.buffer.slice(..));
+            }
+            (InstancingMode::StorageBuffer, None) => {
+                render_pass.set_bind_group(3, &self.instance_storage.bind_group, &[]);
+            }
+            (InstancingMode::StorageBuffer, Some(culler)) => {
+                render_pass.set_bind_group(3, culler.render_bind_group(), &[]);
+            }
+        }
+
+        // Batches are sorted, so only rebind what differs from the previous batch
+        let mut previous: Option<(&DrawBatch, Option<PipelineKey>)> = None;
+        for batch_index in range {
+            let batch = &batches[batch_index];
+            let key = self.pipeline_key(batch, pass);
+            if previous.is_none_or(|(_, prev_key)| prev_key != key) {
+                match key {
+                    Some(key) => render_pass.set_pipeline(&self.pipeline_cache[&key]),
+                    None => render_pass.set_pipeline(&self.gbuffer_pipeline),
+                }
+            }
+            if previous.is_none_or(|(prev, _)| prev.material != batch.material) {
+                render_pass.set_bind_group(0, &self.materials[batch.material].bind_group, &[]);
+            }
+            let mesh = &self.meshes[batch.mesh];
+            if previous.is_none_or(|(prev, _)| prev.mesh != batch.mesh) {
+                render_pass.set_vertex_buffer(0, mesh.vertex_buffer.slice(..));
+                render_pass
+                    .set_index_buffer(mesh.index_buffer.slice(..), wgpu::IndexFormat::Uint32);
+            }
+            previous = Some((batch, key));
+
+            match &self.instance_culler {
+                Some(culler) => culler.draw(render_pass, batch_index),
+                None => {
+                    render_pass.draw_indexed(0..mesh.num_indices as u32, 0, batch.instances.clone())
+                }
+            }
+        }
     }
 
+    // Skybox and the sorted transparent batches unless they go through OIT. Both
+    // come after the opaque geometry of either render path.
+    fn draw_after_opaque(
+        &self,
+        render_pass: &mut wgpu::RenderPass,
+        batches: &[DrawBatch],
+        transparent: Range<usize>,
+        sample_count: u32,
+    ) {
+        // Only pixels left uncovered by geometry pass the far plane depth test
+        if let Some(skybox) = &self.skybox {
+            skybox.draw(render_pass, &self.camera_binding, sample_count);
+        }
+        if self.transparency_mode == TransparencyMode::Sorted && !transparent.is_empty() {
+            self.draw_batches(
+                render_pass,
+                batches,
+                transparent,
+                BatchPass::Transparent { sample_count },
+            );
+        }
+    }
+
+    // World text and debug lines, on top of all the transparent geometry
+    fn draw_world_overlays(&self, render_pass: &mut wgpu::RenderPass, sample_count: u32) {
+        self.text
+            .draw_world(render_pass, &self.camera_binding, sample_count);
+        self.debug_draw
+            .draw(render_pass, &self.camera_binding, sample_count);
+    }
+
+    /// Replaces the image based lighting with an equirectangular `.hdr`/`.exr` map.
+    pub fn set_environment_map(&mut self, image_path: &str) -> anyhow::Result<()> {
+        let environment = Environment::from_equirectangular(&self.device, &self.queue, image_path)?;
+        self.light_binding
+            .set_environment(&self.device, &environment);
+        Ok(())
+    }
+
+    // Face images in +X, -X, +Y, -Y, +Z, -Z order
+    pub fn set_skybox_faces(&mut self, face_paths: [&str; 6]) {
+        self.skybox = Some(Skybox::from_faces(
+            &self.device,
+            &self.queue,
+            HDR_FORMAT,
+            self.msaa_samples,
+            &self.camera_binding,
+            face_paths,
+        ));
+    }
+
+    // Shows the environment map loaded with `set_environment_map` as the background
+    pub fn set_skybox_from_environment(&mut self) {
+        self.skybox = Some(Skybox::from_environment(
+            &self.device,
+            HDR_FORMAT,
+            self.msaa_samples,
+            &self.camera_binding,
+            &self.light_binding.environment,
+        ));
+    }
+
+    /// Fifo waits for vblank, Mailbox replaces the queued frame without tearing and
+    /// Immediate presents right away. Unsupported modes fall back to Fifo, which
+    /// every surface has. Returns the mode in use.
+    pub fn set_present_mode(&mut self, present_mode: wgpu::PresentMode) -> wgpu::PresentMode {
+        let present_mode = if self.present_modes.contains(&present_mode) {
+            present_mode
+        } else {
+            log::warn!(
+                "{:?} is not supported by the surface, using Fifo",
+                present_mode
+            );
+            wgpu::PresentMode::Fifo
+        };
+        self.config.present_mode = present_mode;
+        self.surface.configure(&self.device, &self.config);
+        present_mode
+    }
+
+    // `width` and `height` are the framebuffer size in pixels
     pub fn resize(&mut self, width: u32, height: u32) {
         if width > 0 && height > 0 {
             self.config.width = width;
             self.config.height = height;
             self.surface.configure(&self.device, &self.config);
+            self.post_processor.resize(&self.device, width, height);
+            self.deferred_lighting.resize();
+            self.oit_compositor.resize();
+            self.camera.aspect = width as f32 / height as f32;
+        }
+    }
+
+    pub fn set_scale_factor(&mut self, scale_factor: f32) {
+        if scale_factor > 0.0 {
+            self.scale_factor = scale_factor;
+        }
+    }
+
+    // Surface size in logical pixels, the space UI and text are laid out in
+    pub fn logical_size(&self) -> (f32, f32) {
+        (
+            self.config.width as f32 / self.scale_factor,
+            self.config.height as f32 / self.scale_factor,
+        )
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn build_render_pipeline(
+    device: &wgpu::Device,
+    color_formats: &[wgpu::TextureFormat],
+    shader: &ShaderSource,
+    fragment_entry: &str,
+    variant: PipelineVariant,
+    sample_count: u32,
+    material_layout: &wgpu::BindGroupLayout,
+    camera_binding: &CameraBinding,
+    light_binding: &LightBinding,
+    instancing_mode: InstancingMode,
+    instance_storage: &InstanceStorage,
+    instance_culler: Option<&InstanceCuller>,
+) -> wgpu::RenderPipeline {
+    let vertex_entry = match (instancing_mode, instance_culler) {
+        (InstancingMode::VertexBuffer, _) => "vs_main",
+        (InstancingMode::StorageBuffer, None) => "vs_storage",
+        (InstancingMode::StorageBuffer, Some(_)) => "vs_culled",
+    };
+    let fragment_entry = match variant {
+        PipelineVariant::OitAccumulate => format!("{}_oit", fragment_entry),
+        _ => fragment_entry.to_string(),
+    };
+    let mut pipeline_builder = pipeline_builder::PipelineBuilder::new(
+        device,
+        shader.clone(),
+        vertex_entry,
+        &fragment_entry,
+        color_formats[0],
+    );
+    pipeline_builder.set_color_formats(color_formats);
+    pipeline_builder.add_vertex_buffer_layout(Vertex::desc());
+    // Transparent variants are depth tested against the opaque geometry only
+    let depth_write = matches!(
+        variant,
+        PipelineVariant::Opaque | PipelineVariant::AlphaToCoverage
+    );
+    pipeline_builder.set_depth_stencil(DEPTH_FORMAT, depth_write, wgpu::CompareFunction::Less);
+    match variant {
+        PipelineVariant::Opaque => {}
+        PipelineVariant::AlphaToCoverage => {
+            pipeline_builder.set_constant("alpha_to_coverage", 1.0);
+        }
+        PipelineVariant::Blend => {
+            pipeline_builder.set_blend_states(&[wgpu::BlendState::ALPHA_BLENDING]);
+        }
+        PipelineVariant::OitAccumulate => pipeline_builder.set_blend_states(&OIT_BLEND_STATES),
+    }
+    pipeline_builder.set_multisample(sample_count, variant == PipelineVariant::AlphaToCoverage);
+    pipeline_builder.add_bind_group_layout(material_layout);
+    pipeline_builder.add_bind_group_layout(&camera_binding.bind_group_layout);
+    pipeline_builder.add_bind_group_layout(&light_binding.bind_group_layout);
+    match (instancing_mode, instance_culler) {
+        (InstancingMode::VertexBuffer, _) => {
+            pipeline_builder.add_vertex_buffer_layout(Instance::desc());
+        }
+        (InstancingMode::StorageBuffer, None) => {
+            pipeline_builder.add_bind_group_layout(&instance_storage.bind_group_layout);
+        }
+        (InstancingMode::StorageBuffer, Some(culler)) => {
+            pipeline_builder.add_bind_group_layout(&culler.render_bind_group_layout);
         }
     }
+    pipeline_builder.build_pipeline("Render Pipeline")
 }
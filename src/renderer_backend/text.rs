@@ -0,0 +1,774 @@
+use std::{collections::HashMap, path::Path};
+
+use ab_glyph::{Font as _, FontVec, GlyphId, PxScale, ScaleFont};
+use cgmath::{Matrix4, Vector3};
+
+use super::{
+    camera::CameraBinding,
+    depth::DEPTH_FORMAT,
+    instance::InstanceStorage,
+    material::{AlphaMode, Material, MaterialFactors},
+    pipeline_builder::ShaderSource,
+    quad::{InstancedQuad, PixelCamera, QuadInstance, quad_model},
+};
+
+// Pixel size world space glyphs are rasterized at, whatever their size in the world
+const WORLD_RASTER_SIZE: f32 = 64.0;
+const INITIAL_ATLAS_SIZE: u32 = 512;
+// Empty texels between glyphs so filtering doesn't bleed into the neighbours
+const GLYPH_PADDING: u32 = 1;
+
+pub type FontId = usize;
+
+pub struct Font {
+    font: FontVec,
+}
+
+impl Font {
+    /// Loads a TTF or OTF file. The path is used as given, fonts usually come from
+    /// the system rather than the application's `src/`.
+    pub fn from_file(path: impl AsRef<Path>) -> anyhow::Result<Self> {
+        let bytes = std::fs::read(path)?;
+        Self::from_bytes(bytes)
+    }
+
+    pub fn from_bytes(bytes: Vec<u8>) -> anyhow::Result<Self> {
+        Ok(Self {
+            font: FontVec::try_from_vec(bytes)?,
+        })
+    }
+}
+
+/// Horizontal alignment of each line around the anchor position.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum TextAlign {
+    #[default]
+    Left,
+    Center,
+    Right,
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct TextStyle {
+    // Line height in logical pixels for screen text, world units for world text
+    pub size: f32,
+    // Linear RGBA
+    pub color: [f32; 4],
+    pub align: TextAlign,
+    // Multiplier of the font's line height
+    pub line_spacing: f32,
+    // Wraps lines at word boundaries, same units as `size`
+    pub max_width: Option<f32>,
+}
+
+impl Default for TextStyle {
+    fn default() -> Self {
+        Self {
+            size: 16.0,
+            color: [1.0, 1.0, 1.0, 1.0],
+            align: TextAlign::Left,
+            line_spacing: 1.0,
+            max_width: None,
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct LaidOutGlyph {
+    pub id: GlyphId,
+    // Pen position on the baseline
+    pub x: f32,
+    pub y: f32,
+}
+
+pub struct TextLayout {
+    pub glyphs: Vec<LaidOutGlyph>,
+    pub width: f32,
+    pub height: f32,
+}
+
+/// Lays out `text` in the units of `style.size`, with y pointing down from the
+/// top of the first line. Applies kerning, `\n` line breaks, word wrapping at
+/// `style.max_width` and the alignment of each line around x = 0.
+pub fn layout_text(font: &Font, text: &str, style: &TextStyle) -> TextLayout {
+    layout_glyphs(&font.font, text, style)
+}
+
+fn layout_glyphs(font: &impl ab_glyph::Font, text: &str, style: &TextStyle) -> TextLayout {
+    let scaled = font.as_scaled(PxScale::from(style.size));
+    let line_height = (scaled.ascent() - scaled.descent() + scaled.line_gap()) * style.line_spacing;
+
+    // Glyphs of each line and the width up to its last visible glyph
+    let mut lines = Vec::new();
+    for paragraph in text.split('\n') {
+        let mut line = Vec::new();
+        let mut x = 0.0;
+        let mut width = 0.0;
+        let mut previous = None;
+        for word in paragraph.trim_end_matches('\r').split_inclusive(' ') {
+            let word_width = word
+                .trim_end()
+                .chars()
+                .map(|character| scaled.h_advance(scaled.glyph_id(character)))
+                .sum::<f32>();
+            if let Some(max_width) = style.max_width
+                && !line.is_empty()
+                && x + word_width > max_width
+            {
+                lines.push((std::mem::take(&mut line), width));
+                x = 0.0;
+                width = 0.0;
+                previous = None;
+            }
+            for character in word.chars() {
+                let id = scaled.glyph_id(character);
+                if let Some(previous) = previous {
+                    x += scaled.kern(previous, id);
+                }
+                line.push(LaidOutGlyph { id, x, y: 0.0 });
+                x += scaled.h_advance(id);
+                if !character.is_whitespace() {
+                    width = x;
+                }
+                previous = Some(id);
+            }
+        }
+        lines.push((line, width));
+    }
+
+    let width = lines.iter().map(|(_, width)| *width).fold(0.0, f32::max);
+    let height = lines.len() as f32 * line_height;
+    let mut glyphs = Vec::new();
+    for (index, (line, line_width)) in lines.into_iter().enumerate() {
+        let offset = match style.align {
+            TextAlign::Left => 0.0,
+            TextAlign::Center => -line_width * 0.5,
+            TextAlign::Right => -line_width,
+        };
+        let baseline = scaled.ascent() + index as f32 * line_height;
+        glyphs.extend(line.into_iter().map(|glyph| LaidOutGlyph {
+            x: glyph.x + offset,
+            y: baseline,
+            ..glyph
+        }));
+    }
+    TextLayout {
+        glyphs,
+        width,
+        height,
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+struct GlyphKey {
+    font: FontId,
+    id: GlyphId,
+    // Rasterized pixel size
+    size: u32,
+}
+
+#[derive(Copy, Clone, Debug)]
+struct AtlasGlyph {
+    // Normalized offset and size in the atlas
+    uv_rect: [f32; 4],
+    // Pixel bounds relative to the pen position, y down
+    min: [f32; 2],
+    size: [f32; 2],
+}
+
+struct AtlasFull;
+
+/// Glyphs rasterized on first use and packed into rows of a texture, wrapped in
+/// a `Material` so the quads bind it like any other base color texture.
+struct GlyphAtlas {
+    material: Material,
+    size: u32,
+    cursor: (u32, u32),
+    row_height: u32,
+    // None for glyphs without an outline, like spaces
+    glyphs: HashMap<GlyphKey, Option<AtlasGlyph>>,
+}
+
+impl GlyphAtlas {
+    fn new(device: &wgpu::Device, queue: &wgpu::Queue, size: u32) -> Self {
+        // White texels with the coverage in alpha, so the atlas works as a base color map
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("Glyph Atlas"),
+            size: wgpu::Extent3d {
+                width: size,
+                height: size,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: wgpu::TextureFormat::Rgba8Unorm,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+            view_formats: &[],
+        });
+        let material = Material::from_texture(
+            device,
+            queue,
+            texture,
+            MaterialFactors::default(),
+            AlphaMode::Blend,
+        );
+        Self {
+            material,
+            size,
+            cursor: (0, 0),
+            row_height: 0,
+            glyphs: HashMap::new(),
+        }
+    }
+
+    fn glyph(
+        &mut self,
+        queue: &wgpu::Queue,
+        fonts: &[Font],
+        key: GlyphKey,
+    ) -> Result<Option<AtlasGlyph>, AtlasFull> {
+        if let Some(glyph) = self.glyphs.get(&key) {
+            return Ok(*glyph);
+        }
+        let glyph = key.id.with_scale(PxScale::from(key.size as f32));
+        let Some(outline) = fonts[key.font].font.outline_glyph(glyph) else {
+            self.glyphs.insert(key, None);
+            return Ok(None);
+        };
+        let bounds = outline.px_bounds();
+        let width = bounds.width() as u32;
+        let height = bounds.height() as u32;
+
+        if self.cursor.0 + width + GLYPH_PADDING > self.size {
+            self.cursor = (0, self.cursor.1 + self.row_height);
+            self.row_height = 0;
+        }
+        if self.cursor.0 + width + GLYPH_PADDING > self.size
+            || self.cursor.1 + height + GLYPH_PADDING > self.size
+        {
+            return Err(AtlasFull);
+        }
+        let (x, y) = self.cursor;
+        self.cursor.0 += width + GLYPH_PADDING;
+        self.row_height = self.row_height.max(height + GLYPH_PADDING);
+
+        let mut pixels = [255, 255, 255, 0].repeat((width * height) as usize);
+        outline.draw(|pixel_x, pixel_y, coverage| {
+            let index = ((pixel_y * width + pixel_x) * 4 + 3) as usize;
+            pixels[index] = (coverage.clamp(0.0, 1.0) * 255.0).round() as u8;
+        });
+        if width > 0 && height > 0 {
+            queue.write_texture(
+                wgpu::TexelCopyTextureInfo {
+                    texture: &self.material.texture,
+                    mip_level: 0,
+                    origin: wgpu::Origin3d { x, y, z: 0 },
+                    aspect: wgpu::TextureAspect::All,
+                },
+                &pixels,
+                wgpu::TexelCopyBufferLayout {
+                    offset: 0,
+                    bytes_per_row: Some(width * 4),
+                    rows_per_image: Some(height),
+                },
+                wgpu::Extent3d {
+                    width,
+                    height,
+                    depth_or_array_layers: 1,
+                },
+            );
+        }
+
+        let atlas_size = self.size as f32;
+        let atlas_glyph = AtlasGlyph {
+            uv_rect: [
+                x as f32 / atlas_size,
+                y as f32 / atlas_size,
+                width as f32 / atlas_size,
+                height as f32 / atlas_size,
+            ],
+            min: [bounds.min.x, bounds.min.y],
+            size: [width as f32, height as f32],
+        };
+        self.glyphs.insert(key, Some(atlas_glyph));
+        Ok(Some(atlas_glyph))
+    }
+}
+
+enum TextSpace {
+    // Top-left anchor in logical pixels
+    Screen { position: [f32; 2] },
+    World { transform: Matrix4<f32> },
+}
+
+struct TextSection {
+    font: FontId,
+    text: String,
+    style: TextStyle,
+    space: TextSpace,
+}
+
+/// Text for HUDs and labels, drawn as instanced quads textured from a glyph atlas.
+/// World text is drawn at the end of the scene pass, screen text on top of the
+/// post-processed image. Like debug lines, queued text is drawn for one frame.
+pub struct TextRenderer {
+    pub fonts: Vec<Font>,
+    atlas: GlyphAtlas,
+    sections: Vec<TextSection>,
+    // One instance per glyph
+    quad: InstancedQuad,
+    world_pipelines: Vec<(u32, wgpu::RenderPipeline)>,
+    screen_pipeline: wgpu::RenderPipeline,
+    // Over the surface in physical pixels, screen text is scaled by the scale factor
+    screen_camera: PixelCamera,
+    world_instances: InstanceStorage,
+    screen_instances: InstanceStorage,
+}
+
+impl TextRenderer {
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        scene_format: wgpu::TextureFormat,
+        surface_format: wgpu::TextureFormat,
+        sample_count: u32,
+        surface_size: (u32, u32),
+        material_layout: &wgpu::BindGroupLayout,
+        camera_binding: &CameraBinding,
+    ) -> Self {
+        let build_pipeline = |format, sample_count, depth_tested, label| {
+            // World text can be seen from behind, the quad pipelines don't cull
+            let mut pipeline_builder = InstancedQuad::pipeline_builder(
+                device,
+                ShaderSource::Builtin("shaders/text.wgsl"),
+                "vs_text",
+                "fs_text",
+                format,
+                material_layout,
+                &camera_binding.bind_group_layout,
+            );
+            if depth_tested {
+                pipeline_builder.set_depth_stencil(
+                    DEPTH_FORMAT,
+                    false,
+                    wgpu::CompareFunction::LessEqual,
+                );
+            }
+            pipeline_builder.set_multisample(sample_count, false);
+            pipeline_builder.build_pipeline(label)
+        };
+        let mut world_pipelines = vec![(
+            1,
+            build_pipeline(scene_format, 1, true, "World Text Pipeline"),
+        )];
+        if sample_count > 1 {
+            world_pipelines.push((
+                sample_count,
+                build_pipeline(scene_format, sample_count, true, "World Text Pipeline"),
+            ));
+        }
+        let screen_pipeline = build_pipeline(surface_format, 1, false, "Screen Text Pipeline");
+
+        let screen_camera =
+            PixelCamera::new(device, (surface_size.0 as f32, surface_size.1 as f32));
+
+        Self {
+            fonts: Vec::new(),
+            atlas: GlyphAtlas::new(device, queue, INITIAL_ATLAS_SIZE),
+            sections: Vec::new(),
+            quad: InstancedQuad::new(device),
+            world_pipelines,
+            screen_pipeline,
+            screen_camera,
+            world_instances: InstancedQuad::create_instances(device, queue),
+            screen_instances: InstancedQuad::create_instances(device, queue),
+        }
+    }
+
+    pub fn add_font(&mut self, font: Font) -> FontId {
+        self.fonts.push(font);
+        self.fonts.len() - 1
+    }
+
+    // `position` is the top-left anchor in logical pixels, see `TextAlign`
+    pub fn screen_text(&mut self, font: FontId, text: &str, position: [f32; 2], style: &TextStyle) {
+        self.sections.push(TextSection {
+            font,
+            text: text.to_string(),
+            style: *style,
+            space: TextSpace::Screen { position },
+        });
+    }
+
+    // Text on the XY plane of `transform`, reading along +X with +Y up
+    pub fn world_text(
+        &mut self,
+        font: FontId,
+        text: &str,
+        transform: Matrix4<f32>,
+        style: &TextStyle,
+    ) {
+        self.sections.push(TextSection {
+            font,
+            text: text.to_string(),
+            style: *style,
+            space: TextSpace::World { transform },
+        });
+    }
+
+    /// Rasterizes missing glyphs and uploads this frame's instances. `surface_size`
+    /// is in pixels, screen text is scaled by `scale_factor`.
+    pub fn prepare(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        surface_size: (u32, u32),
+        scale_factor: f32,
+    ) {
+        self.screen_camera.update(
+            queue,
+            (surface_size.0 as f32, surface_size.1 as f32),
+            [0.0, 0.0],
+        );
+
+        let max_size = device.limits().max_texture_dimension_2d;
+        let mut evicted = false;
+        let (world, screen) = loop {
+            match build_instances(
+                &mut self.atlas,
+                queue,
+                &self.fonts,
+                &self.sections,
+                scale_factor,
+            ) {
+                Ok(instances) => break instances,
+                Err(AtlasFull) => {
+                    // Grow, or start over at the largest size to drop glyphs no longer used
+                    let size = (self.atlas.size * 2).min(max_size);
+                    if size == self.atlas.size && evicted {
+                        log::warn!("Glyph atlas is full, text skipped this frame");
+                        break (Vec::new(), Vec::new());
+                    }
+                    evicted = size == self.atlas.size;
+                    self.atlas = GlyphAtlas::new(device, queue, size);
+                }
+            }
+        };
+        self.world_instances.update(device, queue, &world);
+        self.screen_instances.update(device, queue, &screen);
+    }
+
+    pub fn has_screen_text(&self) -> bool {
+        self.screen_instances.count > 0
+    }
+
+    // `sample_count` of the attachments in `render_pass`
+    pub fn draw_world(
+        &self,
+        render_pass: &mut wgpu::RenderPass,
+        camera_binding: &CameraBinding,
+        sample_count: u32,
+    ) {
+        let pipeline = self
+            .world_pipelines
+            .iter()
+            .find(|(count, _)| *count == sample_count);
+        if let Some((_, pipeline)) = pipeline {
+            self.draw_glyphs(render_pass, pipeline, camera_binding, &self.world_instances);
+        }
+    }
+
+    // Into the surface, after post-processing
+    pub fn draw_screen(&self, render_pass: &mut wgpu::RenderPass) {
+        self.draw_glyphs(
+            render_pass,
+            &self.screen_pipeline,
+            &self.screen_camera.binding,
+            &self.screen_instances,
+        );
+    }
+
+    fn draw_glyphs(
+        &self,
+        render_pass: &mut wgpu::RenderPass,
+        pipeline: &wgpu::RenderPipeline,
+        camera_binding: &CameraBinding,
+        instances: &InstanceStorage,
+    ) {
+        if instances.count == 0 {
+            return;
+        }
+        render_pass.set_pipeline(pipeline);
+        render_pass.set_bind_group(0, &self.atlas.material.bind_group, &[]);
+        render_pass.set_bind_group(1, &camera_binding.bind_group, &[]);
+        self.quad.bind(render_pass, instances);
+        self.quad.draw(render_pass, 0..instances.count);
+    }
+
+    // Drops the text drawn this frame, call after the frame is submitted
+    pub fn end_frame(&mut self) {
+        self.sections.clear();
+    }
+}
+
+// World and screen glyph instances of every queued section
+fn build_instances(
+    atlas: &mut GlyphAtlas,
+    queue: &wgpu::Queue,
+    fonts: &[Font],
+    sections: &[TextSection],
+    scale_factor: f32,
+) -> Result<(Vec<QuadInstance>, Vec<QuadInstance>), AtlasFull> {
+    let mut world = Vec::new();
+    let mut screen = Vec::new();
+    for section in sections {
+        let style = &section.style;
+        // Maps layout units, which point y down, into the section's space
+        let (raster_size, base, instances) = match section.space {
+            TextSpace::Screen { position } => (
+                style.size * scale_factor,
+                Matrix4::from_translation(Vector3::new(
+                    position[0] * scale_factor,
+                    position[1] * scale_factor,
+                    0.0,
+                )) * Matrix4::from_scale(scale_factor),
+                &mut screen,
+            ),
+            TextSpace::World { transform } => (
+                WORLD_RASTER_SIZE,
+                transform * Matrix4::from_nonuniform_scale(1.0, -1.0, 1.0),
+                &mut world,
+            ),
+        };
+        let raster_size = raster_size.round().max(1.0) as u32;
+        // Layout units per rasterized pixel
+        let texel_scale = style.size / raster_size as f32;
+
+        let layout = layout_text(&fonts[section.font], &section.text, style);
+        for laid_out in layout.glyphs {
+            let key = GlyphKey {
+                font: section.font,
+                id: laid_out.id,
+                size: raster_size,
+            };
+            let Some(glyph) = atlas.glyph(queue, fonts, key)? else {
+                continue;
+            };
+            let width = glyph.size[0] * texel_scale;
+            let height = glyph.size[1] * texel_scale;
+            let center = Vector3::new(
+                laid_out.x + glyph.min[0] * texel_scale + width * 0.5,
+                laid_out.y + glyph.min[1] * texel_scale + height * 0.5,
+                0.0,
+            );
+            let model = base * quad_model(center, [width, height]);
+            instances.push(QuadInstance {
+                model: model.into(),
+                uv_rect: glyph.uv_rect,
+                color: style.color,
+            });
+        }
+    }
+    Ok((world, screen))
+}
+
+#[cfg(test)]
+mod tests {
+    use ab_glyph::{Outline, v2};
+
+    use super::*;
+
+    // egui's built-in proportional font, so the tests don't depend on system fonts
+    fn test_font() -> FontVec {
+        let fonts = egui::FontDefinitions::default();
+        FontVec::try_from_vec(fonts.font_data["Ubuntu-Light"].font.to_vec()).unwrap()
+    }
+
+    // The test font has no kerning table, this adds a single pair
+    struct Kerned {
+        font: FontVec,
+        pair: (GlyphId, GlyphId),
+        kern: f32,
+    }
+
+    impl ab_glyph::Font for Kerned {
+        fn units_per_em(&self) -> Option<f32> {
+            self.font.units_per_em()
+        }
+        fn ascent_unscaled(&self) -> f32 {
+            self.font.ascent_unscaled()
+        }
+        fn descent_unscaled(&self) -> f32 {
+            self.font.descent_unscaled()
+        }
+        fn line_gap_unscaled(&self) -> f32 {
+            self.font.line_gap_unscaled()
+        }
+        fn glyph_id(&self, character: char) -> GlyphId {
+            self.font.glyph_id(character)
+        }
+        fn h_advance_unscaled(&self, id: GlyphId) -> f32 {
+            self.font.h_advance_unscaled(id)
+        }
+        fn h_side_bearing_unscaled(&self, id: GlyphId) -> f32 {
+            self.font.h_side_bearing_unscaled(id)
+        }
+        fn v_advance_unscaled(&self, id: GlyphId) -> f32 {
+            self.font.v_advance_unscaled(id)
+        }
+        fn v_side_bearing_unscaled(&self, id: GlyphId) -> f32 {
+            self.font.v_side_bearing_unscaled(id)
+        }
+        fn kern_unscaled(&self, first: GlyphId, second: GlyphId) -> f32 {
+            if (first, second) == self.pair {
+                self.kern
+            } else {
+                self.font.kern_unscaled(first, second)
+            }
+        }
+        fn outline(&self, id: GlyphId) -> Option<Outline> {
+            self.font.outline(id)
+        }
+        fn glyph_count(&self) -> usize {
+            self.font.glyph_count()
+        }
+        fn codepoint_ids(&self) -> ab_glyph::CodepointIdIter<'_> {
+            self.font.codepoint_ids()
+        }
+        fn glyph_raster_image2(&self, id: GlyphId, size: u16) -> Option<v2::GlyphImage<'_>> {
+            self.font.glyph_raster_image2(id, size)
+        }
+    }
+
+    fn style(align: TextAlign, max_width: Option<f32>) -> TextStyle {
+        TextStyle {
+            size: 20.0,
+            align,
+            max_width,
+            ..Default::default()
+        }
+    }
+
+    fn line_height(font: &impl ab_glyph::Font, style: &TextStyle) -> f32 {
+        let scaled = font.as_scaled(PxScale::from(style.size));
+        scaled.ascent() - scaled.descent() + scaled.line_gap()
+    }
+
+    fn assert_close(a: f32, b: f32) {
+        assert!((a - b).abs() < 1e-3, "{} != {}", a, b);
+    }
+
+    #[test]
+    fn glyphs_advance_along_the_first_baseline() {
+        let font = test_font();
+        let style = style(TextAlign::Left, None);
+        let scaled = font.as_scaled(PxScale::from(style.size));
+        let layout = layout_glyphs(&font, "Hi", &style);
+
+        let advance = scaled.h_advance(scaled.glyph_id('H'));
+        assert_eq!(layout.glyphs.len(), 2);
+        assert_close(layout.glyphs[0].x, 0.0);
+        assert_close(layout.glyphs[1].x, advance);
+        for glyph in &layout.glyphs {
+            assert_close(glyph.y, scaled.ascent());
+        }
+        assert_close(
+            layout.width,
+            advance + scaled.h_advance(scaled.glyph_id('i')),
+        );
+        assert_close(layout.height, line_height(&font, &style));
+    }
+
+    #[test]
+    fn kerning_moves_the_following_glyphs() {
+        let plain = test_font();
+        let pair = (plain.glyph_id('A'), plain.glyph_id('V'));
+        let font = Kerned {
+            font: test_font(),
+            pair,
+            kern: -100.0,
+        };
+        let style = style(TextAlign::Left, None);
+        let scaled = font.as_scaled(PxScale::from(style.size));
+        let kern = scaled.kern(pair.0, pair.1);
+        assert!(kern < 0.0);
+
+        let kerned = layout_glyphs(&font, "AVA", &style);
+        let unkerned = layout_glyphs(&plain, "AVA", &style);
+        assert_close(kerned.glyphs[1].x, scaled.h_advance(pair.0) + kern);
+        assert_close(kerned.glyphs[1].x, unkerned.glyphs[1].x + kern);
+        assert_close(kerned.glyphs[2].x, unkerned.glyphs[2].x + kern);
+        assert_close(kerned.width, unkerned.width + kern);
+    }
+
+    #[test]
+    fn newlines_start_a_new_line() {
+        let font = test_font();
+        let style = style(TextAlign::Left, None);
+        let layout = layout_glyphs(&font, "ab\ncd", &style);
+
+        assert_eq!(layout.glyphs.len(), 4);
+        assert_close(layout.glyphs[2].x, 0.0);
+        assert_close(
+            layout.glyphs[2].y,
+            layout.glyphs[0].y + line_height(&font, &style),
+        );
+        assert_close(layout.height, 2.0 * line_height(&font, &style));
+    }
+
+    #[test]
+    fn wrapping_breaks_at_word_boundaries() {
+        let font = test_font();
+        let two_words = layout_glyphs(&font, "one two", &style(TextAlign::Left, None)).width;
+        let style = style(TextAlign::Left, Some(two_words));
+        let layout = layout_glyphs(&font, "one two three", &style);
+
+        // "three" starts the second line, the space before it stays on the first
+        let three = &layout.glyphs[8];
+        assert_close(three.x, 0.0);
+        assert_close(three.y, layout.glyphs[0].y + line_height(&font, &style));
+        assert_close(layout.width, two_words);
+        assert_close(layout.height, 2.0 * line_height(&font, &style));
+    }
+
+    #[test]
+    fn words_wider_than_the_wrap_width_are_not_split() {
+        let font = test_font();
+        let style = style(TextAlign::Left, Some(1.0));
+        let layout = layout_glyphs(&font, "abc de", &style);
+
+        let baseline = layout.glyphs[0].y;
+        assert!(layout.glyphs[..4].iter().all(|glyph| glyph.y == baseline));
+        assert_close(layout.glyphs[4].x, 0.0);
+        assert_close(layout.glyphs[4].y, baseline + line_height(&font, &style));
+        assert_close(layout.height, 2.0 * line_height(&font, &style));
+    }
+
+    #[test]
+    fn trailing_spaces_do_not_count_towards_the_width() {
+        let font = test_font();
+        let style = style(TextAlign::Left, None);
+        assert_close(
+            layout_glyphs(&font, "ab  ", &style).width,
+            layout_glyphs(&font, "ab", &style).width,
+        );
+    }
+
+    #[test]
+    fn lines_are_aligned_around_the_anchor() {
+        let font = test_font();
+        let left = |text| layout_glyphs(&font, text, &style(TextAlign::Left, None)).width;
+        let (short, long) = (left("a"), left("abc"));
+
+        let center = layout_glyphs(&font, "a\nabc", &style(TextAlign::Center, None));
+        assert_close(center.glyphs[0].x, -short * 0.5);
+        assert_close(center.glyphs[1].x, -long * 0.5);
+        assert_close(center.width, long);
+
+        let right = layout_glyphs(&font, "a\nabc", &style(TextAlign::Right, None));
+        assert_close(right.glyphs[0].x, -short);
+        assert_close(right.glyphs[1].x, -long);
+    }
+}
@@ -0,0 +1,158 @@
+use std::cell::RefCell;
+
+use super::{pipeline_builder, post_process::HDR_FORMAT};
+
+// Accumulated premultiplied color and weight, then revealage (product of 1 - alpha)
+pub const OIT_FORMATS: [wgpu::TextureFormat; 2] = [
+    wgpu::TextureFormat::Rgba16Float,
+    wgpu::TextureFormat::R8Unorm,
+];
+
+// Cleared values of the OIT targets, nothing accumulated and fully revealed
+pub const OIT_CLEAR: [wgpu::Color; 2] = [wgpu::Color::TRANSPARENT, wgpu::Color::WHITE];
+
+/// How materials with `AlphaMode::Blend` are drawn after the opaque geometry.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum TransparencyMode {
+    // Alpha blended back to front by camera distance, exact unless objects intersect
+    Sorted,
+    // Weighted blended order-independent transparency, no sorting but approximate
+    WeightedBlended,
+}
+
+// Additive accumulation, and revealage multiplied by 1 - alpha
+pub const OIT_BLEND_STATES: [wgpu::BlendState; 2] = [
+    wgpu::BlendState {
+        color: wgpu::BlendComponent {
+            src_factor: wgpu::BlendFactor::One,
+            dst_factor: wgpu::BlendFactor::One,
+            operation: wgpu::BlendOperation::Add,
+        },
+        alpha: wgpu::BlendComponent {
+            src_factor: wgpu::BlendFactor::One,
+            dst_factor: wgpu::BlendFactor::One,
+            operation: wgpu::BlendOperation::Add,
+        },
+    },
+    wgpu::BlendState {
+        color: wgpu::BlendComponent {
+            src_factor: wgpu::BlendFactor::Zero,
+            dst_factor: wgpu::BlendFactor::OneMinusSrc,
+            operation: wgpu::BlendOperation::Add,
+        },
+        alpha: wgpu::BlendComponent {
+            src_factor: wgpu::BlendFactor::Zero,
+            dst_factor: wgpu::BlendFactor::OneMinusSrc,
+            operation: wgpu::BlendOperation::Add,
+        },
+    },
+];
+
+/// Fullscreen pass blending the resolved OIT targets over the scene color, or
+/// over its multisampled target when world text and debug lines follow.
+// Bind group of the OIT targets and the accum and revealage views it was created with
+struct OitBindGroup {
+    views: [wgpu::TextureView; 2],
+    bind_group: wgpu::BindGroup,
+}
+
+pub struct OitCompositor {
+    pub pipeline: wgpu::RenderPipeline,
+    pub multisampled_pipeline: Option<(u32, wgpu::RenderPipeline)>,
+    pub bind_group_layout: wgpu::BindGroupLayout,
+    // Rebuilt when the graph hands out other OIT targets, like on resize
+    bind_group: RefCell<Option<OitBindGroup>>,
+}
+
+impl OitCompositor {
+    pub fn new(device: &wgpu::Device, sample_count: u32) -> Self {
+        let texture_entry = |binding| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::FRAGMENT,
+            ty: wgpu::BindingType::Texture {
+                multisampled: false,
+                view_dimension: wgpu::TextureViewDimension::D2,
+                sample_type: wgpu::TextureSampleType::Float { filterable: false },
+            },
+            count: None,
+        };
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: &[texture_entry(0), texture_entry(1)],
+            label: Some("oit_bind_group_layout"),
+        });
+
+        let build_pipeline = |sample_count| {
+            let mut pipeline_builder = pipeline_builder::PipelineBuilder::new(
+                device,
+                pipeline_builder::ShaderSource::Builtin("shaders/oit.wgsl"),
+                "vs_composite",
+                "fs_composite",
+                HDR_FORMAT,
+            );
+            pipeline_builder.add_bind_group_layout(&bind_group_layout);
+            pipeline_builder.set_cull_mode(None);
+            pipeline_builder.set_blend_states(&[wgpu::BlendState::ALPHA_BLENDING]);
+            pipeline_builder.set_multisample(sample_count, false);
+            pipeline_builder.build_pipeline("OIT Composite Pipeline")
+        };
+        let pipeline = build_pipeline(1);
+        let multisampled_pipeline =
+            (sample_count > 1).then(|| (sample_count, build_pipeline(sample_count)));
+
+        Self {
+            pipeline,
+            multisampled_pipeline,
+            bind_group_layout,
+            bind_group: RefCell::new(None),
+        }
+    }
+
+    // Releases the old OIT targets, the next draw rebuilds the bind group
+    pub fn resize(&self) {
+        self.bind_group.replace(None);
+    }
+
+    // `sample_count` of the attachments in `render_pass`
+    pub fn draw(
+        &self,
+        device: &wgpu::Device,
+        render_pass: &mut wgpu::RenderPass,
+        accum: &wgpu::TextureView,
+        revealage: &wgpu::TextureView,
+        sample_count: u32,
+    ) {
+        let mut cached = self.bind_group.borrow_mut();
+        let outdated = cached
+            .as_ref()
+            .is_none_or(|cached| cached.views[0] != *accum || cached.views[1] != *revealage);
+        if outdated {
+            let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+                layout: &self.bind_group_layout,
+                entries: &[
+                    wgpu::BindGroupEntry {
+                        binding: 0,
+                        resource: wgpu::BindingResource::TextureView(accum),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 1,
+                        resource: wgpu::BindingResource::TextureView(revealage),
+                    },
+                ],
+                label: Some("oit_bind_group"),
+            });
+            *cached = Some(OitBindGroup {
+                views: [accum.clone(), revealage.clone()],
+                bind_group,
+            });
+        }
+        let bind_group = &cached.as_ref().unwrap().bind_group;
+
+        let pipeline = match &self.multisampled_pipeline {
+            Some((count, pipeline)) if *count == sample_count => pipeline,
+            _ => &self.pipeline,
+        };
+        render_pass.set_pipeline(pipeline);
+        render_pass.set_bind_group(0, bind_group, &[]);
+        render_pass.draw(0..3, 0..1);
+    }
+}
@@ -0,0 +1,686 @@
+use std::{collections::HashMap, time::Instant};
+
+use cgmath::{Deg, Euler, Quaternion};
+use egui::epaint::{ClippedPrimitive, Primitive, Vertex as UiVertex};
+
+use super::{
+    camera::Projection,
+    frame_loop::FrameTime,
+    instance::Instance,
+    pipeline_builder,
+    state::State,
+    window::{Key, Modifiers, MouseButton, WindowEvent},
+};
+
+#[repr(C)]
+#[derive(Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct UiScreen {
+    size: [f32; 2],
+    _padding: [f32; 2],
+}
+
+struct UiTexture {
+    texture: wgpu::Texture,
+    bind_group: wgpu::BindGroup,
+}
+
+// Indices of one clipped mesh in the frame's buffers
+struct UiDraw {
+    // In points
+    clip_rect: egui::Rect,
+    texture: egui::TextureId,
+    indices: std::ops::Range<u32>,
+    base_vertex: i32,
+}
+
+/// egui drawn over the final image. Window events are translated into egui input,
+/// `begin_frame` starts a pass whose shapes `prepare` tessellates and uploads, and
+/// `draw` renders them into the surface after post-processing.
+pub struct UiOverlay {
+    pub context: egui::Context,
+    events: Vec<egui::Event>,
+    // In points
+    pointer_position: egui::Pos2,
+    modifiers: egui::Modifiers,
+    focused: bool,
+    start: Instant,
+    pass_active: bool,
+    pipeline: wgpu::RenderPipeline,
+    screen_buffer: wgpu::Buffer,
+    screen_bind_group: wgpu::BindGroup,
+    texture_bind_group_layout: wgpu::BindGroupLayout,
+    textures: HashMap<egui::TextureId, UiTexture>,
+    // Freed once the frame using them is submitted
+    textures_to_free: Vec<egui::TextureId>,
+    // Texture updates of passes that were never rendered, applied by the next `prepare`
+    pending_textures: egui::TexturesDelta,
+    vertex_buffer: wgpu::Buffer,
+    index_buffer: wgpu::Buffer,
+    draws: Vec<UiDraw>,
+    pixels_per_point: f32,
+}
+
+impl UiOverlay {
+    pub fn new(device: &wgpu::Device, surface_format: wgpu::TextureFormat) -> Self {
+        let screen_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("UI Screen Buffer"),
+            size: std::mem::size_of::<UiScreen>() as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let screen_bind_group_layout =
+            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                entries: &[wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::VERTEX,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                }],
+                label: Some("ui_screen_bind_group_layout"),
+            });
+        let screen_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &screen_bind_group_layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: screen_buffer.as_entire_binding(),
+            }],
+            label: Some("ui_screen_bind_group"),
+        });
+        let texture_bind_group_layout =
+            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                entries: &[
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 0,
+                        visibility: wgpu::ShaderStages::FRAGMENT,
+                        ty: wgpu::BindingType::Texture {
+                            multisampled: false,
+                            view_dimension: wgpu::TextureViewDimension::D2,
+                            sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                        },
+                        count: None,
+                    },
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 1,
+                        visibility: wgpu::ShaderStages::FRAGMENT,
+                        ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                        count: None,
+                    },
+                ],
+                label: Some("ui_texture_bind_group_layout"),
+            });
+
+        const ATTRIBUTES: [wgpu::VertexAttribute; 3] =
+            wgpu::vertex_attr_array![0 => Float32x2, 1 => Float32x2, 2 => Unorm8x4];
+        let mut pipeline_builder = pipeline_builder::PipelineBuilder::new(
+            device,
+            pipeline_builder::ShaderSource::Builtin("shaders/ui.wgsl"),
+            "vs_ui",
+            "fs_ui",
+            surface_format,
+        );
+        pipeline_builder.add_bind_group_layout(&screen_bind_group_layout);
+        pipeline_builder.add_bind_group_layout(&texture_bind_group_layout);
+        pipeline_builder.add_vertex_buffer_layout(wgpu::VertexBufferLayout {
+            array_stride: std::mem::size_of::<UiVertex>() as wgpu::BufferAddress,
+            step_mode: wgpu::VertexStepMode::Vertex,
+            attributes: &ATTRIBUTES,
+        });
+        pipeline_builder.set_cull_mode(None);
+        pipeline_builder.set_blend_states(&[wgpu::BlendState::PREMULTIPLIED_ALPHA_BLENDING]);
+        if !surface_format.is_srgb() {
+            pipeline_builder.set_constant("gamma_output", 1.0);
+        }
+        let pipeline = pipeline_builder.build_pipeline("UI Pipeline");
+
+        Self {
+            context: egui::Context::default(),
+            events: Vec::new(),
+            pointer_position: egui::Pos2::ZERO,
+            modifiers: egui::Modifiers::default(),
+            focused: true,
+            start: Instant::now(),
+            pass_active: false,
+            pipeline,
+            screen_buffer,
+            screen_bind_group,
+            texture_bind_group_layout,
+            textures: HashMap::new(),
+            textures_to_free: Vec::new(),
+            pending_textures: egui::TexturesDelta::default(),
+            vertex_buffer: Self::create_buffer(
+                device,
+                "UI Vertex Buffer",
+                0,
+                wgpu::BufferUsages::VERTEX,
+            ),
+            index_buffer: Self::create_buffer(
+                device,
+                "UI Index Buffer",
+                0,
+                wgpu::BufferUsages::INDEX,
+            ),
+            draws: Vec::new(),
+            pixels_per_point: 1.0,
+        }
+    }
+
+    fn create_buffer(
+        device: &wgpu::Device,
+        label: &str,
+        size: wgpu::BufferAddress,
+        usage: wgpu::BufferUsages,
+    ) -> wgpu::Buffer {
+        device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some(label),
+            // Zero sized buffers can't be bound
+            size: size.max(1024).next_power_of_two(),
+            usage: usage | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        })
+    }
+
+    // Events the overlay uses, which the application shouldn't react to as well
+    pub fn consumes(&self, event: &WindowEvent) -> bool {
+        match event {
+            WindowEvent::CursorMoved { .. }
+            | WindowEvent::MouseButton { .. }
+            | WindowEvent::Scroll { .. } => self.context.wants_pointer_input(),
+            WindowEvent::Key { .. } | WindowEvent::Char(_) => self.context.wants_keyboard_input(),
+            _ => false,
+        }
+    }
+
+    /// Queues `event` as egui input for the next `begin_frame`. Positions are
+    /// converted from pixels to points with `scale_factor`.
+    pub fn handle_event(&mut self, event: &WindowEvent, scale_factor: f32) {
+        match *event {
+            WindowEvent::Key {
+                key,
+                pressed,
+                repeat,
+                modifiers,
+            } => {
+                self.modifiers = translate_modifiers(modifiers);
+                if let Some(key) = translate_key(key) {
+                    self.events.push(egui::Event::Key {
+                        key,
+                        physical_key: None,
+                        pressed,
+                        repeat,
+                        modifiers: self.modifiers,
+                    });
+                }
+            }
+            WindowEvent::Char(character) => {
+                self.events.push(egui::Event::Text(character.to_string()));
+            }
+            WindowEvent::CursorMoved { x, y } => {
+                self.pointer_position = egui::pos2(x / scale_factor, y / scale_factor);
+                self.events
+                    .push(egui::Event::PointerMoved(self.pointer_position));
+            }
+            WindowEvent::MouseButton {
+                button,
+                pressed,
+                modifiers,
+            } => {
+                self.modifiers = translate_modifiers(modifiers);
+                let button = match button {
+                    MouseButton::Left => egui::PointerButton::Primary,
+                    MouseButton::Right => egui::PointerButton::Secondary,
+                    MouseButton::Middle => egui::PointerButton::Middle,
+                    MouseButton::Other(3) => egui::PointerButton::Extra1,
+                    MouseButton::Other(_) => egui::PointerButton::Extra2,
+                };
+                self.events.push(egui::Event::PointerButton {
+                    pos: self.pointer_position,
+                    button,
+                    pressed,
+                    modifiers: self.modifiers,
+                });
+            }
+            WindowEvent::Scroll { x, y } => {
+                self.events.push(egui::Event::MouseWheel {
+                    unit: egui::MouseWheelUnit::Line,
+                    delta: egui::vec2(x, y),
+                    modifiers: self.modifiers,
+                });
+            }
+            WindowEvent::Focused(focused) => {
+                self.focused = focused;
+                self.events.push(egui::Event::WindowFocused(focused));
+            }
+            _ => {}
+        }
+    }
+
+    /// Starts a UI pass for a `size` pixel surface, build windows on the returned
+    /// context before `State::render`.
+    pub fn begin_frame(&mut self, size: (u32, u32), scale_factor: f32) -> egui::Context {
+        let mut raw_input = egui::RawInput {
+            screen_rect: Some(egui::Rect::from_min_size(
+                egui::Pos2::ZERO,
+                egui::vec2(size.0 as f32, size.1 as f32) / scale_factor,
+            )),
+            time: Some(self.start.elapsed().as_secs_f64()),
+            modifiers: self.modifiers,
+            events: std::mem::take(&mut self.events),
+            focused: self.focused,
+            ..Default::default()
+        };
+        raw_input
+            .viewports
+            .entry(raw_input.viewport_id)
+            .or_default()
+            .native_pixels_per_point = Some(scale_factor);
+        if self.pass_active {
+            // The previous pass was never rendered, but egui won't resend its texture
+            // updates (e.g. the font atlas)
+            let output = self.context.end_pass();
+            self.pending_textures.append(output.textures_delta);
+        }
+        self.context.begin_pass(raw_input);
+        self.pass_active = true;
+        self.context.clone()
+    }
+
+    /// Ends the pass started by `begin_frame`, updates textures and uploads the
+    /// tessellated meshes. Nothing is drawn when no pass was started.
+    pub fn prepare(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, size: (u32, u32)) {
+        self.draws.clear();
+        if !self.pass_active {
+            return;
+        }
+        self.pass_active = false;
+        let output = self.context.end_pass();
+
+        let mut textures_delta = std::mem::take(&mut self.pending_textures);
+        textures_delta.append(output.textures_delta);
+        for (id, delta) in &textures_delta.set {
+            self.update_texture(device, queue, *id, delta);
+        }
+        self.textures_to_free
+            .extend(textures_delta.free.iter().copied());
+
+        self.pixels_per_point = output.pixels_per_point;
+        let primitives = self
+            .context
+            .tessellate(output.shapes, output.pixels_per_point);
+        let mut vertices = Vec::new();
+        let mut indices = Vec::new();
+        for ClippedPrimitive {
+            clip_rect,
+            primitive,
+        } in primitives
+        {
+            // Paint callbacks are not supported
+            let Primitive::Mesh(mesh) = primitive else {
+                continue;
+            };
+            let first_index = indices.len() as u32;
+            self.draws.push(UiDraw {
+                clip_rect,
+                texture: mesh.texture_id,
+                indices: first_index..first_index + mesh.indices.len() as u32,
+                base_vertex: vertices.len() as i32,
+            });
+            vertices.extend_from_slice(&mesh.vertices);
+            indices.extend_from_slice(&mesh.indices);
+        }
+
+        let vertex_bytes: &[u8] = bytemuck::cast_slice(&vertices);
+        let index_bytes: &[u8] = bytemuck::cast_slice(&indices);
+        if vertex_bytes.len() as wgpu::BufferAddress > self.vertex_buffer.size() {
+            self.vertex_buffer = Self::create_buffer(
+                device,
+                "UI Vertex Buffer",
+                vertex_bytes.len() as wgpu::BufferAddress,
+                wgpu::BufferUsages::VERTEX,
+            );
+        }
+        if index_bytes.len() as wgpu::BufferAddress > self.index_buffer.size() {
+            self.index_buffer = Self::create_buffer(
+                device,
+                "UI Index Buffer",
+                index_bytes.len() as wgpu::BufferAddress,
+                wgpu::BufferUsages::INDEX,
+            );
+        }
+        queue.write_buffer(&self.vertex_buffer, 0, vertex_bytes);
+        queue.write_buffer(&self.index_buffer, 0, index_bytes);
+        let screen = UiScreen {
+            size: [
+                size.0 as f32 / self.pixels_per_point,
+                size.1 as f32 / self.pixels_per_point,
+            ],
+            _padding: [0.0; 2],
+        };
+        queue.write_buffer(&self.screen_buffer, 0, bytemuck::cast_slice(&[screen]));
+    }
+
+    fn update_texture(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        id: egui::TextureId,
+        delta: &egui::epaint::ImageDelta,
+    ) {
+        let egui::ImageData::Color(image) = &delta.image;
+        let [width, height] = image.size.map(|side| side as u32);
+        let origin = match delta.pos {
+            Some([x, y]) => wgpu::Origin3d {
+                x: x as u32,
+                y: y as u32,
+                z: 0,
+            },
+            // A whole new image replaces the texture
+            None => {
+                let texture = device.create_texture(&wgpu::TextureDescriptor {
+                    label: Some("UI Texture"),
+                    size: wgpu::Extent3d {
+                        width,
+                        height,
+                        depth_or_array_layers: 1,
+                    },
+                    mip_level_count: 1,
+                    sample_count: 1,
+                    dimension: wgpu::TextureDimension::D2,
+                    format: wgpu::TextureFormat::Rgba8UnormSrgb,
+                    usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+                    view_formats: &[],
+                });
+                let filter = |filter| match filter {
+                    egui::TextureFilter::Nearest => wgpu::FilterMode::Nearest,
+                    egui::TextureFilter::Linear => wgpu::FilterMode::Linear,
+                };
+                let address_mode = match delta.options.wrap_mode {
+                    egui::TextureWrapMode::ClampToEdge => wgpu::AddressMode::ClampToEdge,
+                    egui::TextureWrapMode::Repeat => wgpu::AddressMode::Repeat,
+                    egui::TextureWrapMode::MirroredRepeat => wgpu::AddressMode::MirrorRepeat,
+                };
+                let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+                    label: Some("UI Sampler"),
+                    address_mode_u: address_mode,
+                    address_mode_v: address_mode,
+                    mag_filter: filter(delta.options.magnification),
+                    min_filter: filter(delta.options.minification),
+                    ..Default::default()
+                });
+                let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+                let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+                    layout: &self.texture_bind_group_layout,
+                    entries: &[
+                        wgpu::BindGroupEntry {
+                            binding: 0,
+                            resource: wgpu::BindingResource::TextureView(&view),
+                        },
+                        wgpu::BindGroupEntry {
+                            binding: 1,
+                            resource: wgpu::BindingResource::Sampler(&sampler),
+                        },
+                    ],
+                    label: Some("ui_texture_bind_group"),
+                });
+                self.textures.insert(
+                    id,
+                    UiTexture {
+                        texture,
+                        bind_group,
+                    },
+                );
+                wgpu::Origin3d::ZERO
+            }
+        };
+        let Some(texture) = self.textures.get(&id) else {
+            log::warn!("Partial update of unknown UI texture {:?}", id);
+            return;
+        };
+        queue.write_texture(
+            wgpu::TexelCopyTextureInfo {
+                texture: &texture.texture,
+                mip_level: 0,
+                origin,
+                aspect: wgpu::TextureAspect::All,
+            },
+            bytemuck::cast_slice(&image.pixels),
+            wgpu::TexelCopyBufferLayout {
+                offset: 0,
+                bytes_per_row: Some(width * 4),
+                rows_per_image: Some(height),
+            },
+            wgpu::Extent3d {
+                width,
+                height,
+                depth_or_array_layers: 1,
+            },
+        );
+    }
+
+    pub fn has_draws(&self) -> bool {
+        !self.draws.is_empty()
+    }
+
+    // Into the `size` pixel surface, after post-processing
+    pub fn draw(&self, render_pass: &mut wgpu::RenderPass, size: (u32, u32)) {
+        render_pass.set_pipeline(&self.pipeline);
+        render_pass.set_bind_group(0, &self.screen_bind_group, &[]);
+        render_pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
+        render_pass.set_index_buffer(self.index_buffer.slice(..), wgpu::IndexFormat::Uint32);
+        for draw in &self.draws {
+            let Some(texture) = self.textures.get(&draw.texture) else {
+                continue;
+            };
+            // Clip rectangle in pixels, clamped to the surface
+            let min_x = (draw.clip_rect.min.x * self.pixels_per_point)
+                .round()
+                .max(0.0) as u32;
+            let min_y = (draw.clip_rect.min.y * self.pixels_per_point)
+                .round()
+                .max(0.0) as u32;
+            let max_x = ((draw.clip_rect.max.x * self.pixels_per_point)
+                .round()
+                .max(0.0) as u32)
+                .min(size.0);
+            let max_y = ((draw.clip_rect.max.y * self.pixels_per_point)
+                .round()
+                .max(0.0) as u32)
+                .min(size.1);
+            if max_x <= min_x || max_y <= min_y {
+                continue;
+            }
+            render_pass.set_scissor_rect(min_x, min_y, max_x - min_x, max_y - min_y);
+            render_pass.set_bind_group(1, &texture.bind_group, &[]);
+            render_pass.draw_indexed(draw.indices.clone(), draw.base_vertex, 0..1);
+        }
+    }
+
+    // Frees the textures egui no longer uses, call after the frame is submitted
+    pub fn end_frame(&mut self) {
+        for id in self.textures_to_free.drain(..) {
+            if let Some(texture) = self.textures.remove(&id) {
+                texture.texture.destroy();
+            }
+        }
+    }
+}
+
+fn translate_modifiers(modifiers: Modifiers) -> egui::Modifiers {
+    egui::Modifiers {
+        alt: modifiers.alt,
+        ctrl: modifiers.control,
+        shift: modifiers.shift,
+        mac_cmd: cfg!(target_os = "macos") && modifiers.super_key,
+        command: if cfg!(target_os = "macos") {
+            modifiers.super_key
+        } else {
+            modifiers.control
+        },
+    }
+}
+
+fn translate_key(key: Key) -> Option<egui::Key> {
+    let key = match key {
+        Key::Escape => egui::Key::Escape,
+        Key::Enter => egui::Key::Enter,
+        Key::Tab => egui::Key::Tab,
+        Key::Backspace => egui::Key::Backspace,
+        Key::Delete => egui::Key::Delete,
+        Key::Insert => egui::Key::Insert,
+        Key::Home => egui::Key::Home,
+        Key::End => egui::Key::End,
+        Key::PageUp => egui::Key::PageUp,
+        Key::PageDown => egui::Key::PageDown,
+        Key::Left => egui::Key::ArrowLeft,
+        Key::Right => egui::Key::ArrowRight,
+        Key::Up => egui::Key::ArrowUp,
+        Key::Down => egui::Key::ArrowDown,
+        Key::Space => egui::Key::Space,
+        Key::F(number) => egui::Key::from_name(&format!("F{}", number))?,
+        Key::Character(character) => {
+            egui::Key::from_name(&character.to_ascii_uppercase().to_string())?
+        }
+        Key::Unknown => return None,
+    };
+    Some(key)
+}
+
+/// Window with frame timings, the camera, material factors and the live
+/// `state.instances`, toggled with F1 by the runner.
+pub fn debug_panel(context: &egui::Context, state: &mut State, frame_time: &FrameTime) {
+    egui::Window::new("Debug")
+        .default_width(280.0)
+        .show(context, |ui| {
+            ui.heading("Frame");
+            ui.label(format!(
+                "{:.2} ms ({:.0} fps)",
+                frame_time.delta * 1000.0,
+                1.0 / frame_time.delta.max(f32::EPSILON)
+            ));
+            ui.label(format!(
+                "Frame {}, {} updates, alpha {:.2}",
+                frame_time.frame, frame_time.updates, frame_time.alpha
+            ));
+            ui.label(format!("Elapsed {:.1} s", frame_time.elapsed));
+
+            egui::CollapsingHeader::new("Camera").show(ui, |ui| {
+                let camera = &mut state.camera;
+                vector_row(
+                    ui,
+                    "Eye",
+                    &mut camera.eye.x,
+                    &mut camera.eye.y,
+                    &mut camera.eye.z,
+                );
+                vector_row(
+                    ui,
+                    "Target",
+                    &mut camera.target.x,
+                    &mut camera.target.y,
+                    &mut camera.target.z,
+                );
+                match &mut camera.projection {
+                    Projection::Perspective { fovy } => {
+                        ui.add(egui::Slider::new(&mut fovy.0, 10.0..=120.0).text("Fov Y"));
+                    }
+                    Projection::Orthographic { half_height } => {
+                        ui.add(egui::Slider::new(half_height, 0.1..=50.0).text("Half height"));
+                    }
+                }
+            });
+
+            egui::CollapsingHeader::new("Materials").show(ui, |ui| {
+                for (index, material) in state.materials.iter_mut().enumerate() {
+                    ui.push_id(index, |ui| {
+                        ui.label(format!("Material {}", index));
+                        let factors = &mut material.factors;
+                        let mut changed = ui
+                            .color_edit_button_rgba_unmultiplied(&mut factors.base_color)
+                            .changed();
+                        changed |= ui
+                            .add(
+                                egui::Slider::new(&mut factors.metallic, 0.0..=1.0)
+                                    .text("Metallic"),
+                            )
+                            .changed();
+                        changed |= ui
+                            .add(
+                                egui::Slider::new(&mut factors.roughness, 0.0..=1.0)
+                                    .text("Roughness"),
+                            )
+                            .changed();
+                        if changed {
+                            material.update_factors(&state.queue);
+                        }
+                    });
+                }
+            });
+
+            egui::CollapsingHeader::new(format!("Instances ({})", state.instances.len()))
+                .default_open(true)
+                .show(ui, |ui| {
+                    ui.horizontal(|ui| {
+                        if ui.button("Add").clicked() {
+                            let instance = state.instances.last().map_or(
+                                Instance {
+                                    position: cgmath::Vector3::new(0.0, 0.0, 0.0),
+                                    rotation: Quaternion::new(1.0, 0.0, 0.0, 0.0),
+                                    scale: cgmath::Vector3::new(1.0, 1.0, 1.0),
+                                },
+                                |last| Instance { ..*last },
+                            );
+                            state.instances.push(instance);
+                        }
+                        if ui.button("Remove").clicked() {
+                            state.instances.pop();
+                        }
+                    });
+                    egui::ScrollArea::vertical()
+                        .max_height(300.0)
+                        .show(ui, |ui| {
+                            for (index, instance) in state.instances.iter_mut().enumerate() {
+                                egui::CollapsingHeader::new(format!("Instance {}", index))
+                                    .show(ui, |ui| instance_editor(ui, instance));
+                            }
+                        });
+                });
+        });
+}
+
+fn instance_editor(ui: &mut egui::Ui, instance: &mut Instance) {
+    let position = &mut instance.position;
+    vector_row(
+        ui,
+        "Position",
+        &mut position.x,
+        &mut position.y,
+        &mut position.z,
+    );
+
+    // Edited as Euler angles, written back only when changed so the quaternion doesn't drift
+    let euler = Euler::from(instance.rotation);
+    let mut angles = [euler.x, euler.y, euler.z].map(|angle| Deg::from(angle).0);
+    let [x, y, z] = &mut angles;
+    if vector_row(ui, "Rotation", x, y, z) {
+        instance.rotation = Quaternion::from(Euler::new(Deg(*x), Deg(*y), Deg(*z)));
+    }
+
+    let scale = &mut instance.scale;
+    vector_row(ui, "Scale", &mut scale.x, &mut scale.y, &mut scale.z);
+}
+
+// Returns whether any component changed
+fn vector_row(ui: &mut egui::Ui, label: &str, x: &mut f32, y: &mut f32, z: &mut f32) -> bool {
+    ui.horizontal(|ui| {
+        ui.label(label);
+        let mut changed = false;
+        for value in [x, y, z] {
+            changed |= ui.add(egui::DragValue::new(value).speed(0.01)).changed();
+        }
+        changed
+    })
+    .inner
+}
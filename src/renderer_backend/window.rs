@@ -0,0 +1,94 @@
+use wgpu::rwh::{HasDisplayHandle, HasWindowHandle};
+
+/// A window `State` can render into. Implemented for the glfw and winit windows
+/// behind the `glfw` and `winit` features, other windowing libraries only need
+/// raw window handles and a size.
+pub trait RenderWindow: HasWindowHandle + HasDisplayHandle {
+    // Size of the framebuffer in physical pixels, which is what the surface is
+    // configured with. Differs from the window size in screen coordinates on HiDPI
+    // displays.
+    fn size(&self) -> (u32, u32);
+
+    // Physical pixels per logical pixel, for sizing UI and text
+    fn scale_factor(&self) -> f32;
+}
+
+/// Window input translated from the backend's events. Sizes and positions are
+/// in physical pixels.
+#[derive(Clone, Debug, PartialEq)]
+pub enum WindowEvent {
+    // New framebuffer size
+    Resized {
+        width: u32,
+        height: u32,
+    },
+    // The window moved to a display with a different scale, or its scale changed.
+    // A `Resized` follows when the framebuffer size changes with it.
+    ScaleFactorChanged(f32),
+    Key {
+        key: Key,
+        // Also true for key repeats
+        pressed: bool,
+        // Generated by holding the key down, always pressed
+        repeat: bool,
+        modifiers: Modifiers,
+    },
+    // Text input, after keyboard layout and dead keys
+    Char(char),
+    CursorMoved {
+        x: f32,
+        y: f32,
+    },
+    MouseButton {
+        button: MouseButton,
+        pressed: bool,
+        modifiers: Modifiers,
+    },
+    // In lines, positive y scrolls up
+    Scroll {
+        x: f32,
+        y: f32,
+    },
+    Focused(bool),
+    CloseRequested,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Key {
+    Escape,
+    Enter,
+    Tab,
+    Backspace,
+    Delete,
+    Insert,
+    Home,
+    End,
+    PageUp,
+    PageDown,
+    Left,
+    Right,
+    Up,
+    Down,
+    Space,
+    // F1 is `F(1)`
+    F(u8),
+    // Other printable keys, letters are lowercase
+    Character(char),
+    Unknown,
+}
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct Modifiers {
+    pub shift: bool,
+    pub control: bool,
+    pub alt: bool,
+    pub super_key: bool,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum MouseButton {
+    Left,
+    Right,
+    Middle,
+    Other(u16),
+}
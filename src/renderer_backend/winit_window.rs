@@ -0,0 +1,212 @@
+use std::rc::Rc;
+
+use winit::{
+    application::ApplicationHandler,
+    event::{ElementState, MouseScrollDelta},
+    event_loop::{ActiveEventLoop, EventLoop},
+    keyboard::NamedKey,
+    window::{Window, WindowId},
+};
+
+use super::{
+    app::{App, Runner, RunnerSettings},
+    state::State,
+    window::{Key, Modifiers, MouseButton, RenderWindow, WindowEvent},
+};
+
+// Pixels per scroll line, for touchpads reporting pixel deltas
+const PIXELS_PER_LINE: f32 = 20.0;
+
+impl RenderWindow for Window {
+    fn size(&self) -> (u32, u32) {
+        let size = self.inner_size();
+        (size.width, size.height)
+    }
+
+    fn scale_factor(&self) -> f32 {
+        Window::scale_factor(self) as f32
+    }
+}
+
+pub fn run(settings: RunnerSettings, app: impl App) {
+    let event_loop = EventLoop::new().unwrap();
+    let mut handler = WinitHandler {
+        settings,
+        app: Some(app),
+        window: None,
+        runner: None,
+        modifiers: Modifiers::default(),
+    };
+    event_loop.run_app(&mut handler).unwrap();
+}
+
+// The window can only be created once the event loop has resumed
+struct WinitHandler<A: App> {
+    settings: RunnerSettings,
+    app: Option<A>,
+    window: Option<Rc<Window>>,
+    runner: Option<Runner<A>>,
+    // winit reports modifiers separately from key presses
+    modifiers: Modifiers,
+}
+
+impl<A: App> ApplicationHandler for WinitHandler<A> {
+    fn resumed(&mut self, event_loop: &ActiveEventLoop) {
+        let Some(app) = self.app.take() else {
+            return;
+        };
+        let attributes = Window::default_attributes()
+            .with_title(self.settings.title.clone())
+            .with_inner_size(winit::dpi::LogicalSize::new(
+                self.settings.width,
+                self.settings.height,
+            ));
+        let window = Rc::new(event_loop.create_window(attributes).unwrap());
+        let state = pollster::block_on(State::new(window.clone()));
+        self.runner = Some(Runner::new(&self.settings, app, state));
+        self.window = Some(window);
+    }
+
+    fn window_event(
+        &mut self,
+        event_loop: &ActiveEventLoop,
+        _window_id: WindowId,
+        event: winit::event::WindowEvent,
+    ) {
+        let Some(runner) = &mut self.runner else {
+            return;
+        };
+        match event {
+            winit::event::WindowEvent::CloseRequested => {
+                runner.handle_event(&WindowEvent::CloseRequested);
+                runner.finish();
+                event_loop.exit();
+            }
+            winit::event::WindowEvent::RedrawRequested => runner.frame(),
+            winit::event::WindowEvent::ModifiersChanged(modifiers) => {
+                let modifiers = modifiers.state();
+                self.modifiers = Modifiers {
+                    shift: modifiers.shift_key(),
+                    control: modifiers.control_key(),
+                    alt: modifiers.alt_key(),
+                    super_key: modifiers.super_key(),
+                };
+            }
+            event => {
+                for event in translate_event(event, self.modifiers) {
+                    runner.handle_event(&event);
+                }
+            }
+        }
+    }
+
+    fn about_to_wait(&mut self, _event_loop: &ActiveEventLoop) {
+        if let Some(window) = &self.window {
+            window.request_redraw();
+        }
+    }
+}
+
+// A key press can also produce text, so one winit event may become several
+pub fn translate_event(event: winit::event::WindowEvent, modifiers: Modifiers) -> Vec<WindowEvent> {
+    match event {
+        winit::event::WindowEvent::Resized(size) => vec![WindowEvent::Resized {
+            width: size.width,
+            height: size.height,
+        }],
+        winit::event::WindowEvent::KeyboardInput { event, .. } => {
+            let pressed = event.state == ElementState::Pressed;
+            let mut events = vec![WindowEvent::Key {
+                key: translate_key(&event.logical_key),
+                pressed,
+                repeat: event.repeat,
+                modifiers,
+            }];
+            if pressed && let Some(text) = &event.text {
+                events.extend(
+                    text.chars()
+                        .filter(|character| !character.is_control())
+                        .map(WindowEvent::Char),
+                );
+            }
+            events
+        }
+        winit::event::WindowEvent::CursorMoved { position, .. } => {
+            vec![WindowEvent::CursorMoved {
+                x: position.x as f32,
+                y: position.y as f32,
+            }]
+        }
+        winit::event::WindowEvent::MouseInput { state, button, .. } => {
+            vec![WindowEvent::MouseButton {
+                button: match button {
+                    winit::event::MouseButton::Left => MouseButton::Left,
+                    winit::event::MouseButton::Right => MouseButton::Right,
+                    winit::event::MouseButton::Middle => MouseButton::Middle,
+                    winit::event::MouseButton::Back => MouseButton::Other(3),
+                    winit::event::MouseButton::Forward => MouseButton::Other(4),
+                    winit::event::MouseButton::Other(button) => MouseButton::Other(button),
+                },
+                pressed: state == ElementState::Pressed,
+                modifiers,
+            }]
+        }
+        winit::event::WindowEvent::MouseWheel { delta, .. } => {
+            let (x, y) = match delta {
+                MouseScrollDelta::LineDelta(x, y) => (x, y),
+                MouseScrollDelta::PixelDelta(position) => (
+                    position.x as f32 / PIXELS_PER_LINE,
+                    position.y as f32 / PIXELS_PER_LINE,
+                ),
+            };
+            vec![WindowEvent::Scroll { x, y }]
+        }
+        // winit sends the matching `Resized` afterwards
+        winit::event::WindowEvent::ScaleFactorChanged { scale_factor, .. } => {
+            vec![WindowEvent::ScaleFactorChanged(scale_factor as f32)]
+        }
+        winit::event::WindowEvent::Focused(focused) => vec![WindowEvent::Focused(focused)],
+        _ => Vec::new(),
+    }
+}
+
+fn translate_key(key: &winit::keyboard::Key) -> Key {
+    match key {
+        winit::keyboard::Key::Named(named) => match named {
+            NamedKey::Escape => Key::Escape,
+            NamedKey::Enter => Key::Enter,
+            NamedKey::Tab => Key::Tab,
+            NamedKey::Backspace => Key::Backspace,
+            NamedKey::Delete => Key::Delete,
+            NamedKey::Insert => Key::Insert,
+            NamedKey::Home => Key::Home,
+            NamedKey::End => Key::End,
+            NamedKey::PageUp => Key::PageUp,
+            NamedKey::PageDown => Key::PageDown,
+            NamedKey::ArrowLeft => Key::Left,
+            NamedKey::ArrowRight => Key::Right,
+            NamedKey::ArrowUp => Key::Up,
+            NamedKey::ArrowDown => Key::Down,
+            NamedKey::Space => Key::Space,
+            NamedKey::F1 => Key::F(1),
+            NamedKey::F2 => Key::F(2),
+            NamedKey::F3 => Key::F(3),
+            NamedKey::F4 => Key::F(4),
+            NamedKey::F5 => Key::F(5),
+            NamedKey::F6 => Key::F(6),
+            NamedKey::F7 => Key::F(7),
+            NamedKey::F8 => Key::F(8),
+            NamedKey::F9 => Key::F(9),
+            NamedKey::F10 => Key::F(10),
+            NamedKey::F11 => Key::F(11),
+            NamedKey::F12 => Key::F(12),
+            _ => Key::Unknown,
+        },
+        winit::keyboard::Key::Character(text) => {
+            text.chars().next().map_or(Key::Unknown, |character| {
+                Key::Character(character.to_ascii_lowercase())
+            })
+        }
+        _ => Key::Unknown,
+    }
+}
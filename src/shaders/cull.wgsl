@@ -0,0 +1,76 @@
+// Same layout as InstanceRaw
+struct InstanceData {
+    model: mat4x4<f32>,
+    normal: mat3x3<f32>,
+}
+
+struct CullParams {
+    planes: array<vec4<f32>, 6>,
+    instance_count: u32,
+    batch_count: u32,
+}
+
+struct CullBatch {
+    bounding_sphere: vec4<f32>,
+    first_instance: u32,
+    instance_count: u32,
+}
+
+// Same layout as wgpu::util::DrawIndexedIndirectArgs
+struct DrawIndexedIndirectArgs {
+    index_count: u32,
+    instance_count: atomic<u32>,
+    first_index: u32,
+    base_vertex: i32,
+    first_instance: u32,
+}
+
+@group(0) @binding(0) var<uniform> params: CullParams;
+@group(0) @binding(1) var<storage, read> instances: array<InstanceData>;
+@group(0) @binding(2) var<storage, read_write> visible_indices: array<u32>;
+@group(0) @binding(3) var<storage, read_write> draw_args: array<DrawIndexedIndirectArgs>;
+@group(0) @binding(4) var<storage, read> batches: array<CullBatch>;
+
+// Batches are sorted by first_instance, find the last one starting at or before `index`
+fn find_batch(index: u32) -> u32 {
+    var low = 0u;
+    var high = params.batch_count;
+    while (high - low > 1u) {
+        let middle = (low + high) / 2u;
+        if (batches[middle].first_instance <= index) {
+            low = middle;
+        } else {
+            high = middle;
+        }
+    }
+    return low;
+}
+
+@compute @workgroup_size(64)
+fn cs_cull(@builtin(global_invocation_id) id: vec3<u32>) {
+    let index = id.x;
+    if (index >= params.instance_count) {
+        return;
+    }
+
+    let batch_index = find_batch(index);
+    let batch = batches[batch_index];
+    if (index >= batch.first_instance + batch.instance_count) {
+        return;
+    }
+
+    let model = instances[index].model;
+    let center = (model * vec4<f32>(batch.bounding_sphere.xyz, 1.0)).xyz;
+    let scale = max(length(model[0].xyz), max(length(model[1].xyz), length(model[2].xyz)));
+    let radius = batch.bounding_sphere.w * scale;
+
+    for (var i = 0u; i < 6u; i++) {
+        let plane = params.planes[i];
+        if (dot(plane.xyz, center) + plane.w < -radius) {
+            return;
+        }
+    }
+
+    let slot = atomicAdd(&draw_args[batch_index].instance_count, 1u);
+    visible_indices[batch.first_instance + slot] = index;
+}
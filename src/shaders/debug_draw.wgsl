@@ -0,0 +1,30 @@
+struct Camera {
+    view_proj: mat4x4<f32>,
+    view_position: vec4<f32>,
+    inverse_view_proj: mat4x4<f32>,
+}
+
+@group(0) @binding(0) var<uniform> camera: Camera;
+
+struct DebugVertex {
+    @location(0) position: vec3<f32>,
+    @location(1) color: vec4<f32>,
+}
+
+struct DebugPayload {
+    @builtin(position) position: vec4<f32>,
+    @location(0) color: vec4<f32>,
+}
+
+@vertex
+fn vs_debug(in: DebugVertex) -> DebugPayload {
+    var out: DebugPayload;
+    out.position = camera.view_proj * vec4<f32>(in.position, 1.0);
+    out.color = in.color;
+    return out;
+}
+
+@fragment
+fn fs_debug(in: DebugPayload) -> @location(0) vec4<f32> {
+    return in.color;
+}
@@ -0,0 +1,43 @@
+// Precomputes the BRDF lookup table of the split sum approximation. Uses ibl_common.wgsl.
+
+@group(0) @binding(0) var brdf_output: texture_storage_2d<rgba16float, write>;
+
+fn geometry_schlick_ggx_ibl(n_dot_v: f32, roughness: f32) -> f32 {
+    let k = (roughness * roughness) / 2.0;
+    return n_dot_v / (n_dot_v * (1.0 - k) + k);
+}
+
+// Scale (r) and bias (g) applied to F0 by the split sum approximation,
+// indexed by (n_dot_v, roughness)
+@compute @workgroup_size(8, 8, 1)
+fn cs_brdf_lut(@builtin(global_invocation_id) id: vec3<u32>) {
+    let size = textureDimensions(brdf_output);
+    if (id.x >= size.x || id.y >= size.y) {
+        return;
+    }
+
+    let n_dot_v = (f32(id.x) + 0.5) / f32(size.x);
+    let roughness = (f32(id.y) + 0.5) / f32(size.y);
+    let view_dir = vec3<f32>(sqrt(1.0 - n_dot_v * n_dot_v), 0.0, n_dot_v);
+
+    let sample_count = 512u;
+    var scale = 0.0;
+    var bias = 0.0;
+    for (var i = 0u; i < sample_count; i++) {
+        let half_dir = importance_sample_ggx(hammersley(i, sample_count), roughness);
+        let light_dir = normalize(2.0 * dot(view_dir, half_dir) * half_dir - view_dir);
+        let n_dot_l = max(light_dir.z, 0.0);
+        if (n_dot_l <= 0.0) {
+            continue;
+        }
+
+        let n_dot_h = max(half_dir.z, 0.0);
+        let v_dot_h = max(dot(view_dir, half_dir), 0.0);
+        let g = geometry_schlick_ggx_ibl(n_dot_v, roughness) * geometry_schlick_ggx_ibl(n_dot_l, roughness);
+        let g_vis = g * v_dot_h / (n_dot_h * n_dot_v);
+        let fresnel = pow(1.0 - v_dot_h, 5.0);
+        scale += (1.0 - fresnel) * g_vis;
+        bias += fresnel * g_vis;
+    }
+    textureStore(brdf_output, id.xy, vec4<f32>(scale, bias, 0.0, 1.0) / vec4<f32>(f32(sample_count), f32(sample_count), 1.0, 1.0));
+}
@@ -0,0 +1,21 @@
+// Converts an equirectangular HDR image into the faces of a cubemap, one mip level
+// viewed as a 2D array of its six faces. Uses ibl_common.wgsl.
+
+@group(0) @binding(0) var equirect_texture: texture_2d<f32>;
+@group(0) @binding(1) var cube_output: texture_storage_2d_array<rgba16float, write>;
+
+@compute @workgroup_size(8, 8, 1)
+fn cs_equirect_to_cube(@builtin(global_invocation_id) id: vec3<u32>) {
+    let size = textureDimensions(cube_output).x;
+    if (id.x >= size || id.y >= size) {
+        return;
+    }
+
+    let direction = cube_direction(id.z, id.xy, size);
+    let uv = vec2<f32>(atan2(direction.z, direction.x) / (2.0 * PI) + 0.5, acos(direction.y) / PI);
+    // Float32 textures are not filterable, the source is much larger than a face anyway
+    let source_size = textureDimensions(equirect_texture);
+    let texel = min(vec2<u32>(uv * vec2<f32>(source_size)), source_size - 1u);
+    let color = textureLoad(equirect_texture, texel, 0);
+    textureStore(cube_output, id.xy, id.z, vec4<f32>(color.rgb, 1.0));
+}
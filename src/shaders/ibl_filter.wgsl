@@ -0,0 +1,94 @@
+// Filters the environment cubemap into one mip level of the downsampled, irradiance
+// or prefiltered cubemap, viewed as a 2D array of its six faces. Uses ibl_common.wgsl.
+
+struct FilterParams {
+    roughness: f32,
+    // Face size of the environment cubemap's first mip
+    source_size: f32,
+    sample_count: u32,
+}
+
+@group(0) @binding(0) var source_cube: texture_cube<f32>;
+@group(0) @binding(1) var filter_output: texture_storage_2d_array<rgba16float, write>;
+@group(0) @binding(2) var source_sampler: sampler;
+@group(0) @binding(3) var<uniform> params: FilterParams;
+
+// Box filters the previous mip level into the current one
+@compute @workgroup_size(8, 8, 1)
+fn cs_downsample(@builtin(global_invocation_id) id: vec3<u32>) {
+    let size = textureDimensions(filter_output).x;
+    if (id.x >= size || id.y >= size) {
+        return;
+    }
+
+    let direction = cube_direction(id.z, id.xy, size);
+    let color = textureSampleLevel(source_cube, source_sampler, direction, 0.0);
+    textureStore(filter_output, id.xy, id.z, color);
+}
+
+// Cosine weighted hemisphere convolution for diffuse lighting
+@compute @workgroup_size(8, 8, 1)
+fn cs_irradiance(@builtin(global_invocation_id) id: vec3<u32>) {
+    let size = textureDimensions(filter_output).x;
+    if (id.x >= size || id.y >= size) {
+        return;
+    }
+
+    let normal = cube_direction(id.z, id.xy, size);
+    let frame = tangent_frame(normal);
+    let delta = 0.05;
+    // Lower mips are enough for such a wide filter and avoid undersampling
+    let level = max(log2(params.source_size) - 5.0, 0.0);
+
+    var irradiance = vec3<f32>(0.0);
+    var sample_count = 0.0;
+    for (var phi = 0.0; phi < 2.0 * PI; phi += delta) {
+        for (var theta = 0.0; theta < 0.5 * PI; theta += delta) {
+            let tangent_sample = vec3<f32>(sin(theta) * cos(phi), sin(theta) * sin(phi), cos(theta));
+            let direction = frame * tangent_sample;
+            irradiance += textureSampleLevel(source_cube, source_sampler, direction, level).rgb
+                * cos(theta) * sin(theta);
+            sample_count += 1.0;
+        }
+    }
+    irradiance = PI * irradiance / sample_count;
+    textureStore(filter_output, id.xy, id.z, vec4<f32>(irradiance, 1.0));
+}
+
+// GGX prefiltered radiance for one roughness level, split sum approximation
+@compute @workgroup_size(8, 8, 1)
+fn cs_prefilter(@builtin(global_invocation_id) id: vec3<u32>) {
+    let size = textureDimensions(filter_output).x;
+    if (id.x >= size || id.y >= size) {
+        return;
+    }
+
+    // Assumes view direction == normal == reflection direction
+    let normal = cube_direction(id.z, id.xy, size);
+    let frame = tangent_frame(normal);
+    let texel_solid_angle = 4.0 * PI / (6.0 * params.source_size * params.source_size);
+
+    var color = vec3<f32>(0.0);
+    var total_weight = 0.0;
+    for (var i = 0u; i < params.sample_count; i++) {
+        let half_dir = frame * importance_sample_ggx(hammersley(i, params.sample_count), params.roughness);
+        let light_dir = normalize(2.0 * dot(normal, half_dir) * half_dir - normal);
+        let n_dot_l = dot(normal, light_dir);
+        if (n_dot_l <= 0.0) {
+            continue;
+        }
+
+        // Sample a mip whose texels cover the solid angle of this sample
+        let n_dot_h = max(dot(normal, half_dir), 0.0);
+        let pdf = distribution_ggx(n_dot_h, params.roughness) / 4.0 + 0.0001;
+        let sample_solid_angle = 1.0 / (f32(params.sample_count) * pdf);
+        var level = 0.0;
+        if (params.roughness > 0.0) {
+            level = 0.5 * log2(sample_solid_angle / texel_solid_angle);
+        }
+
+        color += textureSampleLevel(source_cube, source_sampler, light_dir, max(level, 0.0)).rgb * n_dot_l;
+        total_weight += n_dot_l;
+    }
+    textureStore(filter_output, id.xy, id.z, vec4<f32>(color / max(total_weight, 0.0001), 1.0));
+}
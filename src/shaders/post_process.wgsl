@@ -0,0 +1,195 @@
+// Same layout as PostParams
+struct PostParams {
+    texel_size: vec2<f32>,
+    direction: vec2<f32>,
+    exposure: f32,
+    gamma: f32,
+    tone_mapping: u32,
+    encode: u32,
+    threshold: f32,
+    intensity: f32,
+    vignette_strength: f32,
+    vignette_radius: f32,
+}
+
+@group(0) @binding(0) var input_texture: texture_2d<f32>;
+@group(0) @binding(1) var input_sampler: sampler;
+@group(0) @binding(2) var<uniform> params: PostParams;
+// Only read by the bloom composite, the other passes bind the input again
+@group(0) @binding(3) var secondary_texture: texture_2d<f32>;
+
+const TONE_MAPPING_NONE: u32 = 0u;
+const TONE_MAPPING_REINHARD: u32 = 1u;
+const TONE_MAPPING_ACES: u32 = 2u;
+const TONE_MAPPING_FILMIC: u32 = 3u;
+
+const ENCODE_LINEAR: u32 = 0u;
+const ENCODE_SRGB_SURFACE: u32 = 1u;
+const ENCODE_LINEAR_SURFACE: u32 = 2u;
+
+struct FullscreenPayload {
+    @builtin(position) position: vec4<f32>,
+    @location(0) uv: vec2<f32>,
+}
+
+@vertex
+fn vs_fullscreen(@builtin(vertex_index) vertex_index: u32) -> FullscreenPayload {
+    let uv = vec2<f32>(f32((vertex_index << 1u) & 2u), f32(vertex_index & 2u));
+
+    var out: FullscreenPayload;
+    out.position = vec4<f32>(uv * vec2<f32>(2.0, -2.0) + vec2<f32>(-1.0, 1.0), 0.0, 1.0);
+    out.uv = uv;
+    return out;
+}
+
+// Applies the display gamma on the pass that writes the surface. An sRGB
+// surface already encodes with ~2.2, so only the difference is applied.
+fn encode_output(color: vec3<f32>) -> vec4<f32> {
+    var encoded = max(color, vec3<f32>(0.0));
+    if (params.encode == ENCODE_SRGB_SURFACE) {
+        encoded = pow(encoded, vec3<f32>(2.2 / params.gamma));
+    } else if (params.encode == ENCODE_LINEAR_SURFACE) {
+        encoded = pow(encoded, vec3<f32>(1.0 / params.gamma));
+    }
+    return vec4<f32>(encoded, 1.0);
+}
+
+fn luminance(color: vec3<f32>) -> f32 {
+    return dot(color, vec3<f32>(0.2126, 0.7152, 0.0722));
+}
+
+@fragment
+fn fs_copy(in: FullscreenPayload) -> @location(0) vec4<f32> {
+    return encode_output(textureSample(input_texture, input_sampler, in.uv).rgb);
+}
+
+// Narkowicz's fit of the ACES reference rendering transform
+fn tone_map_aces(color: vec3<f32>) -> vec3<f32> {
+    let a = 2.51;
+    let b = 0.03;
+    let c = 2.43;
+    let d = 0.59;
+    let e = 0.14;
+    return clamp((color * (a * color + b)) / (color * (c * color + d) + e), vec3<f32>(0.0), vec3<f32>(1.0));
+}
+
+// Hable's Uncharted 2 curve
+fn hable(x: vec3<f32>) -> vec3<f32> {
+    let a = 0.15;
+    let b = 0.50;
+    let c = 0.10;
+    let d = 0.20;
+    let e = 0.02;
+    let f = 0.30;
+    return ((x * (a * x + c * b) + d * e) / (x * (a * x + b) + d * f)) - e / f;
+}
+
+fn tone_map_filmic(color: vec3<f32>) -> vec3<f32> {
+    let white_point = vec3<f32>(11.2);
+    return hable(color * 2.0) / hable(white_point);
+}
+
+@fragment
+fn fs_tone_map(in: FullscreenPayload) -> @location(0) vec4<f32> {
+    let color = textureSample(input_texture, input_sampler, in.uv).rgb * params.exposure;
+    var mapped = color;
+    switch params.tone_mapping {
+        case TONE_MAPPING_REINHARD: {
+            mapped = color / (vec3<f32>(1.0) + color);
+        }
+        case TONE_MAPPING_ACES: {
+            mapped = tone_map_aces(color);
+        }
+        case TONE_MAPPING_FILMIC: {
+            mapped = tone_map_filmic(color);
+        }
+        default: {}
+    }
+    return encode_output(mapped);
+}
+
+// Keeps what is brighter than the threshold, box filtered into the half size target
+@fragment
+fn fs_bloom_extract(in: FullscreenPayload) -> @location(0) vec4<f32> {
+    let offset = params.texel_size * 0.5;
+    let color = (textureSample(input_texture, input_sampler, in.uv + vec2<f32>(-offset.x, -offset.y)).rgb
+        + textureSample(input_texture, input_sampler, in.uv + vec2<f32>(offset.x, -offset.y)).rgb
+        + textureSample(input_texture, input_sampler, in.uv + vec2<f32>(-offset.x, offset.y)).rgb
+        + textureSample(input_texture, input_sampler, in.uv + vec2<f32>(offset.x, offset.y)).rgb) * 0.25;
+    let brightness = max(color.r, max(color.g, color.b));
+    let contribution = max(brightness - params.threshold, 0.0) / max(brightness, 0.0001);
+    return vec4<f32>(color * contribution, 1.0);
+}
+
+// Separable 9 tap gaussian, `direction` selects the horizontal or vertical pass
+@fragment
+fn fs_blur(in: FullscreenPayload) -> @location(0) vec4<f32> {
+    let weights = array<f32, 5>(0.227027, 0.1945946, 0.1216216, 0.054054, 0.016216);
+    let step = params.direction * params.texel_size;
+    var color = textureSample(input_texture, input_sampler, in.uv).rgb * weights[0];
+    for (var i = 1; i < 5; i++) {
+        let offset = step * f32(i);
+        color += textureSample(input_texture, input_sampler, in.uv + offset).rgb * weights[i];
+        color += textureSample(input_texture, input_sampler, in.uv - offset).rgb * weights[i];
+    }
+    return vec4<f32>(color, 1.0);
+}
+
+@fragment
+fn fs_bloom_composite(in: FullscreenPayload) -> @location(0) vec4<f32> {
+    let scene = textureSample(input_texture, input_sampler, in.uv).rgb;
+    let bloom = textureSample(secondary_texture, input_sampler, in.uv).rgb;
+    return encode_output(scene + bloom * params.intensity);
+}
+
+@fragment
+fn fs_vignette(in: FullscreenPayload) -> @location(0) vec4<f32> {
+    let color = textureSample(input_texture, input_sampler, in.uv).rgb;
+    let distance = length(in.uv - vec2<f32>(0.5)) * sqrt(2.0);
+    let falloff = smoothstep(params.vignette_radius, params.vignette_radius + 0.5, distance);
+    return encode_output(color * (1.0 - params.vignette_strength * falloff));
+}
+
+const FXAA_REDUCE_MIN: f32 = 1.0 / 128.0;
+const FXAA_REDUCE_MUL: f32 = 1.0 / 8.0;
+const FXAA_SPAN_MAX: f32 = 8.0;
+
+// Perceptual luma, the chain works on linear values
+fn fxaa_luma(color: vec3<f32>) -> f32 {
+    return sqrt(luminance(clamp(color, vec3<f32>(0.0), vec3<f32>(1.0))));
+}
+
+// FXAA without the edge end search: blurs along the edge found from the luma gradient
+@fragment
+fn fs_fxaa(in: FullscreenPayload) -> @location(0) vec4<f32> {
+    let texel = params.texel_size;
+    let color_m = textureSample(input_texture, input_sampler, in.uv).rgb;
+    let luma_nw = fxaa_luma(textureSample(input_texture, input_sampler, in.uv + vec2<f32>(-1.0, -1.0) * texel).rgb);
+    let luma_ne = fxaa_luma(textureSample(input_texture, input_sampler, in.uv + vec2<f32>(1.0, -1.0) * texel).rgb);
+    let luma_sw = fxaa_luma(textureSample(input_texture, input_sampler, in.uv + vec2<f32>(-1.0, 1.0) * texel).rgb);
+    let luma_se = fxaa_luma(textureSample(input_texture, input_sampler, in.uv + vec2<f32>(1.0, 1.0) * texel).rgb);
+    let luma_m = fxaa_luma(color_m);
+
+    let luma_min = min(luma_m, min(min(luma_nw, luma_ne), min(luma_sw, luma_se)));
+    let luma_max = max(luma_m, max(max(luma_nw, luma_ne), max(luma_sw, luma_se)));
+
+    var direction = vec2<f32>(
+        -((luma_nw + luma_ne) - (luma_sw + luma_se)),
+        (luma_nw + luma_sw) - (luma_ne + luma_se),
+    );
+    let reduce = max((luma_nw + luma_ne + luma_sw + luma_se) * 0.25 * FXAA_REDUCE_MUL, FXAA_REDUCE_MIN);
+    let scale = 1.0 / (min(abs(direction.x), abs(direction.y)) + reduce);
+    direction = clamp(direction * scale, vec2<f32>(-FXAA_SPAN_MAX), vec2<f32>(FXAA_SPAN_MAX)) * texel;
+
+    let color_a = 0.5 * (textureSample(input_texture, input_sampler, in.uv + direction * (1.0 / 3.0 - 0.5)).rgb
+        + textureSample(input_texture, input_sampler, in.uv + direction * (2.0 / 3.0 - 0.5)).rgb);
+    let color_b = color_a * 0.5 + 0.25 * (textureSample(input_texture, input_sampler, in.uv - direction * 0.5).rgb
+        + textureSample(input_texture, input_sampler, in.uv + direction * 0.5).rgb);
+
+    // The wider blend overshot the local contrast, fall back to the narrow one
+    let luma_b = fxaa_luma(color_b);
+    if (luma_b < luma_min || luma_b > luma_max) {
+        return encode_output(color_a);
+    }
+    return encode_output(color_b);
+}
@@ -1,9 +1,109 @@
+// Base color, also the diffuse texture of the Blinn-Phong path
 @group(0) @binding(0) var material_texture: texture_2d<f32>;
 @group(0) @binding(1) var material_sampler: sampler;
+@group(0) @binding(2) var metallic_roughness_texture: texture_2d<f32>;
+@group(0) @binding(3) var normal_texture: texture_2d<f32>;
+@group(0) @binding(4) var occlusion_texture: texture_2d<f32>;
+@group(0) @binding(5) var emissive_texture: texture_2d<f32>;
+
+// Same layout as MaterialFactors
+struct MaterialFactors {
+    base_color: vec4<f32>,
+    emissive: vec3<f32>,
+    metallic: f32,
+    roughness: f32,
+    normal_scale: f32,
+    occlusion_strength: f32,
+    alpha_cutoff: f32,
+}
+
+@group(0) @binding(6) var<uniform> material: MaterialFactors;
+
+// Set for pipelines drawing masked materials with alpha-to-coverage
+override alpha_to_coverage: bool = false;
+
+// Alpha test of masked materials. With alpha-to-coverage the alpha is sharpened to
+// about one pixel of falloff instead, so the samples antialias the cut-out edge
+fn apply_alpha_cutoff(alpha: f32) -> f32 {
+    if (material.alpha_cutoff <= 0.0) {
+        return alpha;
+    }
+    if (alpha_to_coverage) {
+        return clamp((alpha - material.alpha_cutoff) / max(fwidth(alpha), 0.0001) + 0.5, 0.0, 1.0);
+    }
+    if (alpha < material.alpha_cutoff) {
+        discard;
+    }
+    return 1.0;
+}
+
+struct Camera {
+    view_proj: mat4x4<f32>,
+    view_position: vec4<f32>,
+    inverse_view_proj: mat4x4<f32>,
+}
+
+@group(1) @binding(0) var<uniform> camera: Camera;
+
+const LIGHT_DIRECTIONAL: u32 = 0u;
+const LIGHT_POINT: u32 = 1u;
+const LIGHT_SPOT: u32 = 2u;
+
+// Same layout as LightRaw
+struct Light {
+    position: vec3<f32>,
+    kind: u32,
+    direction: vec3<f32>,
+    intensity: f32,
+    color: vec3<f32>,
+    cos_inner_cone: f32,
+    attenuation: vec3<f32>,
+    cos_outer_cone: f32,
+    shadow_layer: i32,
+}
+
+struct Lighting {
+    ambient: vec3<f32>,
+    light_count: u32,
+    specular_strength: f32,
+    shininess: f32,
+    environment_intensity: f32,
+    max_reflection_lod: f32,
+}
+
+@group(2) @binding(0) var<uniform> lighting: Lighting;
+@group(2) @binding(1) var<storage, read> lights: array<Light>;
+@group(2) @binding(2) var irradiance_map: texture_cube<f32>;
+@group(2) @binding(3) var prefiltered_map: texture_cube<f32>;
+@group(2) @binding(4) var brdf_lut: texture_2d<f32>;
+@group(2) @binding(5) var environment_sampler: sampler;
+
+// Same values as shadow::CASCADE_COUNT and shadow::SHADOW_LAYERS, checked by a test
+const CASCADE_COUNT: u32 = 4u;
+const SHADOW_LAYERS: u32 = 8u;
+const CASCADE_SPLIT_VECTORS: u32 = (CASCADE_COUNT + 3u) / 4u;
+
+// Same layout as ShadowUniform
+struct Shadows {
+    light_view_proj: array<mat4x4<f32>, SHADOW_LAYERS>,
+    // Four cascades per vector
+    cascade_splits: array<vec4<f32>, CASCADE_SPLIT_VECTORS>,
+    camera_forward: vec4<f32>,
+    pcf_radius: u32,
+    depth_bias: f32,
+    normal_offset: f32,
+    cascade_count: u32,
+}
+
+@group(2) @binding(6) var shadow_maps: texture_depth_2d_array;
+@group(2) @binding(7) var shadow_sampler: sampler_comparison;
+@group(2) @binding(8) var<uniform> shadows: Shadows;
 
 struct Vertex {
     @location(0) position: vec3<f32>,
-    @location(1) texture_coords: vec2<f32>
+    @location(1) texture_coords: vec2<f32>,
+    @location(2) normal: vec3<f32>,
+    @location(3) tangent: vec4<f32>,
 }
 
 struct InstanceInput {
@@ -11,30 +111,433 @@ struct InstanceInput {
     @location(6) vec_2 : vec4<f32>,
     @location(7) vec_3 : vec4<f32>,
     @location(8) vec_4 : vec4<f32>,
+    @location(9) normal_1 : vec4<f32>,
+    @location(10) normal_2 : vec4<f32>,
+    @location(11) normal_3 : vec4<f32>,
+}
+
+// Same layout as InstanceRaw, used by the storage buffer instancing path
+struct InstanceData {
+    model: mat4x4<f32>,
+    normal: mat3x3<f32>,
 }
 
+@group(3) @binding(0) var<storage, read> instances: array<InstanceData>;
+// Indices of the instances that survived frustum culling, only used by vs_culled
+@group(3) @binding(1) var<storage, read> visible_indices: array<u32>;
+
 struct VertexPayload {
     @builtin(position) position: vec4<f32>,
-    @location(0) texture_coords: vec2<f32>
+    @location(0) texture_coords: vec2<f32>,
+    @location(1) world_position: vec3<f32>,
+    @location(2) world_normal: vec3<f32>,
+    @location(3) world_tangent: vec4<f32>,
 }
 
+fn transform_vertex(vertex: Vertex, model: mat4x4<f32>, normal_matrix: mat3x3<f32>) -> VertexPayload {
+    var out: VertexPayload;
+
+    let world_position = model * vec4<f32>(vertex.position, 1.0);
+    out.position = camera.view_proj * world_position;
+    out.texture_coords = vertex.texture_coords;
+    out.world_position = world_position.xyz;
+    out.world_normal = normal_matrix * vertex.normal;
+    out.world_tangent = vec4<f32>((model * vec4<f32>(vertex.tangent.xyz, 0.0)).xyz, vertex.tangent.w);
+    return out;
+}
 
 @vertex
 fn vs_main(vertex: Vertex, instance_input: InstanceInput) -> VertexPayload {
-    var out: VertexPayload;
-
     var instance_matrix = mat4x4<f32> (
         instance_input.vec_1,
         instance_input.vec_2,
         instance_input.vec_3,
         instance_input.vec_4,
     );
-    out.position = instance_matrix * vec4<f32>(vertex.position, 1.0);
-    out.texture_coords = vertex.texture_coords;
-    return out;
+    var normal_matrix = mat3x3<f32> (
+        instance_input.normal_1.xyz,
+        instance_input.normal_2.xyz,
+        instance_input.normal_3.xyz,
+    );
+    return transform_vertex(vertex, instance_matrix, normal_matrix);
+}
+
+@vertex
+fn vs_storage(vertex: Vertex, @builtin(instance_index) instance_index: u32) -> VertexPayload {
+    let instance = instances[instance_index];
+    return transform_vertex(vertex, instance.model, instance.normal);
+}
+
+@vertex
+fn vs_culled(vertex: Vertex, @builtin(instance_index) instance_index: u32) -> VertexPayload {
+    let instance = instances[visible_indices[instance_index]];
+    return transform_vertex(vertex, instance.model, instance.normal);
+}
+
+// Direction towards the light and its incoming radiance at `position`
+struct LightSample {
+    direction: vec3<f32>,
+    radiance: vec3<f32>,
+}
+
+// Fraction of the light reaching `position`, 1.0 is fully lit
+fn shadow_factor(light: Light, position: vec3<f32>, normal: vec3<f32>) -> f32 {
+    if (light.shadow_layer < 0) {
+        return 1.0;
+    }
+
+    var layer = u32(light.shadow_layer);
+    if (light.kind == LIGHT_DIRECTIONAL) {
+        // Pick the first cascade that still contains the view depth
+        let view_depth = dot(position - camera.view_position.xyz, shadows.camera_forward.xyz);
+        var cascade = 0u;
+        while (cascade < shadows.cascade_count && view_depth > shadows.cascade_splits[cascade / 4u][cascade % 4u]) {
+            cascade++;
+        }
+        if (cascade == shadows.cascade_count) {
+            return 1.0;
+        }
+        layer += cascade;
+    }
+
+    let offset_position = position + normal * shadows.normal_offset;
+    let light_clip = shadows.light_view_proj[layer] * vec4<f32>(offset_position, 1.0);
+    let ndc = light_clip.xyz / light_clip.w;
+    if (light_clip.w <= 0.0 || ndc.z > 1.0) {
+        return 1.0;
+    }
+    let uv = ndc.xy * vec2<f32>(0.5, -0.5) + 0.5;
+    let depth = ndc.z - shadows.depth_bias;
+
+    // Percentage closer filtering over a square kernel
+    let texel = 1.0 / vec2<f32>(textureDimensions(shadow_maps));
+    let radius = i32(shadows.pcf_radius);
+    var lit = 0.0;
+    for (var y = -radius; y <= radius; y++) {
+        for (var x = -radius; x <= radius; x++) {
+            let offset = vec2<f32>(f32(x), f32(y)) * texel;
+            lit += textureSampleCompareLevel(shadow_maps, shadow_sampler, uv + offset, layer, depth);
+        }
+    }
+    let taps = f32((2 * radius + 1) * (2 * radius + 1));
+    return lit / taps;
+}
+
+fn sample_light(light: Light, position: vec3<f32>, normal: vec3<f32>) -> LightSample {
+    let shadow = shadow_factor(light, position, normal);
+    if (light.kind == LIGHT_DIRECTIONAL) {
+        return LightSample(-light.direction, light.color * light.intensity * shadow);
+    }
+
+    let to_light = light.position - position;
+    let distance = length(to_light);
+    let direction = to_light / distance;
+    var falloff = 1.0 / (light.attenuation.x
+        + light.attenuation.y * distance
+        + light.attenuation.z * distance * distance);
+    if (light.kind == LIGHT_SPOT) {
+        falloff *= smoothstep(light.cos_outer_cone, light.cos_inner_cone, dot(-direction, light.direction));
+    }
+    return LightSample(direction, light.color * light.intensity * falloff * shadow);
+}
+
+struct LightContribution {
+    diffuse: vec3<f32>,
+    specular: vec3<f32>,
+}
+
+// Blinn-Phong contribution of one light, without the surface albedo
+fn shade_light(light: Light, position: vec3<f32>, normal: vec3<f32>, view_dir: vec3<f32>) -> LightContribution {
+    let light_sample = sample_light(light, position, normal);
+
+    let diffuse = max(dot(normal, light_sample.direction), 0.0);
+    let half_dir = normalize(light_sample.direction + view_dir);
+    var specular = 0.0;
+    if (diffuse > 0.0) {
+        specular = lighting.specular_strength * pow(max(dot(normal, half_dir), 0.0), lighting.shininess);
+    }
+
+    return LightContribution(light_sample.radiance * diffuse, light_sample.radiance * specular);
+}
+
+fn shade_blinn_phong(in: VertexPayload) -> vec4<f32> {
+    let albedo = textureSample(material_texture, material_sampler, in.texture_coords);
+    let alpha = apply_alpha_cutoff(albedo.a * material.base_color.a);
+    let normal = normalize(in.world_normal);
+    let view_dir = normalize(camera.view_position.xyz - in.world_position);
+
+    var diffuse = lighting.ambient;
+    var specular = vec3<f32>(0.0);
+    for (var i = 0u; i < lighting.light_count; i++) {
+        let contribution = shade_light(lights[i], in.world_position, normal, view_dir);
+        diffuse += contribution.diffuse;
+        specular += contribution.specular;
+    }
+
+    return vec4<f32>(albedo.rgb * diffuse + specular, alpha);
 }
 
 @fragment
 fn fs_main(in: VertexPayload) -> @location(0) vec4<f32> {
-    return textureSample(material_texture, material_sampler, in.texture_coords);
-}
\ No newline at end of file
+    return shade_blinn_phong(in);
+}
+
+const PI: f32 = 3.14159265359;
+
+// Trowbridge-Reitz GGX normal distribution
+fn distribution_ggx(n_dot_h: f32, roughness: f32) -> f32 {
+    let a = roughness * roughness;
+    let a2 = a * a;
+    let denom = n_dot_h * n_dot_h * (a2 - 1.0) + 1.0;
+    return a2 / (PI * denom * denom);
+}
+
+// Smith's method with the Schlick-GGX approximation for direct lighting
+fn geometry_smith(n_dot_v: f32, n_dot_l: f32, roughness: f32) -> f32 {
+    let r = roughness + 1.0;
+    let k = (r * r) / 8.0;
+    let ggx_v = n_dot_v / (n_dot_v * (1.0 - k) + k);
+    let ggx_l = n_dot_l / (n_dot_l * (1.0 - k) + k);
+    return ggx_v * ggx_l;
+}
+
+fn fresnel_schlick(cos_theta: f32, f0: vec3<f32>) -> vec3<f32> {
+    return f0 + (1.0 - f0) * pow(clamp(1.0 - cos_theta, 0.0, 1.0), 5.0);
+}
+
+// Fresnel for the ambient term, rough surfaces reflect less at grazing angles
+fn fresnel_schlick_roughness(cos_theta: f32, f0: vec3<f32>, roughness: f32) -> vec3<f32> {
+    return f0 + (max(vec3<f32>(1.0 - roughness), f0) - f0) * pow(clamp(1.0 - cos_theta, 0.0, 1.0), 5.0);
+}
+
+// Tangent space to world space, the bitangent is cross(normal, tangent) * handedness as in glTF
+fn perturb_normal(normal: vec3<f32>, tangent: vec4<f32>, tangent_normal: vec3<f32>) -> vec3<f32> {
+    // Re-orthogonalize, interpolation skews the frame across the triangle
+    let t = normalize(tangent.xyz - normal * dot(normal, tangent.xyz));
+    let b = cross(normal, t) * tangent.w;
+    return normalize(mat3x3<f32>(t, b, normal) * tangent_normal);
+}
+
+// Everything the metallic-roughness model needs at one point, filled from the
+// material textures in the forward path or read back from the G-buffer
+struct SurfaceSample {
+    position: vec3<f32>,
+    normal: vec3<f32>,
+    base_color: vec3<f32>,
+    alpha: f32,
+    metallic: f32,
+    roughness: f32,
+    occlusion: f32,
+    emissive: vec3<f32>,
+}
+
+// Cook-Torrance metallic-roughness shading following the glTF material model.
+// Only explicit-level sampling, the deferred path calls it in non-uniform control flow.
+fn shade_pbr(surface: SurfaceSample) -> vec3<f32> {
+    let normal = surface.normal;
+    let base_color = surface.base_color;
+    let metallic = surface.metallic;
+    let roughness = surface.roughness;
+
+    let view_dir = normalize(camera.view_position.xyz - surface.position);
+    let n_dot_v = max(dot(normal, view_dir), 0.0001);
+    // Dielectrics reflect about 4% at normal incidence
+    let f0 = mix(vec3<f32>(0.04), base_color, metallic);
+
+    var radiance_out = vec3<f32>(0.0);
+    for (var i = 0u; i < lighting.light_count; i++) {
+        let light = sample_light(lights[i], surface.position, normal);
+        let half_dir = normalize(light.direction + view_dir);
+        let n_dot_l = max(dot(normal, light.direction), 0.0);
+        if (n_dot_l <= 0.0) {
+            continue;
+        }
+
+        let d = distribution_ggx(max(dot(normal, half_dir), 0.0), roughness);
+        let g = geometry_smith(n_dot_v, n_dot_l, roughness);
+        let f = fresnel_schlick(max(dot(half_dir, view_dir), 0.0), f0);
+        let specular = d * g * f / (4.0 * n_dot_v * n_dot_l + 0.0001);
+        let k_diffuse = (vec3<f32>(1.0) - f) * (1.0 - metallic);
+
+        radiance_out += (k_diffuse * base_color / PI + specular) * light.radiance * n_dot_l;
+    }
+
+    // Split sum image based lighting
+    let f_ambient = fresnel_schlick_roughness(n_dot_v, f0, roughness);
+    let k_diffuse_ambient = (vec3<f32>(1.0) - f_ambient) * (1.0 - metallic);
+    let irradiance = textureSampleLevel(irradiance_map, environment_sampler, normal, 0.0).rgb;
+    let reflected = reflect(-view_dir, normal);
+    let prefiltered = textureSampleLevel(prefiltered_map, environment_sampler, reflected, roughness * lighting.max_reflection_lod).rgb;
+    let brdf = textureSampleLevel(brdf_lut, environment_sampler, vec2<f32>(n_dot_v, roughness), 0.0).rg;
+    let ibl = k_diffuse_ambient * irradiance * base_color + prefiltered * (f_ambient * brdf.x + brdf.y);
+
+    let ambient = (lighting.ambient * base_color + ibl * lighting.environment_intensity) * surface.occlusion;
+    return ambient + radiance_out + surface.emissive;
+}
+
+// Reads the material textures, shared by the forward and G-buffer passes
+fn sample_surface(in: VertexPayload) -> SurfaceSample {
+    let base_color = textureSample(material_texture, material_sampler, in.texture_coords) * material.base_color;
+    let metallic_roughness = textureSample(metallic_roughness_texture, material_sampler, in.texture_coords);
+    let occlusion = textureSample(occlusion_texture, material_sampler, in.texture_coords).r;
+    let emissive = textureSample(emissive_texture, material_sampler, in.texture_coords).rgb;
+
+    var tangent_normal = textureSample(normal_texture, material_sampler, in.texture_coords).xyz * 2.0 - 1.0;
+    tangent_normal = vec3<f32>(tangent_normal.xy * material.normal_scale, tangent_normal.z);
+
+    var surface: SurfaceSample;
+    surface.position = in.world_position;
+    surface.normal = perturb_normal(normalize(in.world_normal), in.world_tangent, tangent_normal);
+    surface.base_color = base_color.rgb;
+    surface.alpha = apply_alpha_cutoff(base_color.a);
+    surface.metallic = metallic_roughness.b * material.metallic;
+    surface.roughness = clamp(metallic_roughness.g * material.roughness, 0.04, 1.0);
+    surface.occlusion = mix(1.0, occlusion, material.occlusion_strength);
+    surface.emissive = emissive * material.emissive;
+    return surface;
+}
+
+@fragment
+fn fs_pbr(in: VertexPayload) -> @location(0) vec4<f32> {
+    let surface = sample_surface(in);
+    return vec4<f32>(shade_pbr(surface), surface.alpha);
+}
+
+// Weighted blended order-independent transparency (McGuire and Bavoil 2013),
+// same order as OIT_FORMATS
+struct OitOutput {
+    @location(0) accum: vec4<f32>,
+    @location(1) revealage: f32,
+}
+
+// Closer fragments get a larger weight so they dominate the composited color
+fn weighted_oit(color: vec4<f32>, world_position: vec3<f32>) -> OitOutput {
+    let distance = length(camera.view_position.xyz - world_position);
+    let falloff = 10.0 / (0.00001 + pow(distance / 5.0, 2.0) + pow(distance / 200.0, 6.0));
+    let weight = color.a * clamp(falloff, 0.01, 3000.0);
+
+    var out: OitOutput;
+    out.accum = vec4<f32>(color.rgb * color.a, color.a) * weight;
+    out.revealage = color.a;
+    return out;
+}
+
+// OIT entries are the fragment entry of the batch pipeline with an `_oit` suffix
+@fragment
+fn fs_main_oit(in: VertexPayload) -> OitOutput {
+    return weighted_oit(shade_blinn_phong(in), in.world_position);
+}
+
+@fragment
+fn fs_pbr_oit(in: VertexPayload) -> OitOutput {
+    let surface = sample_surface(in);
+    return weighted_oit(vec4<f32>(shade_pbr(surface), surface.alpha), in.world_position);
+}
+
+// Same order as GBUFFER_FORMATS
+struct GBufferOutput {
+    // rgb base color, a occlusion
+    @location(0) albedo: vec4<f32>,
+    @location(1) normal: vec4<f32>,
+    // r metallic, g roughness
+    @location(2) material: vec4<f32>,
+    @location(3) emissive: vec4<f32>,
+}
+
+@fragment
+fn fs_gbuffer(in: VertexPayload) -> GBufferOutput {
+    let surface = sample_surface(in);
+
+    var out: GBufferOutput;
+    out.albedo = vec4<f32>(surface.base_color, surface.occlusion);
+    out.normal = vec4<f32>(surface.normal, 0.0);
+    out.material = vec4<f32>(surface.metallic, surface.roughness, 0.0, 0.0);
+    out.emissive = vec4<f32>(surface.emissive, 0.0);
+    return out;
+}
+
+// Deferred lighting reads the G-buffer from group 0, after the material bindings
+@group(0) @binding(7) var gbuffer_albedo: texture_2d<f32>;
+@group(0) @binding(8) var gbuffer_normal: texture_2d<f32>;
+@group(0) @binding(9) var gbuffer_material: texture_2d<f32>;
+@group(0) @binding(10) var gbuffer_emissive: texture_2d<f32>;
+@group(0) @binding(11) var gbuffer_depth: texture_depth_2d;
+
+const DEBUG_VIEW_NONE: u32 = 0u;
+const DEBUG_VIEW_ALBEDO: u32 = 1u;
+const DEBUG_VIEW_NORMAL: u32 = 2u;
+const DEBUG_VIEW_METALLIC: u32 = 3u;
+const DEBUG_VIEW_ROUGHNESS: u32 = 4u;
+const DEBUG_VIEW_OCCLUSION: u32 = 5u;
+const DEBUG_VIEW_EMISSIVE: u32 = 6u;
+const DEBUG_VIEW_DEPTH: u32 = 7u;
+
+// Same layout as DeferredParams
+struct DeferredParams {
+    clear_color: vec4<f32>,
+    debug_view: u32,
+}
+
+@group(0) @binding(12) var<uniform> deferred: DeferredParams;
+
+@vertex
+fn vs_deferred(@builtin(vertex_index) vertex_index: u32) -> @builtin(position) vec4<f32> {
+    let uv = vec2<f32>(f32((vertex_index << 1u) & 2u), f32(vertex_index & 2u));
+    return vec4<f32>(uv * 2.0 - 1.0, 0.0, 1.0);
+}
+
+@fragment
+fn fs_deferred(@builtin(position) position: vec4<f32>) -> @location(0) vec4<f32> {
+    let pixel = vec2<i32>(position.xy);
+    let depth = textureLoad(gbuffer_depth, pixel, 0);
+    let albedo = textureLoad(gbuffer_albedo, pixel, 0);
+    let normal = textureLoad(gbuffer_normal, pixel, 0).xyz;
+    let material_params = textureLoad(gbuffer_material, pixel, 0);
+    let emissive = textureLoad(gbuffer_emissive, pixel, 0).rgb;
+
+    switch deferred.debug_view {
+        case DEBUG_VIEW_ALBEDO: {
+            return vec4<f32>(albedo.rgb, 1.0);
+        }
+        case DEBUG_VIEW_NORMAL: {
+            return vec4<f32>(normal * 0.5 + 0.5, 1.0);
+        }
+        case DEBUG_VIEW_METALLIC: {
+            return vec4<f32>(vec3<f32>(material_params.r), 1.0);
+        }
+        case DEBUG_VIEW_ROUGHNESS: {
+            return vec4<f32>(vec3<f32>(material_params.g), 1.0);
+        }
+        case DEBUG_VIEW_OCCLUSION: {
+            return vec4<f32>(vec3<f32>(albedo.a), 1.0);
+        }
+        case DEBUG_VIEW_EMISSIVE: {
+            return vec4<f32>(emissive, 1.0);
+        }
+        case DEBUG_VIEW_DEPTH: {
+            // Depth is packed near 1.0, spread it out to be visible
+            return vec4<f32>(vec3<f32>(pow(depth, 64.0)), 1.0);
+        }
+        default: {}
+    }
+
+    // Nothing was drawn here, the skybox pass may still cover it
+    if (depth >= 1.0) {
+        return deferred.clear_color;
+    }
+
+    let size = vec2<f32>(textureDimensions(gbuffer_depth));
+    let ndc = vec2<f32>(position.x / size.x * 2.0 - 1.0, 1.0 - position.y / size.y * 2.0);
+    let world = camera.inverse_view_proj * vec4<f32>(ndc, depth, 1.0);
+
+    var surface: SurfaceSample;
+    surface.position = world.xyz / world.w;
+    surface.normal = normalize(normal);
+    surface.base_color = albedo.rgb;
+    surface.alpha = 1.0;
+    surface.metallic = material_params.r;
+    surface.roughness = material_params.g;
+    surface.occlusion = albedo.a;
+    surface.emissive = emissive;
+    return vec4<f32>(shade_pbr(surface), 1.0);
+}
@@ -0,0 +1,39 @@
+struct Vertex {
+    @location(0) position: vec3<f32>,
+    @location(1) texture_coords: vec2<f32>,
+    @location(2) normal: vec3<f32>,
+    @location(3) tangent: vec4<f32>,
+}
+
+struct InstanceInput {
+    @location(5) vec_1 : vec4<f32>,
+    @location(6) vec_2 : vec4<f32>,
+    @location(7) vec_3 : vec4<f32>,
+    @location(8) vec_4 : vec4<f32>,
+}
+
+// Same layout as InstanceRaw
+struct InstanceData {
+    model: mat4x4<f32>,
+    normal: mat3x3<f32>,
+}
+
+// Light-space view projection of the layer being rendered
+@group(0) @binding(0) var<uniform> light_view_proj: mat4x4<f32>;
+@group(1) @binding(0) var<storage, read> instances: array<InstanceData>;
+
+@vertex
+fn vs_shadow(vertex: Vertex, instance_input: InstanceInput) -> @builtin(position) vec4<f32> {
+    let model = mat4x4<f32>(
+        instance_input.vec_1,
+        instance_input.vec_2,
+        instance_input.vec_3,
+        instance_input.vec_4,
+    );
+    return light_view_proj * model * vec4<f32>(vertex.position, 1.0);
+}
+
+@vertex
+fn vs_shadow_storage(vertex: Vertex, @builtin(instance_index) instance_index: u32) -> @builtin(position) vec4<f32> {
+    return light_view_proj * instances[instance_index].model * vec4<f32>(vertex.position, 1.0);
+}
@@ -0,0 +1,33 @@
+struct Camera {
+    view_proj: mat4x4<f32>,
+    view_position: vec4<f32>,
+    inverse_view_proj: mat4x4<f32>,
+}
+
+@group(0) @binding(0) var skybox_texture: texture_cube<f32>;
+@group(0) @binding(1) var skybox_sampler: sampler;
+@group(1) @binding(0) var<uniform> camera: Camera;
+
+struct SkyboxPayload {
+    @builtin(position) position: vec4<f32>,
+    @location(0) ndc: vec2<f32>,
+}
+
+// Fullscreen triangle placed on the far plane, so any geometry drawn before wins the depth test
+@vertex
+fn vs_skybox(@builtin(vertex_index) vertex_index: u32) -> SkyboxPayload {
+    let uv = vec2<f32>(f32((vertex_index << 1u) & 2u), f32(vertex_index & 2u));
+    let ndc = uv * 2.0 - 1.0;
+
+    var out: SkyboxPayload;
+    out.position = vec4<f32>(ndc, 1.0, 1.0);
+    out.ndc = ndc;
+    return out;
+}
+
+@fragment
+fn fs_skybox(in: SkyboxPayload) -> @location(0) vec4<f32> {
+    let far_point = camera.inverse_view_proj * vec4<f32>(in.ndc, 1.0, 1.0);
+    let direction = far_point.xyz / far_point.w - camera.view_position.xyz;
+    return vec4<f32>(textureSample(skybox_texture, skybox_sampler, direction).rgb, 1.0);
+}
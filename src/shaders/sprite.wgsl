@@ -0,0 +1,65 @@
+@group(0) @binding(0) var material_texture: texture_2d<f32>;
+@group(0) @binding(1) var material_sampler: sampler;
+
+// Same layout as MaterialFactors
+struct MaterialFactors {
+    base_color: vec4<f32>,
+    emissive: vec3<f32>,
+    metallic: f32,
+    roughness: f32,
+    normal_scale: f32,
+    occlusion_strength: f32,
+    alpha_cutoff: f32,
+}
+
+@group(0) @binding(6) var<uniform> material: MaterialFactors;
+
+struct Camera {
+    view_proj: mat4x4<f32>,
+    view_position: vec4<f32>,
+    inverse_view_proj: mat4x4<f32>,
+}
+
+@group(1) @binding(0) var<uniform> camera: Camera;
+
+struct Vertex {
+    @location(0) position: vec3<f32>,
+    @location(1) texture_coords: vec2<f32>,
+}
+
+// Same layout as QuadInstance, the color is the tint
+struct SpriteInput {
+    @location(5) model_1: vec4<f32>,
+    @location(6) model_2: vec4<f32>,
+    @location(7) model_3: vec4<f32>,
+    @location(8) model_4: vec4<f32>,
+    // Offset and size of the sprite in the texture
+    @location(9) uv_rect: vec4<f32>,
+    @location(10) tint: vec4<f32>,
+}
+
+struct SpritePayload {
+    @builtin(position) position: vec4<f32>,
+    @location(0) uv: vec2<f32>,
+    @location(1) tint: vec4<f32>,
+}
+
+@vertex
+fn vs_sprite(vertex: Vertex, sprite: SpriteInput) -> SpritePayload {
+    let model = mat4x4<f32>(sprite.model_1, sprite.model_2, sprite.model_3, sprite.model_4);
+
+    var out: SpritePayload;
+    out.position = camera.view_proj * model * vec4<f32>(vertex.position, 1.0);
+    out.uv = sprite.uv_rect.xy + vertex.texture_coords * sprite.uv_rect.zw;
+    out.tint = sprite.tint * material.base_color;
+    return out;
+}
+
+@fragment
+fn fs_sprite(in: SpritePayload) -> @location(0) vec4<f32> {
+    let color = in.tint * textureSample(material_texture, material_sampler, in.uv);
+    if (color.a <= 0.0) {
+        discard;
+    }
+    return color;
+}
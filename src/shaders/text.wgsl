@@ -0,0 +1,66 @@
+// Glyph atlas, coverage is stored in alpha
+@group(0) @binding(0) var material_texture: texture_2d<f32>;
+@group(0) @binding(1) var material_sampler: sampler;
+
+// Same layout as MaterialFactors
+struct MaterialFactors {
+    base_color: vec4<f32>,
+    emissive: vec3<f32>,
+    metallic: f32,
+    roughness: f32,
+    normal_scale: f32,
+    occlusion_strength: f32,
+    alpha_cutoff: f32,
+}
+
+@group(0) @binding(6) var<uniform> material: MaterialFactors;
+
+struct Camera {
+    view_proj: mat4x4<f32>,
+    view_position: vec4<f32>,
+    inverse_view_proj: mat4x4<f32>,
+}
+
+@group(1) @binding(0) var<uniform> camera: Camera;
+
+struct Vertex {
+    @location(0) position: vec3<f32>,
+    @location(1) texture_coords: vec2<f32>,
+}
+
+// Same layout as QuadInstance
+struct GlyphInput {
+    @location(5) model_1: vec4<f32>,
+    @location(6) model_2: vec4<f32>,
+    @location(7) model_3: vec4<f32>,
+    @location(8) model_4: vec4<f32>,
+    // Offset and size of the glyph in the atlas
+    @location(9) uv_rect: vec4<f32>,
+    @location(10) color: vec4<f32>,
+}
+
+struct GlyphPayload {
+    @builtin(position) position: vec4<f32>,
+    @location(0) uv: vec2<f32>,
+    @location(1) color: vec4<f32>,
+}
+
+@vertex
+fn vs_text(vertex: Vertex, glyph: GlyphInput) -> GlyphPayload {
+    let model = mat4x4<f32>(glyph.model_1, glyph.model_2, glyph.model_3, glyph.model_4);
+
+    var out: GlyphPayload;
+    out.position = camera.view_proj * model * vec4<f32>(vertex.position, 1.0);
+    out.uv = glyph.uv_rect.xy + vertex.texture_coords * glyph.uv_rect.zw;
+    out.color = glyph.color * material.base_color;
+    return out;
+}
+
+@fragment
+fn fs_text(in: GlyphPayload) -> @location(0) vec4<f32> {
+    let coverage = textureSample(material_texture, material_sampler, in.uv).a;
+    if (coverage <= 0.0) {
+        discard;
+    }
+    return vec4<f32>(in.color.rgb, in.color.a * coverage);
+}
@@ -0,0 +1,64 @@
+// Same layout as UiScreen
+struct Screen {
+    // In points, the logical pixels egui lays out in
+    size: vec2<f32>,
+    _padding: vec2<f32>,
+}
+
+@group(0) @binding(0) var<uniform> screen: Screen;
+@group(1) @binding(0) var ui_texture: texture_2d<f32>;
+@group(1) @binding(1) var ui_sampler: sampler;
+
+// Set when the surface format is not sRGB, the output is then encoded by hand
+override gamma_output: bool = false;
+
+struct UiVertex {
+    @location(0) position: vec2<f32>,
+    @location(1) uv: vec2<f32>,
+    // Premultiplied sRGB
+    @location(2) color: vec4<f32>,
+}
+
+struct UiPayload {
+    @builtin(position) position: vec4<f32>,
+    @location(0) uv: vec2<f32>,
+    @location(1) color: vec4<f32>,
+}
+
+fn linear_from_gamma(srgb: vec3<f32>) -> vec3<f32> {
+    let cutoff = srgb < vec3<f32>(0.04045);
+    let lower = srgb / 12.92;
+    let higher = pow((srgb + 0.055) / 1.055, vec3<f32>(2.4));
+    return select(higher, lower, cutoff);
+}
+
+fn gamma_from_linear(linear: vec3<f32>) -> vec3<f32> {
+    let cutoff = linear < vec3<f32>(0.0031308);
+    let lower = linear * 12.92;
+    let higher = 1.055 * pow(linear, vec3<f32>(1.0 / 2.4)) - 0.055;
+    return select(higher, lower, cutoff);
+}
+
+@vertex
+fn vs_ui(vertex: UiVertex) -> UiPayload {
+    var out: UiPayload;
+    out.position = vec4<f32>(
+        2.0 * vertex.position.x / screen.size.x - 1.0,
+        1.0 - 2.0 * vertex.position.y / screen.size.y,
+        0.0,
+        1.0,
+    );
+    out.uv = vertex.uv;
+    out.color = vec4<f32>(linear_from_gamma(vertex.color.rgb), vertex.color.a);
+    return out;
+}
+
+@fragment
+fn fs_ui(in: UiPayload) -> @location(0) vec4<f32> {
+    // The textures are sRGB, so sampling returns linear colors
+    let color = in.color * textureSample(ui_texture, ui_sampler, in.uv);
+    if (gamma_output) {
+        return vec4<f32>(gamma_from_linear(color.rgb), color.a);
+    }
+    return color;
+}